@@ -0,0 +1,88 @@
+use axum::extract::{FromRequestParts, OptionalFromRequestParts};
+use axum::http::header::AUTHORIZATION;
+use axum::http::request::Parts;
+
+use crate::convex::{ConvexError, api_tokens, sessions};
+use crate::prelude::*;
+use crate::proxy::tokens::{TOKEN_PREFIX, hash_token};
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+  #[error("missing bearer token")]
+  MissingToken,
+  #[error("invalid or expired token")]
+  InvalidToken,
+  #[error("api token is revoked or not bound to a user")]
+  NoUser,
+  #[error("failed to check token: {0}")]
+  Convex(#[from] ConvexError),
+}
+
+into_response!(AuthError {
+  MissingToken => StatusCode::UNAUTHORIZED,
+  InvalidToken => StatusCode::UNAUTHORIZED,
+  NoUser => StatusCode::FORBIDDEN,
+  Convex(_) => StatusCode::INTERNAL_SERVER_ERROR,
+});
+
+/// the `Authorization: Bearer` token, if any
+pub fn bearer_token(parts: &Parts) -> Option<&str> {
+  parts
+    .headers
+    .get(AUTHORIZATION)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.strip_prefix("Bearer "))
+    .map(str::trim)
+    .filter(|token| !token.is_empty())
+}
+
+/// the signed in user, from the session token the frontend also hands to convex or from an api token bound to a user
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+  /// the id convex stores on the threads of the user
+  pub user_id: String,
+}
+
+impl AuthUser {
+  async fn from_token(state: &AppState, token: &str) -> Result<Self, AuthError> {
+    let mut convex = state.convex.clone();
+
+    if token.starts_with(TOKEN_PREFIX) {
+      let Some(token) = api_tokens::get_by_hash(&mut convex, hash_token(token)).await? else {
+        return Err(AuthError::InvalidToken);
+      };
+
+      return match token.fields.user_id {
+        Some(user_id) if token.fields.revoked_at.is_none() => Ok(Self { user_id }),
+        _ => Err(AuthError::NoUser),
+      };
+    }
+
+    match sessions::viewer(&convex, token).await? {
+      Some(user_id) => Ok(Self { user_id }),
+      None => Err(AuthError::InvalidToken),
+    }
+  }
+}
+
+impl FromRequestParts<AppState> for AuthUser {
+  type Rejection = AuthError;
+
+  async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+    let token = bearer_token(parts).ok_or(AuthError::MissingToken)?;
+
+    Self::from_token(state, token).await
+  }
+}
+
+/// anonymous requests are `None`, a token that is sent has to be valid
+impl OptionalFromRequestParts<AppState> for AuthUser {
+  type Rejection = AuthError;
+
+  async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Option<Self>, Self::Rejection> {
+    match bearer_token(parts) {
+      Some(token) => Ok(Some(Self::from_token(state, token).await?)),
+      None => Ok(None),
+    }
+  }
+}
@@ -0,0 +1,171 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::import::ImportFormat;
+use crate::openrouter::types::ReasoningEffort;
+use crate::types::TypesFormat;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Chat api server and tooling")]
+pub struct Cli {
+  /// toml config file, defaults to `CONFIG_FILE` or `config.toml` when it exists
+  #[arg(long, global = true, value_name = "PATH")]
+  pub config: Option<PathBuf>,
+
+  /// runs the server when no command is given
+  #[command(subcommand)]
+  pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+  /// Run the http server
+  Serve,
+  /// Write the typescript types and route helpers used by the frontend
+  ExportTypes(ExportTypesArgs),
+  /// Validate the config file and environment, listing every problem
+  CheckConfig,
+  /// Inspect the models available on openrouter
+  #[command(subcommand)]
+  Models(ModelsCommand),
+  /// Stream a recorded openrouter response through the chat stream parser
+  Replay(ReplayArgs),
+  /// Import conversations from a ChatGPT or Claude data export
+  Import(ImportArgs),
+  /// Chat with a model from the terminal
+  Chat(ChatArgs),
+  /// Manage the api tokens of the openai compatible `/v1` api
+  #[command(subcommand)]
+  Tokens(TokensCommand),
+}
+
+#[derive(Debug, Args)]
+pub struct ExportTypesArgs {
+  /// file to write, usually `front/src/lib/types/generated.ts`
+  #[arg(short, long, env = "TYPES_PATH", value_name = "PATH")]
+  pub output: PathBuf,
+
+  #[arg(long, value_enum, default_value_t)]
+  pub format: TypesFormat,
+
+  /// prefix every type with the source location it was defined at
+  #[arg(long)]
+  pub debug_locations: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ModelsCommand {
+  /// List model ids with their context length and pricing
+  List(ModelsListArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ModelsListArgs {
+  /// only show models whose id contains this
+  #[arg(long)]
+  pub filter: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct ReplayArgs {
+  /// recorded `text/event-stream` body, see `test_data/chat-stream.sse`
+  pub fixture: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct ImportArgs {
+  /// `conversations.json` from the export
+  pub path: PathBuf,
+
+  /// user the threads are created for
+  #[arg(long)]
+  pub user: String,
+
+  /// detected from the file when omitted
+  #[arg(long, value_enum)]
+  pub format: Option<ImportFormat>,
+
+  /// parse and report without writing anything
+  #[arg(long)]
+  pub dry_run: bool,
+
+  /// import conversations that were already imported before
+  #[arg(long)]
+  pub allow_duplicates: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ChatArgs {
+  /// model id as shown by `models list`
+  #[arg(short, long, default_value = "openai/gpt-4o-mini")]
+  pub model: String,
+
+  #[arg(long, value_enum)]
+  pub reasoning: Option<ReasoningEffort>,
+
+  /// personal openrouter key, the server key is used when omitted
+  #[arg(long, env = "OPENROUTER_USER_KEY", hide_env_values = true)]
+  pub key: Option<String>,
+
+  /// system prompt sent before the conversation
+  #[arg(long)]
+  pub system: Option<String>,
+
+  /// files attached to the first message, more can be added with `/attach <path>`
+  #[arg(short, long = "attach", value_name = "PATH")]
+  pub attachments: Vec<PathBuf>,
+
+  /// save the session as a thread owned by this user when exiting
+  #[arg(long, value_name = "USER_ID")]
+  pub save_for: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TokensCommand {
+  /// Create a token, it is only shown once
+  Create(TokensCreateArgs),
+  /// List tokens without their secrets
+  List,
+  /// Revoke a token by id
+  Revoke(TokensRevokeArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct TokensCreateArgs {
+  /// what the token is used for
+  #[arg(long)]
+  pub name: String,
+
+  /// record usage of this token against a user
+  #[arg(long, value_name = "USER_ID")]
+  pub user: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct TokensRevokeArgs {
+  pub id: String,
+}
+
+#[cfg(test)]
+mod tests {
+  use clap::CommandFactory;
+
+  use super::*;
+
+  #[test]
+  fn test_cli_definition() {
+    Cli::command().debug_assert();
+  }
+
+  #[test]
+  fn test_parse_models_list() {
+    let cli = Cli::try_parse_from(["api", "--config", "prod.toml", "models", "list", "--filter", "openai/"]).unwrap();
+
+    assert_eq!(cli.config, Some(PathBuf::from("prod.toml")));
+    assert!(matches!(
+      cli.command,
+      Some(Command::Models(ModelsCommand::List(ModelsListArgs { filter: Some(filter) }))) if filter == "openai/"
+    ));
+  }
+}
@@ -1,29 +1,174 @@
+use std::collections::BTreeMap;
 use std::env;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 
-use anyhow::{Context, bail};
 use reqwest::Url;
 use secrecy::SecretString;
+use serde::Deserialize;
 
-fn get_var(key: &'static str) -> anyhow::Result<String> {
-  let var = env::var(key).with_context(|| format!("{} must be set in the environment", key))?;
+use crate::keys::{KeyCipher, KeyPolicy};
+use crate::openrouter::types::DataCollection;
 
-  if var.is_empty() {
-    bail!("{} must not be empty", key);
+/// read when `CONFIG_FILE` is not set, a missing default file is not an error
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+const CONFIG_FILE_KEY: &str = "CONFIG_FILE";
+
+/// the explicitly configured file, or the default one if it exists
+pub fn config_path() -> Option<PathBuf> {
+  if let Some(path) = env::var_os(CONFIG_FILE_KEY).filter(|path| !path.is_empty()) {
+    return Some(path.into());
+  }
+
+  let path = PathBuf::from(DEFAULT_CONFIG_PATH);
+  path.exists().then_some(path)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+  #[error("failed to read config file {0}: {1}")]
+  Read(PathBuf, std::io::Error),
+  #[error("failed to parse config file {0}: {1}")]
+  Parse(PathBuf, Box<toml::de::Error>),
+  #[error("invalid configuration:\n{}", format_problems(.0))]
+  Invalid(Vec<String>),
+}
+
+fn format_problems(problems: &[String]) -> String {
+  problems
+    .iter()
+    .map(|problem| format!("  - {problem}"))
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+/// where a setting lives in the config file and which environment variable overrides it
+#[derive(Debug, Clone, Copy)]
+struct Key {
+  path: &'static str,
+  env: &'static str,
+}
+
+const fn key(path: &'static str, env: &'static str) -> Key {
+  Key { path, env }
+}
+
+/// resolves settings from the environment and the config file, collecting every problem instead of stopping at
+/// the first one
+struct Loader<E> {
+  env: E,
+  problems: Vec<String>,
+}
+
+impl<E: Fn(&str) -> Option<String>> Loader<E> {
+  fn var(&self, key: Key) -> Option<String> {
+    (self.env)(key.env).filter(|var| !var.trim().is_empty())
+  }
+
+  fn problem(&mut self, key: Key, problem: impl Display) {
+    self.problems.push(format!("{} ({}) {problem}", key.path, key.env));
+  }
+
+  /// environment variable if set, otherwise the file value
+  fn optional<T>(&mut self, key: Key, file: Option<T>) -> Option<T>
+  where
+    T: FromStr,
+    T::Err: Display,
+  {
+    let Some(var) = self.var(key) else {
+      return file;
+    };
+
+    match var.trim().parse() {
+      Ok(value) => Some(value),
+      Err(err) => {
+        self.problem(key, format_args!("is invalid: {err}"));
+        None
+      }
+    }
+  }
+
+  fn required<T>(&mut self, key: Key, file: Option<T>) -> Option<T>
+  where
+    T: FromStr,
+    T::Err: Display,
+  {
+    let problems = self.problems.len();
+    let value = self.optional(key, file);
+
+    // an unparsable value has already been reported
+    if value.is_none() && self.problems.len() == problems {
+      self.problem(key, "must be set in the config file or the environment");
+    }
+
+    value
+  }
+
+  fn url(&mut self, key: Key, file: Option<String>) -> Option<Url> {
+    let url = self.required::<String>(key, file)?;
+
+    self.parse_url(key, url)
+  }
+
+  fn url_or(&mut self, key: Key, file: Option<String>, default: &str) -> Option<Url> {
+    let url = self
+      .optional::<String>(key, file)
+      .unwrap_or_else(|| default.to_string());
+
+    self.parse_url(key, url)
   }
 
-  Ok(var)
+  /// with a trailing slash so relative paths are joined onto it instead of replacing the last segment
+  fn parse_url(&mut self, key: Key, url: String) -> Option<Url> {
+    let url = if !url.ends_with('/') { format!("{url}/") } else { url };
+
+    match url.parse() {
+      Ok(url) => Some(url),
+      Err(err) => {
+        self.problem(key, format_args!("must be a valid URL: {err}"));
+        None
+      }
+    }
+  }
+
+  /// comma separated in the environment, an array in the file
+  fn list(&self, key: Key, file: Option<Vec<String>>) -> Vec<String> {
+    let items = match self.var(key) {
+      Some(var) => var.split(',').map(str::to_string).collect(),
+      None => file.unwrap_or_default(),
+    };
+
+    items
+      .into_iter()
+      .map(|item| item.trim().to_string())
+      .filter(|item| !item.is_empty())
+      .collect()
+  }
 }
 
-fn get_url_var(key: &'static str) -> anyhow::Result<Url> {
-  let url_str = get_var(key)?;
+/// contents of the config file, every key is optional so env only deployments keep working
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+  application: ApplicationSection,
+  snowflake: SnowflakeSection,
+  openrouter: OpenrouterSection,
+  convex: ConvexSection,
+  limits: LimitsSection,
+  worker: WorkerSection,
+  models: ModelsSection,
+  keys: KeysSection,
+  attachments: AttachmentsSection,
+}
 
-  let url_str = if !url_str.ends_with('/') {
-    format!("{url_str}/")
-  } else {
-    url_str
-  };
+impl ConfigFile {
+  fn read(path: &Path) -> Result<Self, ConfigError> {
+    let contents = std::fs::read_to_string(path).map_err(|err| ConfigError::Read(path.into(), err))?;
 
-  url_str.parse().context(format!("{key} must be a valid URL"))
+    toml::from_str(&contents).map_err(|err| ConfigError::Parse(path.into(), Box::new(err)))
+  }
 }
 
 #[derive(Debug, Clone)]
@@ -32,24 +177,87 @@ pub struct Config {
   pub snowflake: SnowflakeConfig,
   pub openrouter: OpenrouterConfig,
   pub convex: ConvexConfig,
+  pub limits: LimitsConfig,
+  pub worker: WorkerConfig,
+  pub models: ModelsConfig,
+  pub keys: KeysConfig,
+  pub attachments: AttachmentsConfig,
 }
 
 impl Config {
-  pub fn from_env() -> anyhow::Result<Self> {
-    let application = ApplicationConfig::from_env()?;
-    let snowflake = SnowflakeConfig::from_env()?;
-    let openrouter = OpenrouterConfig::from_env()?;
-    let convex = ConvexConfig::from_env()?;
+  /// layers environment variables over the file from [`config_path`]
+  pub fn load() -> Result<Self, ConfigError> {
+    Self::load_from(config_path().as_deref())
+  }
+
+  /// layers environment variables over `path`, or reads only the environment without a file
+  pub fn load_from(path: Option<&Path>) -> Result<Self, ConfigError> {
+    let file = match path {
+      Some(path) => ConfigFile::read(path)?,
+      None => ConfigFile::default(),
+    };
+
+    Self::from_sources(file, |key| env::var(key).ok())
+  }
 
-    Ok(Self {
+  fn from_sources(file: ConfigFile, env: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+    let mut loader = Loader { env, problems: vec![] };
+
+    let application = ApplicationConfig::load(&mut loader, file.application);
+    let snowflake = SnowflakeConfig::load(&mut loader, file.snowflake);
+    let openrouter = OpenrouterConfig::load(&mut loader, file.openrouter);
+    let convex = ConvexConfig::load(&mut loader, file.convex);
+    let limits = LimitsConfig::load(&mut loader, file.limits);
+    let worker = WorkerConfig::load(&mut loader, file.worker);
+    let models = ModelsConfig::load(&mut loader, file.models);
+    let keys = KeysConfig::load(&mut loader, file.keys);
+    let convex_url = convex.as_ref().map(|convex| &convex.url);
+    let attachments = AttachmentsConfig::load(&mut loader, file.attachments, convex_url);
+
+    match (
       application,
       snowflake,
       openrouter,
       convex,
-    })
+      limits,
+      worker,
+      models,
+      keys,
+      attachments,
+    ) {
+      (
+        Some(application),
+        Some(snowflake),
+        Some(openrouter),
+        Some(convex),
+        Some(limits),
+        Some(worker),
+        Some(models),
+        Some(keys),
+        Some(attachments),
+      ) if loader.problems.is_empty() => Ok(Self {
+        application,
+        snowflake,
+        openrouter,
+        convex,
+        limits,
+        worker,
+        models,
+        keys,
+        attachments,
+      }),
+      _ => Err(ConfigError::Invalid(loader.problems)),
+    }
   }
 }
 
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ApplicationSection {
+  port: Option<u16>,
+  host: Option<String>,
+}
+
 #[derive(Debug, Clone)]
 pub struct ApplicationConfig {
   pub port: u16,
@@ -57,21 +265,30 @@ pub struct ApplicationConfig {
 }
 
 impl ApplicationConfig {
-  const HOST_KEY: &'static str = "APP_HOST";
-  const PORT_KEY: &'static str = "APP_PORT";
+  const HOST_KEY: Key = key("application.host", "APP_HOST");
+  const PORT_KEY: Key = key("application.port", "APP_PORT");
 
-  fn from_env() -> anyhow::Result<Self> {
-    let port = get_var(Self::PORT_KEY)?
-      .parse()
-      .context(format!("{} must be a valid u16", Self::PORT_KEY))?;
-    let host = get_var(Self::HOST_KEY)?;
+  const DEFAULT_HOST: &'static str = "0.0.0.0";
+  const DEFAULT_PORT: u16 = 8080;
 
-    Ok(Self { port, host })
+  fn load(loader: &mut Loader<impl Fn(&str) -> Option<String>>, file: ApplicationSection) -> Option<Self> {
+    let port = loader.optional(Self::PORT_KEY, file.port).unwrap_or(Self::DEFAULT_PORT);
+    let host = loader
+      .optional(Self::HOST_KEY, file.host)
+      .unwrap_or_else(|| Self::DEFAULT_HOST.to_string());
+
+    Some(Self { port, host })
   }
 }
 
 pub const EPOCH_MS: u64 = 1698765529972;
 
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SnowflakeSection {
+  worker: Option<u8>,
+}
+
 #[derive(Debug, Clone)]
 pub struct SnowflakeConfig {
   pub worker: u8,
@@ -79,46 +296,92 @@ pub struct SnowflakeConfig {
 }
 
 impl SnowflakeConfig {
-  const WORKER_KEY: &'static str = "SNOWFLAKE_WORKER";
+  const WORKER_KEY: Key = key("snowflake.worker", "SNOWFLAKE_WORKER");
+
+  fn load(loader: &mut Loader<impl Fn(&str) -> Option<String>>, file: SnowflakeSection) -> Option<Self> {
+    let worker = loader.optional(Self::WORKER_KEY, file.worker).unwrap_or_default();
 
-  fn from_env() -> anyhow::Result<Self> {
-    let worker: u8 = get_var(Self::WORKER_KEY)?
-      .parse()
-      .context(format!("{} must be a valid u8", Self::WORKER_KEY))?;
     if worker > snowflake::WORKER_MAX {
-      bail!("{} must be less than {}", Self::WORKER_KEY, snowflake::WORKER_MAX);
+      loader.problem(
+        Self::WORKER_KEY,
+        format_args!("must be at most {}", snowflake::WORKER_MAX),
+      );
+      return None;
     }
+
     let process = (std::process::id() % snowflake::PROCESS_MAX as u32) as u8;
 
-    Ok(Self { worker, process })
+    Some(Self { worker, process })
   }
 }
 
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct OpenrouterSection {
+  api_url: Option<String>,
+  api_key: Option<String>,
+  model_api_url: Option<String>,
+  fallback_models: Option<Vec<String>>,
+  data_collection: Option<String>,
+}
+
 #[derive(Debug, Clone)]
 pub struct OpenrouterConfig {
   pub api_url: Url,
   pub api_key: SecretString,
   pub model_api_url: Url,
+  /// models tried when the requested one fails and the request does not name its own fallbacks
+  pub fallback_models: Vec<String>,
+  /// `deny` restricts every request to providers that do not retain prompts
+  pub data_collection: Option<DataCollection>,
 }
 
 impl OpenrouterConfig {
-  const API_KEY_KEY: &'static str = "OPENROUTER_API_KEY";
-  const API_URL_KEY: &'static str = "OPENROUTER_API_URL";
-  const MODEL_API_URL_KEY: &'static str = "OPENROUTER_MODEL_API_URL";
-
-  fn from_env() -> anyhow::Result<Self> {
-    let api_key = SecretString::from(get_var(Self::API_KEY_KEY)?);
-    let api_url = get_url_var(Self::API_URL_KEY)?;
-    let model_api_url = get_url_var(Self::MODEL_API_URL_KEY)?;
-
-    Ok(Self {
-      api_key,
-      api_url,
-      model_api_url,
+  const API_KEY_KEY: Key = key("openrouter.api_key", "OPENROUTER_API_KEY");
+  const API_URL_KEY: Key = key("openrouter.api_url", "OPENROUTER_API_URL");
+  const MODEL_API_URL_KEY: Key = key("openrouter.model_api_url", "OPENROUTER_MODEL_API_URL");
+  const FALLBACK_MODELS_KEY: Key = key("openrouter.fallback_models", "OPENROUTER_FALLBACK_MODELS");
+  const DATA_COLLECTION_KEY: Key = key("openrouter.data_collection", "OPENROUTER_DATA_COLLECTION");
+
+  const DEFAULT_API_URL: &'static str = "https://openrouter.ai/api/v1/";
+
+  fn load(loader: &mut Loader<impl Fn(&str) -> Option<String>>, file: OpenrouterSection) -> Option<Self> {
+    let api_key = loader.required::<String>(Self::API_KEY_KEY, file.api_key);
+    let api_url = loader.url_or(Self::API_URL_KEY, file.api_url, Self::DEFAULT_API_URL);
+    let model_api_url = loader.url_or(Self::MODEL_API_URL_KEY, file.model_api_url, Self::DEFAULT_API_URL);
+    let fallback_models = loader.list(Self::FALLBACK_MODELS_KEY, file.fallback_models);
+
+    let data_collection = match loader
+      .optional::<String>(Self::DATA_COLLECTION_KEY, file.data_collection)
+      .as_deref()
+    {
+      None => None,
+      Some("allow") => Some(DataCollection::Allow),
+      Some("deny") => Some(DataCollection::Deny),
+      Some(_) => {
+        loader.problem(Self::DATA_COLLECTION_KEY, "must be either allow or deny");
+        None
+      }
+    };
+
+    Some(Self {
+      api_key: SecretString::from(api_key?),
+      api_url: api_url?,
+      model_api_url: model_api_url?,
+      fallback_models,
+      data_collection,
     })
   }
 }
 
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConvexSection {
+  url: Option<String>,
+  deployment: Option<String>,
+  api_key: Option<String>,
+}
+
 #[derive(Debug, Clone)]
 pub struct ConvexConfig {
   pub url: Url,
@@ -127,15 +390,643 @@ pub struct ConvexConfig {
 }
 
 impl ConvexConfig {
-  const API_KEY_KEY: &'static str = "CONVEX_API_KEY";
-  const DEPLOYMENT_KEY: &'static str = "CONVEX_DEPLOYMENT";
-  const URL_KEY: &'static str = "CONVEX_URL";
+  const API_KEY_KEY: Key = key("convex.api_key", "CONVEX_API_KEY");
+  const DEPLOYMENT_KEY: Key = key("convex.deployment", "CONVEX_DEPLOYMENT");
+  const URL_KEY: Key = key("convex.url", "CONVEX_URL");
+
+  fn load(loader: &mut Loader<impl Fn(&str) -> Option<String>>, file: ConvexSection) -> Option<Self> {
+    let url = loader.url(Self::URL_KEY, file.url);
+    let id = loader.required(Self::DEPLOYMENT_KEY, file.deployment);
+    let api_key = loader.required::<String>(Self::API_KEY_KEY, file.api_key);
+
+    Some(Self {
+      url: url?,
+      id: id?,
+      api_key: SecretString::from(api_key?),
+    })
+  }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct LimitsSection {
+  max_concurrent_generations: Option<usize>,
+  generation_lease_ms: Option<u64>,
+  max_generation_attempts: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LimitsConfig {
+  /// new messages are rejected while this many generations are streaming
+  pub max_concurrent_generations: usize,
+  /// how long a claim on a message lasts without a heartbeat, after that the worker may take it over
+  pub generation_lease: Duration,
+  /// generations started for one message before it is marked as failed
+  pub max_generation_attempts: u32,
+}
+
+impl LimitsConfig {
+  const MAX_CONCURRENT_GENERATIONS_KEY: Key = key("limits.max_concurrent_generations", "MAX_CONCURRENT_GENERATIONS");
+
+  const GENERATION_LEASE_KEY: Key = key("limits.generation_lease_ms", "GENERATION_LEASE_MS");
+  const MAX_GENERATION_ATTEMPTS_KEY: Key = key("limits.max_generation_attempts", "MAX_GENERATION_ATTEMPTS");
+
+  const DEFAULT_MAX_CONCURRENT_GENERATIONS: usize = 256;
+  const DEFAULT_GENERATION_LEASE_MS: u64 = 30_000;
+  const DEFAULT_MAX_GENERATION_ATTEMPTS: u32 = 3;
+
+  fn load(loader: &mut Loader<impl Fn(&str) -> Option<String>>, file: LimitsSection) -> Option<Self> {
+    let max_concurrent_generations = loader
+      .optional(Self::MAX_CONCURRENT_GENERATIONS_KEY, file.max_concurrent_generations)
+      .unwrap_or(Self::DEFAULT_MAX_CONCURRENT_GENERATIONS);
+    let generation_lease = loader
+      .optional(Self::GENERATION_LEASE_KEY, file.generation_lease_ms)
+      .unwrap_or(Self::DEFAULT_GENERATION_LEASE_MS);
+    let max_generation_attempts = loader
+      .optional(Self::MAX_GENERATION_ATTEMPTS_KEY, file.max_generation_attempts)
+      .unwrap_or(Self::DEFAULT_MAX_GENERATION_ATTEMPTS);
+
+    let problems = loader.problems.len();
+
+    if max_concurrent_generations == 0 {
+      loader.problem(Self::MAX_CONCURRENT_GENERATIONS_KEY, "must be greater than 0");
+    }
+
+    // leases are renewed a few times per lease, shorter ones would mostly be spent on heartbeats
+    if generation_lease < 1_000 {
+      loader.problem(Self::GENERATION_LEASE_KEY, "must be at least 1000");
+    }
+
+    if max_generation_attempts == 0 {
+      loader.problem(Self::MAX_GENERATION_ATTEMPTS_KEY, "must be greater than 0");
+    }
+
+    if loader.problems.len() > problems {
+      return None;
+    }
+
+    Some(Self {
+      max_concurrent_generations,
+      generation_lease: Duration::from_millis(generation_lease),
+      max_generation_attempts,
+    })
+  }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct WorkerSection {
+  enabled: Option<bool>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WorkerConfig {
+  /// picks up pending messages from convex, so generations start without a request from the browser. it also retries
+  /// generations whose lease expired, e.g. because the instance running them crashed
+  pub enabled: bool,
+}
+
+impl WorkerConfig {
+  const ENABLED_KEY: Key = key("worker.enabled", "WORKER_ENABLED");
+
+  fn load(loader: &mut Loader<impl Fn(&str) -> Option<String>>, file: WorkerSection) -> Option<Self> {
+    let enabled = loader.optional(Self::ENABLED_KEY, file.enabled).unwrap_or_default();
+
+    Some(Self { enabled })
+  }
+}
 
-  fn from_env() -> anyhow::Result<Self> {
-    let url = get_url_var(Self::URL_KEY)?;
-    let id = get_var(Self::DEPLOYMENT_KEY)?;
-    let api_key = SecretString::from(get_var(Self::API_KEY_KEY)?);
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ModelsSection {
+  title: Option<String>,
+  allowed: Option<Vec<String>>,
+  reasoning_passthrough: Option<Vec<String>>,
+  max_continuations: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ModelsConfig {
+  /// model used to name new threads
+  pub title: String,
+  /// models users may pick, empty allows every model
+  pub allowed: Vec<String>,
+  /// model id prefixes that get the reasoning of earlier turns sent back
+  pub reasoning_passthrough: Vec<String>,
+  /// follow-up requests made when an answer is cut off by the token limit, 0 disables auto-continue
+  pub max_continuations: u32,
+}
+
+impl ModelsConfig {
+  const TITLE_KEY: Key = key("models.title", "TITLE_MODEL");
+  const ALLOWED_KEY: Key = key("models.allowed", "ALLOWED_MODELS");
+  const REASONING_PASSTHROUGH_KEY: Key = key("models.reasoning_passthrough", "REASONING_PASSTHROUGH_MODELS");
+  const MAX_CONTINUATIONS_KEY: Key = key("models.max_continuations", "MAX_CONTINUATIONS");
+
+  const DEFAULT_TITLE_MODEL: &'static str = "anthropic/claude-3-haiku";
+  /// models whose providers verify signed or encrypted reasoning from earlier turns
+  const DEFAULT_REASONING_PASSTHROUGH: [&'static str; 2] = ["anthropic/", "google/gemini"];
+
+  fn load(loader: &mut Loader<impl Fn(&str) -> Option<String>>, file: ModelsSection) -> Option<Self> {
+    let title = loader
+      .optional(Self::TITLE_KEY, file.title)
+      .unwrap_or_else(|| Self::DEFAULT_TITLE_MODEL.to_string());
+    let allowed = loader.list(Self::ALLOWED_KEY, file.allowed);
+    let reasoning_passthrough = loader.list(
+      Self::REASONING_PASSTHROUGH_KEY,
+      Some(
+        file
+          .reasoning_passthrough
+          .unwrap_or_else(|| Self::DEFAULT_REASONING_PASSTHROUGH.map(str::to_string).to_vec()),
+      ),
+    );
+    let max_continuations = loader
+      .optional(Self::MAX_CONTINUATIONS_KEY, file.max_continuations)
+      .unwrap_or_default();
+
+    Some(Self {
+      title,
+      allowed,
+      reasoning_passthrough,
+      max_continuations,
+    })
+  }
+
+  pub fn is_allowed(&self, model: &str) -> bool {
+    self.allowed.is_empty() || self.allowed.iter().any(|allowed| allowed == model)
+  }
+
+  /// label for per model metrics, only models listed in `allowed` get their own series so requests can't add more
+  pub fn metrics_label(&self, model: &str) -> String {
+    if self.allowed.iter().any(|allowed| allowed == model) {
+      model.to_string()
+    } else {
+      "other".to_string()
+    }
+  }
+
+  pub fn passes_reasoning(&self, model: &str) -> bool {
+    self
+      .reasoning_passthrough
+      .iter()
+      .any(|prefix| model.starts_with(prefix.as_str()))
+  }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct KeysSection {
+  policy: Option<KeyPolicy>,
+  encryption_key: Option<String>,
+  server_daily_tokens: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct KeysConfig {
+  pub policy: KeyPolicy,
+  /// base64 master key used to encrypt stored user keys, storing keys is disabled without it
+  pub encryption_key: Option<SecretString>,
+  /// tokens a user may generate per day with the server key, unlimited when not set
+  pub server_daily_tokens: Option<u64>,
+}
+
+impl KeysConfig {
+  const POLICY_KEY: Key = key("keys.policy", "OPENROUTER_KEY_POLICY");
+  const ENCRYPTION_KEY_KEY: Key = key("keys.encryption_key", "OPENROUTER_KEY_ENCRYPTION_KEY");
+  const SERVER_DAILY_TOKENS_KEY: Key = key("keys.server_daily_tokens", "SERVER_KEY_DAILY_TOKENS");
+
+  fn load(loader: &mut Loader<impl Fn(&str) -> Option<String>>, file: KeysSection) -> Option<Self> {
+    // the only behaviour before keys could be stored
+    let policy = loader
+      .optional(Self::POLICY_KEY, file.policy)
+      .unwrap_or(KeyPolicy::User);
+
+    let encryption_key = loader.optional::<String>(Self::ENCRYPTION_KEY_KEY, file.encryption_key);
+
+    let encryption_key = match encryption_key {
+      Some(key) => match KeyCipher::from_base64(&key) {
+        Ok(_) => Some(SecretString::from(key)),
+        Err(err) => {
+          loader.problem(Self::ENCRYPTION_KEY_KEY, format_args!("is invalid: {err:#}"));
+          return None;
+        }
+      },
+      None => None,
+    };
+
+    let server_daily_tokens = loader.optional(Self::SERVER_DAILY_TOKENS_KEY, file.server_daily_tokens);
+
+    Some(Self {
+      policy,
+      encryption_key,
+      server_daily_tokens,
+    })
+  }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct AttachmentsSection {
+  max_bytes: Option<u64>,
+  limits: Option<BTreeMap<String, u64>>,
+  max_image_dimension: Option<u32>,
+  image_dimensions: Option<BTreeMap<String, u32>>,
+  allowed_hosts: Option<Vec<String>>,
+  private_hosts: Option<Vec<String>>,
+  connect_timeout_ms: Option<u64>,
+  read_timeout_ms: Option<u64>,
+  max_redirects: Option<usize>,
+  max_concurrent_downloads: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AttachmentsConfig {
+  /// byte limit for mime types without an entry in `limits`
+  pub max_bytes: u64,
+  /// byte limits by mime type, keys ending in `/` match every subtype
+  pub limits: BTreeMap<String, u64>,
+  /// longest image side for models without an entry in `image_dimensions`
+  pub max_image_dimension: u32,
+  /// longest image side by model id prefix, larger images are downscaled before sending
+  pub image_dimensions: BTreeMap<String, u32>,
+  /// hosts attachments are downloaded from, `*.example.com` matches every subdomain
+  pub allowed_hosts: Vec<String>,
+  /// allowed hosts that may resolve to private or loopback addresses, e.g. a local convex backend
+  pub private_hosts: Vec<String>,
+  pub connect_timeout: Duration,
+  /// longest wait for the next chunk of a download
+  pub read_timeout: Duration,
+  pub max_redirects: usize,
+  /// attachment lookups and downloads running at once while a prompt is built
+  pub max_concurrent_downloads: usize,
+}
+
+impl AttachmentsConfig {
+  const MAX_BYTES_KEY: Key = key("attachments.max_bytes", "ATTACHMENT_MAX_BYTES");
+  const MAX_IMAGE_DIMENSION_KEY: Key = key("attachments.max_image_dimension", "ATTACHMENT_MAX_IMAGE_DIMENSION");
+  const ALLOWED_HOSTS_KEY: Key = key("attachments.allowed_hosts", "ATTACHMENT_ALLOWED_HOSTS");
+  const PRIVATE_HOSTS_KEY: Key = key("attachments.private_hosts", "ATTACHMENT_PRIVATE_HOSTS");
+  const CONNECT_TIMEOUT_KEY: Key = key("attachments.connect_timeout_ms", "ATTACHMENT_CONNECT_TIMEOUT_MS");
+  const READ_TIMEOUT_KEY: Key = key("attachments.read_timeout_ms", "ATTACHMENT_READ_TIMEOUT_MS");
+  const MAX_REDIRECTS_KEY: Key = key("attachments.max_redirects", "ATTACHMENT_MAX_REDIRECTS");
+  const MAX_CONCURRENT_DOWNLOADS_KEY: Key = key(
+    "attachments.max_concurrent_downloads",
+    "ATTACHMENT_MAX_CONCURRENT_DOWNLOADS",
+  );
+
+  const DEFAULT_MAX_BYTES: u64 = 20 * 1024 * 1024;
+  /// text is sent inline and counts fully towards the context
+  const DEFAULT_LIMITS: [(&'static str, u64); 2] = [("text/", 1024 * 1024), ("application/json", 1024 * 1024)];
+  const DEFAULT_MAX_IMAGE_DIMENSION: u32 = 2048;
+  /// sizes the providers document as the largest they use without scaling down themselves
+  const DEFAULT_IMAGE_DIMENSIONS: [(&'static str, u32); 3] =
+    [("anthropic/", 1568), ("openai/", 2048), ("google/", 3072)];
+  const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 5_000;
+  const DEFAULT_READ_TIMEOUT_MS: u64 = 15_000;
+  const DEFAULT_MAX_REDIRECTS: usize = 3;
+  const DEFAULT_MAX_CONCURRENT_DOWNLOADS: usize = 4;
+
+  /// uploads are served from the convex deployment, so its host is allowed when no hosts are configured
+  fn load(
+    loader: &mut Loader<impl Fn(&str) -> Option<String>>,
+    file: AttachmentsSection,
+    convex_url: Option<&Url>,
+  ) -> Option<Self> {
+    let max_bytes = loader
+      .optional(Self::MAX_BYTES_KEY, file.max_bytes)
+      .unwrap_or(Self::DEFAULT_MAX_BYTES);
+    let max_image_dimension = loader
+      .optional(Self::MAX_IMAGE_DIMENSION_KEY, file.max_image_dimension)
+      .unwrap_or(Self::DEFAULT_MAX_IMAGE_DIMENSION);
+    let connect_timeout = loader
+      .optional(Self::CONNECT_TIMEOUT_KEY, file.connect_timeout_ms)
+      .unwrap_or(Self::DEFAULT_CONNECT_TIMEOUT_MS);
+    let read_timeout = loader
+      .optional(Self::READ_TIMEOUT_KEY, file.read_timeout_ms)
+      .unwrap_or(Self::DEFAULT_READ_TIMEOUT_MS);
+    let max_redirects = loader
+      .optional(Self::MAX_REDIRECTS_KEY, file.max_redirects)
+      .unwrap_or(Self::DEFAULT_MAX_REDIRECTS);
+    let max_concurrent_downloads = loader
+      .optional(Self::MAX_CONCURRENT_DOWNLOADS_KEY, file.max_concurrent_downloads)
+      .unwrap_or(Self::DEFAULT_MAX_CONCURRENT_DOWNLOADS);
+    // urls are parsed with lowercase hosts
+    let hosts = |hosts: Vec<String>| {
+      hosts
+        .into_iter()
+        .map(|host| host.to_ascii_lowercase())
+        .collect::<Vec<_>>()
+    };
+    let private_hosts = hosts(loader.list(Self::PRIVATE_HOSTS_KEY, file.private_hosts));
+
+    let mut allowed_hosts = hosts(loader.list(Self::ALLOWED_HOSTS_KEY, file.allowed_hosts));
+    if allowed_hosts.is_empty() {
+      allowed_hosts.extend(convex_url.and_then(Url::host_str).map(str::to_string));
+    }
+
+    let problems = loader.problems.len();
+
+    if max_image_dimension == 0 {
+      loader.problem(Self::MAX_IMAGE_DIMENSION_KEY, "must be greater than 0");
+    }
+
+    if max_concurrent_downloads == 0 {
+      loader.problem(Self::MAX_CONCURRENT_DOWNLOADS_KEY, "must be greater than 0");
+    }
+
+    if loader.problems.len() > problems {
+      return None;
+    }
+
+    let limits = file.limits.unwrap_or_else(|| {
+      Self::DEFAULT_LIMITS
+        .iter()
+        .map(|(mime_type, limit)| (mime_type.to_string(), *limit))
+        .collect()
+    });
+    let image_dimensions = file.image_dimensions.unwrap_or_else(|| {
+      Self::DEFAULT_IMAGE_DIMENSIONS
+        .iter()
+        .map(|(model, dimension)| (model.to_string(), *dimension))
+        .collect()
+    });
+
+    Some(Self {
+      max_bytes,
+      limits,
+      max_image_dimension,
+      image_dimensions,
+      allowed_hosts,
+      private_hosts,
+      connect_timeout: Duration::from_millis(connect_timeout),
+      read_timeout: Duration::from_millis(read_timeout),
+      max_redirects,
+      max_concurrent_downloads,
+    })
+  }
+
+  /// an exact mime type entry wins over a `type/` entry, which wins over `max_bytes`
+  pub fn limit_for(&self, mime_type: &str) -> u64 {
+    if let Some(limit) = self.limits.get(mime_type) {
+      return *limit;
+    }
+
+    mime_type
+      .split_once('/')
+      .and_then(|(kind, _)| self.limits.get(&format!("{kind}/")))
+      .copied()
+      .unwrap_or(self.max_bytes)
+  }
+
+  /// the longest matching model prefix wins
+  pub fn image_dimension_for(&self, model: &str) -> u32 {
+    self
+      .image_dimensions
+      .iter()
+      .filter(|(prefix, _)| model.starts_with(prefix.as_str()))
+      .max_by_key(|(prefix, _)| prefix.len())
+      .map_or(self.max_image_dimension, |(_, dimension)| *dimension)
+  }
+
+  pub fn allows_host(&self, host: &str) -> bool {
+    host_matches(&self.allowed_hosts, host)
+  }
+
+  pub fn allows_private_address(&self, host: &str) -> bool {
+    host_matches(&self.private_hosts, host)
+  }
+}
+
+/// exact matches, or any subdomain for `*.` patterns
+fn host_matches(patterns: &[String], host: &str) -> bool {
+  let host = host.trim_end_matches('.');
+
+  patterns.iter().any(|pattern| match pattern.strip_prefix("*.") {
+    Some(domain) => host
+      .strip_suffix(domain)
+      .is_some_and(|subdomain| subdomain.ends_with('.')),
+    None => pattern == host,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use secrecy::ExposeSecret;
+
+  use super::*;
+
+  const EXAMPLE_CONFIG: &str = include_str!("../example.config.toml");
+
+  fn load(file: &str, env: &[(&str, &str)]) -> Result<Config, ConfigError> {
+    let file = toml::from_str(file).unwrap();
+    let env: HashMap<String, String> = env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+
+    Config::from_sources(file, |key| env.get(key).cloned())
+  }
+
+  #[test]
+  fn test_example_config() {
+    let config = load(EXAMPLE_CONFIG, &[]).unwrap();
+
+    assert_eq!(config.application.port, 3000);
+    assert_eq!(config.openrouter.api_url.as_str(), "https://openrouter.ai/api/v1/");
+    assert_eq!(config.convex.url.as_str(), "http://127.0.0.1:3210/");
+    assert_eq!(config.limits.max_concurrent_generations, 256);
+    assert_eq!(config.limits.generation_lease, Duration::from_secs(30));
+    assert_eq!(config.limits.max_generation_attempts, 3);
+    assert!(!config.worker.enabled);
+    assert_eq!(config.models.title, "anthropic/claude-3-haiku");
+    assert!(config.models.is_allowed("openai/gpt-4o"));
+    assert_eq!(config.models.metrics_label("openai/gpt-4o"), "other");
+    assert!(config.models.passes_reasoning("anthropic/claude-sonnet-4"));
+    assert!(!config.models.passes_reasoning("openai/gpt-4o"));
+    assert_eq!(config.models.max_continuations, 0);
+    assert_eq!(config.keys.policy, KeyPolicy::User);
+    assert!(config.keys.encryption_key.is_none());
+    assert_eq!(config.attachments.limit_for("image/png"), 10 * 1024 * 1024);
+    assert_eq!(config.attachments.limit_for("text/csv"), 1024 * 1024);
+    assert_eq!(config.attachments.limit_for("application/zip"), 20 * 1024 * 1024);
+    assert_eq!(
+      config.attachments.image_dimension_for("anthropic/claude-sonnet-4"),
+      1568
+    );
+    assert_eq!(config.attachments.image_dimension_for("mistralai/pixtral-large"), 2048);
+    assert!(config.attachments.allows_host("127.0.0.1"));
+    assert!(config.attachments.allows_private_address("127.0.0.1"));
+    assert!(!config.attachments.allows_host("169.254.169.254"));
+    assert_eq!(config.attachments.max_concurrent_downloads, 4);
+  }
+
+  #[test]
+  fn test_keys_config() {
+    let config = load(
+      EXAMPLE_CONFIG,
+      &[
+        ("OPENROUTER_KEY_POLICY", "user_with_fallback"),
+        ("SERVER_KEY_DAILY_TOKENS", "100000"),
+      ],
+    )
+    .unwrap();
+
+    assert_eq!(config.keys.policy, KeyPolicy::UserWithFallback);
+    assert_eq!(config.keys.server_daily_tokens, Some(100_000));
+
+    let Err(ConfigError::Invalid(problems)) = load(
+      EXAMPLE_CONFIG,
+      &[
+        ("OPENROUTER_KEY_POLICY", "anyone"),
+        ("OPENROUTER_KEY_ENCRYPTION_KEY", "c2hvcnQ="),
+      ],
+    ) else {
+      panic!("expected an invalid config");
+    };
+
+    assert_eq!(
+      problems,
+      vec![
+        "keys.policy (OPENROUTER_KEY_POLICY) is invalid: expected one of user, server or user_with_fallback"
+          .to_string(),
+        "keys.encryption_key (OPENROUTER_KEY_ENCRYPTION_KEY) is invalid: must be 32 bytes, got 5".into(),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_env_overrides_file() {
+    let config = load(
+      EXAMPLE_CONFIG,
+      &[
+        ("APP_PORT", "8080"),
+        ("OPENROUTER_API_KEY", "from-env"),
+        ("ALLOWED_MODELS", "openai/gpt-4o, anthropic/claude-3-haiku"),
+      ],
+    )
+    .unwrap();
+
+    assert_eq!(config.application.port, 8080);
+    assert_eq!(config.application.host, "0.0.0.0");
+    assert_eq!(config.openrouter.api_key.expose_secret(), "from-env");
+    assert!(config.models.is_allowed("anthropic/claude-3-haiku"));
+    assert!(!config.models.is_allowed("openai/gpt-4o-mini"));
+    assert_eq!(
+      config.models.metrics_label("anthropic/claude-3-haiku"),
+      "anthropic/claude-3-haiku"
+    );
+    assert_eq!(config.models.metrics_label("openai/gpt-4o-mini"), "other");
+  }
+
+  #[test]
+  fn test_reports_every_problem() {
+    let Err(ConfigError::Invalid(problems)) = load(
+      "[snowflake]\nworker = 200\n",
+      &[("APP_PORT", "eighty"), ("OPENROUTER_DATA_COLLECTION", "sometimes")],
+    ) else {
+      panic!("expected an invalid config");
+    };
+
+    assert_eq!(
+      problems,
+      vec![
+        "application.port (APP_PORT) is invalid: invalid digit found in string".to_string(),
+        format!(
+          "snowflake.worker (SNOWFLAKE_WORKER) must be at most {}",
+          snowflake::WORKER_MAX
+        ),
+        "openrouter.api_key (OPENROUTER_API_KEY) must be set in the config file or the environment".into(),
+        "openrouter.data_collection (OPENROUTER_DATA_COLLECTION) must be either allow or deny".into(),
+        "convex.url (CONVEX_URL) must be set in the config file or the environment".into(),
+        "convex.deployment (CONVEX_DEPLOYMENT) must be set in the config file or the environment".into(),
+        "convex.api_key (CONVEX_API_KEY) must be set in the config file or the environment".into(),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_reports_every_limit_problem() {
+    let Err(ConfigError::Invalid(problems)) = load(
+      EXAMPLE_CONFIG,
+      &[
+        ("MAX_CONCURRENT_GENERATIONS", "0"),
+        ("GENERATION_LEASE_MS", "10"),
+        ("MAX_GENERATION_ATTEMPTS", "0"),
+      ],
+    ) else {
+      panic!("expected an invalid config");
+    };
+
+    assert_eq!(
+      problems,
+      vec![
+        "limits.max_concurrent_generations (MAX_CONCURRENT_GENERATIONS) must be greater than 0".to_string(),
+        "limits.generation_lease_ms (GENERATION_LEASE_MS) must be at least 1000".into(),
+        "limits.max_generation_attempts (MAX_GENERATION_ATTEMPTS) must be greater than 0".into(),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_reports_every_attachment_problem() {
+    let Err(ConfigError::Invalid(problems)) = load(
+      EXAMPLE_CONFIG,
+      &[
+        ("ATTACHMENT_MAX_IMAGE_DIMENSION", "0"),
+        ("ATTACHMENT_MAX_CONCURRENT_DOWNLOADS", "0"),
+      ],
+    ) else {
+      panic!("expected an invalid config");
+    };
+
+    assert_eq!(
+      problems,
+      vec![
+        "attachments.max_image_dimension (ATTACHMENT_MAX_IMAGE_DIMENSION) must be greater than 0".to_string(),
+        "attachments.max_concurrent_downloads (ATTACHMENT_MAX_CONCURRENT_DOWNLOADS) must be greater than 0".into(),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_defaults() {
+    let config = load(
+      "",
+      &[
+        ("OPENROUTER_API_KEY", "test-key"),
+        ("CONVEX_URL", "http://127.0.0.1:3210"),
+        ("CONVEX_DEPLOYMENT", "anonymous:anonymous-t4chat"),
+        ("CONVEX_API_KEY", "test-key"),
+      ],
+    )
+    .unwrap();
+
+    assert_eq!(config.application.host, "0.0.0.0");
+    assert_eq!(config.application.port, 8080);
+    assert_eq!(config.snowflake.worker, 0);
+    assert_eq!(config.openrouter.api_url.as_str(), "https://openrouter.ai/api/v1/");
+    assert_eq!(
+      config.openrouter.model_api_url.as_str(),
+      "https://openrouter.ai/api/v1/"
+    );
+  }
+
+  #[test]
+  fn test_attachment_hosts() {
+    let config = load(
+      EXAMPLE_CONFIG,
+      &[("ATTACHMENT_ALLOWED_HOSTS", "*.convex.cloud, Files.Example.com")],
+    )
+    .unwrap();
+
+    assert!(config.attachments.allows_host("happy-otter-123.convex.cloud"));
+    assert!(config.attachments.allows_host("files.example.com"));
+    assert!(!config.attachments.allows_host("convex.cloud"));
+    assert!(!config.attachments.allows_host("evilconvex.cloud"));
+    assert!(!config.attachments.allows_host("127.0.0.1"));
+  }
 
-    Ok(Self { url, id, api_key })
+  #[test]
+  fn test_unknown_keys_are_rejected() {
+    assert!(toml::from_str::<ConfigFile>("[application]\nprot = 3000\n").is_err());
   }
 }
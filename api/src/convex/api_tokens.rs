@@ -0,0 +1,46 @@
+use serde::Deserialize;
+use serde::de::IgnoredAny;
+
+use crate::convex::bindings::{convex_args, convex_functions};
+use crate::convex::{ConvexClient, Doc, Id, Result, Table};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiToken {
+  pub name: String,
+  pub user_id: Option<String>,
+  pub created_at: f64,
+  pub last_used_at: Option<f64>,
+  pub revoked_at: Option<f64>,
+}
+
+impl Table for ApiToken {
+  const NAME: &'static str = "apiTokens";
+}
+
+convex_args! {
+  #[derive(Debug)]
+  pub struct CreateTokenArgs {
+    pub name: String,
+    /// sha256 of the token, the token itself is only shown once
+    pub token_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+  }
+}
+
+convex_functions! {
+  /// returns the id of the new token
+  pub mutation create("apiTokens:apiCreate", args: &CreateTokenArgs) -> Id<ApiToken>;
+  pub query get_by_hash("apiTokens:apiGetByHash", token_hash: String) -> Option<Doc<ApiToken>>;
+  pub query list("apiTokens:apiList") -> Vec<Doc<ApiToken>>;
+  /// returns false when there is no token with this id
+  pub idempotent mutation revoke("apiTokens:apiRevoke", id: Id<ApiToken>) -> bool;
+  idempotent mutation api_touch("apiTokens:apiTouch", id: Id<ApiToken>) -> IgnoredAny;
+}
+
+pub async fn touch(client: &mut ConvexClient, id: Id<ApiToken>) -> Result<()> {
+  api_touch(client, id).await?;
+
+  Ok(())
+}
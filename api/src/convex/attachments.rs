@@ -1,27 +1,22 @@
-use std::collections::BTreeMap;
-
-use convex::Value;
 use serde::Deserialize;
 
-use crate::convex::{ConvexClient, Result, convex_query};
+use crate::convex::bindings::convex_functions;
+use crate::convex::{Doc, Id, Table};
 
-#[derive(Deserialize)]
+#[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Attachment {
-  #[serde(rename = "_id")]
-  pub id: String,
   pub name: String,
   pub url: String,
   pub mime_type: String,
+  /// bytes as reported by the uploader
+  pub size: f64,
 }
 
-pub async fn get_by_id(client: &mut ConvexClient, id: String) -> Result<Option<Attachment>> {
-  const GET_BY_ID: &str = "attachments:apiGetById";
+impl Table for Attachment {
+  const NAME: &'static str = "attachments";
+}
 
-  convex_query(
-    client,
-    GET_BY_ID,
-    BTreeMap::from([("id".to_string(), Value::String(id))]),
-  )
-  .await
+convex_functions! {
+  pub query get_by_id("attachments:apiGetById", id: Id<Attachment>) -> Option<Doc<Attachment>>;
 }
@@ -0,0 +1,335 @@
+use std::collections::BTreeMap;
+
+use convex::Value;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::convex::{ConvexClient, Result, convex_mutation, convex_query};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FunctionKind {
+  Query,
+  Mutation,
+  /// a mutation that can run twice without changing the result, only these are retried after losing the connection
+  IdempotentMutation,
+}
+
+/// a convex function declared with [`convex_functions!`], checked against the convex sources in the tests
+#[derive(Debug)]
+pub struct FunctionSpec {
+  /// `module:function`
+  pub name: &'static str,
+  pub kind: FunctionKind,
+  /// argument names as written in rust, they are sent in camel case. `apiKey` is added to every call
+  pub args: &'static [&'static str],
+}
+
+/// argument struct declared with [`convex_args!`]
+pub trait ConvexArgs: Serialize {
+  const FIELDS: &'static [&'static str];
+}
+
+pub async fn call<T: DeserializeOwned>(
+  client: &mut ConvexClient,
+  kind: FunctionKind,
+  name: &'static str,
+  args: BTreeMap<String, Value>,
+) -> Result<T> {
+  match kind {
+    FunctionKind::Query => convex_query(client, name, args).await,
+    FunctionKind::Mutation => convex_mutation(client, name, args, false).await,
+    FunctionKind::IdempotentMutation => convex_mutation(client, name, args, true).await,
+  }
+}
+
+/// Declares an argument struct that is serialized in camel case and remembers its field names.
+///
+/// Usage:
+/// ```ignore
+/// convex_args! {
+///   #[derive(Debug)]
+///   pub struct CancelArgs {
+///     pub message_id: Id<Message>,
+///   }
+/// }
+/// ```
+macro_rules! convex_args {
+  (
+    $(#[$attr:meta])*
+    $vis:vis struct $name:ident {
+      $($(#[$field_attr:meta])* $field_vis:vis $field:ident: $ty:ty),* $(,)?
+    }
+  ) => {
+    $(#[$attr])*
+    #[derive(serde::Serialize)]
+    #[serde(rename_all = "camelCase")]
+    $vis struct $name {
+      $($(#[$field_attr])* $field_vis $field: $ty),*
+    }
+
+    impl $crate::convex::bindings::ConvexArgs for $name {
+      const FIELDS: &'static [&'static str] = &[$(stringify!($field)),*];
+    }
+  };
+}
+
+/// Declares convex functions with their name, arguments and return type, and generates an async wrapper for each.
+///
+/// Functions are a `query`, a `mutation` or a `subscription`, which watches a query and returns a stream of its
+/// results. Mutations are only retried after a connection error when they are marked `idempotent`, because the
+/// first attempt may already have been applied. Arguments are either listed inline or passed as a struct declared
+/// with [`convex_args!`]. Every block also generates a `FUNCTIONS` list of its declarations, so there can be only one
+/// block per module.
+///
+/// Usage:
+/// ```ignore
+/// convex_functions! {
+///   pub query get_by_id("messages:apiGetById", id: Id<Message>) -> Option<Doc<Message>>;
+///   pub idempotent mutation complete("messages:apiComplete", args: &CompleteMessageArgs) -> Option<IdOnly>;
+///   pub mutation append("messages:apiAppend", args: &AppendArgs) -> Option<IdOnly>;
+/// }
+/// ```
+macro_rules! convex_functions {
+  (
+    @munch [$($spec:tt)*]
+    $(#[$attr:meta])*
+    $vis:vis idempotent mutation $($rest:tt)*
+  ) => {
+    $crate::convex::bindings::convex_functions!(
+      @munch [$($spec)*] $(#[$attr])* $vis idempotent_mutation $($rest)*
+    );
+  };
+
+  (
+    @munch [$($spec:tt)*]
+    $(#[$attr:meta])*
+    $vis:vis $kind:ident $name:ident($path:literal, args: &$args:ty) -> $ret:ty;
+    $($rest:tt)*
+  ) => {
+    $(#[$attr])*
+    $vis async fn $name(
+      client: &mut $crate::convex::ConvexClient,
+      args: &$args,
+    ) -> $crate::convex::bindings::convex_functions!(@ret $kind $ret) {
+      $crate::convex::bindings::convex_functions!(@call $kind client, $path, $crate::convex_serde::to_map(args)?)
+    }
+
+    $crate::convex::bindings::convex_functions!(
+      @munch [$($spec)* ($path, $kind, <$args as $crate::convex::bindings::ConvexArgs>::FIELDS)] $($rest)*
+    );
+  };
+
+  (
+    @munch [$($spec:tt)*]
+    $(#[$attr:meta])*
+    $vis:vis $kind:ident $name:ident($path:literal $(, $arg:ident: $ty:ty)* $(,)?) -> $ret:ty;
+    $($rest:tt)*
+  ) => {
+    $(#[$attr])*
+    $vis async fn $name(
+      client: &mut $crate::convex::ConvexClient,
+      $($arg: $ty),*
+    ) -> $crate::convex::bindings::convex_functions!(@ret $kind $ret) {
+      #[derive(serde::Serialize)]
+      #[serde(rename_all = "camelCase")]
+      struct Args {
+        $($arg: $ty),*
+      }
+
+      $crate::convex::bindings::convex_functions!(
+        @call $kind client, $path, $crate::convex_serde::to_map(&Args { $($arg),* })?
+      )
+    }
+
+    $crate::convex::bindings::convex_functions!(
+      @munch [$($spec)* ($path, $kind, &[$(stringify!($arg)),*])] $($rest)*
+    );
+  };
+
+  (@munch [$(($path:literal, $kind:ident, $args:expr))*]) => {
+    #[cfg(test)]
+    pub(crate) const FUNCTIONS: &[$crate::convex::bindings::FunctionSpec] = &[$(
+      $crate::convex::bindings::FunctionSpec {
+        name: $path,
+        kind: $crate::convex::bindings::convex_functions!(@kind $kind),
+        args: $args,
+      }
+    ),*];
+  };
+
+  (@kind query) => {
+    $crate::convex::bindings::FunctionKind::Query
+  };
+
+  // subscriptions are queries that keep sending results
+  (@kind subscription) => {
+    $crate::convex::bindings::FunctionKind::Query
+  };
+
+  (@kind mutation) => {
+    $crate::convex::bindings::FunctionKind::Mutation
+  };
+
+  (@kind idempotent_mutation) => {
+    $crate::convex::bindings::FunctionKind::IdempotentMutation
+  };
+
+  (@ret subscription $ret:ty) => {
+    std::result::Result<
+      impl futures::Stream<Item = std::result::Result<$ret, $crate::convex::ConvexError>>,
+      $crate::convex::ConvexError,
+    >
+  };
+
+  (@ret $kind:ident $ret:ty) => {
+    std::result::Result<$ret, $crate::convex::ConvexError>
+  };
+
+  (@call subscription $client:ident, $path:literal, $args:expr) => {
+    $crate::convex::convex_subscribe($client, $path, $args).await
+  };
+
+  (@call $kind:ident $client:ident, $path:literal, $args:expr) => {
+    $crate::convex::bindings::call(
+      $client,
+      $crate::convex::bindings::convex_functions!(@kind $kind),
+      $path,
+      $args,
+    )
+    .await
+  };
+
+  ($($body:tt)*) => {
+    $crate::convex::bindings::convex_functions!(@munch [] $($body)*);
+  };
+}
+
+pub(crate) use {convex_args, convex_functions};
+
+#[cfg(test)]
+mod tests {
+  use std::collections::BTreeSet;
+  use std::path::PathBuf;
+
+  use super::*;
+  use crate::convex::{api_tokens, attachments, health, imports, messages, openrouter_keys, threads, usage};
+
+  fn convex_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../front/convex")
+  }
+
+  /// modules listed in the generated `api.d.ts`
+  fn api_modules() -> BTreeSet<String> {
+    let api = std::fs::read_to_string(convex_dir().join("_generated/api.d.ts")).unwrap();
+
+    api
+      .lines()
+      .filter_map(|line| line.trim().strip_suffix(';')?.split_once(": typeof "))
+      .map(|(module, _)| module.to_string())
+      .collect()
+  }
+
+  /// the entries of the `args: { ... }` object of an exported function, with whether they are optional
+  fn typescript_args(source: &str, function: &str, kind: FunctionKind) -> Option<BTreeMap<String, bool>> {
+    let kind = match kind {
+      FunctionKind::Query => "query",
+      FunctionKind::Mutation | FunctionKind::IdempotentMutation => "mutation",
+    };
+
+    let start = source.find(&format!("export const {function} = {kind}({{"))?;
+    let body = &source[start..];
+    let body = &body[body.find("args: {")? + "args: {".len()..];
+
+    let mut args = BTreeMap::new();
+    let mut depth = 0;
+    let mut entry = String::new();
+
+    for char in body.chars() {
+      match char {
+        '{' | '(' | '[' => depth += 1,
+        '}' | ')' | ']' if depth > 0 => depth -= 1,
+        '}' | ',' if depth == 0 => {
+          if let Some((name, validator)) = entry.split_once(':') {
+            args.insert(name.trim().to_string(), validator.trim().starts_with("v.optional("));
+          }
+
+          entry.clear();
+
+          if char == '}' {
+            return Some(args);
+          }
+
+          continue;
+        }
+        _ => {}
+      }
+
+      entry.push(char);
+    }
+
+    None
+  }
+
+  fn camel_case(name: &str) -> String {
+    let mut parts = name.split('_');
+    let first = parts.next().unwrap_or_default().to_string();
+
+    parts.fold(first, |mut camel, part| {
+      let mut chars = part.chars();
+      camel.extend(chars.next().map(|char| char.to_ascii_uppercase()));
+      camel.push_str(chars.as_str());
+      camel
+    })
+  }
+
+  #[test]
+  fn test_camel_case() {
+    assert_eq!(camel_case("id"), "id");
+    assert_eq!(camel_case("time_to_first_token_ms"), "timeToFirstTokenMs");
+  }
+
+  #[test]
+  fn test_bindings_match_convex_functions() {
+    let modules = api_modules();
+    let functions = [
+      api_tokens::FUNCTIONS,
+      attachments::FUNCTIONS,
+      health::FUNCTIONS,
+      imports::FUNCTIONS,
+      messages::FUNCTIONS,
+      openrouter_keys::FUNCTIONS,
+      threads::FUNCTIONS,
+      usage::FUNCTIONS,
+    ];
+
+    for function in functions.into_iter().flatten() {
+      let (module, name) = function.name.split_once(':').unwrap();
+      assert!(
+        modules.contains(module),
+        "{}: {module} is not in api.d.ts",
+        function.name
+      );
+
+      let source = std::fs::read_to_string(convex_dir().join(format!("{module}.ts"))).unwrap();
+      let Some(mut expected) = typescript_args(&source, name, function.kind) else {
+        panic!("{}: no exported {:?} with args", function.name, function.kind);
+      };
+
+      assert_eq!(expected.remove("apiKey"), Some(false), "{}: apiKey", function.name);
+
+      let declared = function.args.iter().map(|arg| camel_case(arg)).collect::<BTreeSet<_>>();
+
+      for arg in &declared {
+        assert!(expected.contains_key(arg), "{}: unknown argument {arg}", function.name);
+      }
+
+      for (arg, optional) in expected {
+        assert!(
+          optional || declared.contains(&arg),
+          "{}: missing argument {arg}",
+          function.name
+        );
+      }
+    }
+  }
+}
@@ -0,0 +1,15 @@
+use serde::de::IgnoredAny;
+
+use crate::convex::bindings::convex_functions;
+use crate::convex::{ConvexClient, Result};
+
+convex_functions! {
+  query api_ping("health:apiPing") -> IgnoredAny;
+}
+
+/// cheap query that only checks the deployment is reachable and accepts our api key
+pub async fn ping(client: &mut ConvexClient) -> Result<()> {
+  api_ping(client).await?;
+
+  Ok(())
+}
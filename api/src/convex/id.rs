@@ -0,0 +1,180 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+use convex::Value;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// a convex table, ties an [`Id`] to the documents it points at
+pub trait Table {
+  const NAME: &'static str;
+}
+
+/// id of a document in table `T`, (de)serialized as the plain string convex uses
+pub struct Id<T> {
+  id: String,
+  table: PhantomData<fn() -> T>,
+}
+
+impl<T> Id<T> {
+  /// the id is not checked, convex rejects ids of other tables when they are validated with `v.id`
+  pub fn new(id: impl Into<String>) -> Self {
+    Self {
+      id: id.into(),
+      table: PhantomData,
+    }
+  }
+
+  pub fn as_str(&self) -> &str {
+    &self.id
+  }
+
+  pub fn into_string(self) -> String {
+    self.id
+  }
+}
+
+// derives would require `T` itself to implement these traits
+
+impl<T> Clone for Id<T> {
+  fn clone(&self) -> Self {
+    Self::new(self.id.clone())
+  }
+}
+
+impl<T> PartialEq for Id<T> {
+  fn eq(&self, other: &Self) -> bool {
+    self.id == other.id
+  }
+}
+
+impl<T> Eq for Id<T> {}
+
+impl<T> PartialOrd for Id<T> {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl<T> Ord for Id<T> {
+  fn cmp(&self, other: &Self) -> Ordering {
+    self.id.cmp(&other.id)
+  }
+}
+
+impl<T> Hash for Id<T> {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.id.hash(state);
+  }
+}
+
+impl<T: Table> fmt::Debug for Id<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Id<{}>({:?})", T::NAME, self.id)
+  }
+}
+
+impl<T> fmt::Display for Id<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.id)
+  }
+}
+
+impl<T> AsRef<str> for Id<T> {
+  fn as_ref(&self) -> &str {
+    &self.id
+  }
+}
+
+impl<T> From<Id<T>> for Value {
+  fn from(id: Id<T>) -> Self {
+    Value::String(id.id)
+  }
+}
+
+impl<T> Serialize for Id<T> {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&self.id)
+  }
+}
+
+impl<'de, T> Deserialize<'de> for Id<T> {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    String::deserialize(deserializer).map(Self::new)
+  }
+}
+
+/// a document as returned by convex, the system fields next to the table's own fields
+#[derive(Debug, Clone, Deserialize)]
+#[serde(bound(deserialize = "T: Deserialize<'de>"))]
+pub struct Doc<T: Table> {
+  #[serde(rename = "_id")]
+  pub id: Id<T>,
+  /// unix ms
+  #[serde(rename = "_creationTime")]
+  pub creation_time: f64,
+  #[serde(flatten)]
+  pub fields: T,
+}
+
+impl<T: Table> Deref for Doc<T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    &self.fields
+  }
+}
+
+impl<T: Table> DerefMut for Doc<T> {
+  fn deref_mut(&mut self) -> &mut T {
+    &mut self.fields
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::BTreeMap;
+
+  use super::*;
+  use crate::convex_serde::{from_value, to_value};
+
+  #[derive(Debug, Deserialize)]
+  #[serde(rename_all = "camelCase")]
+  struct Note {
+    text: String,
+    parent_id: Option<Id<Note>>,
+  }
+
+  impl Table for Note {
+    const NAME: &'static str = "notes";
+  }
+
+  #[test]
+  fn test_id_is_a_string() {
+    let id = Id::<Note>::new("k57abc");
+
+    assert_eq!(to_value(&id).unwrap(), Value::String("k57abc".into()));
+    assert_eq!(from_value::<Id<Note>>(Value::String("k57abc".into())).unwrap(), id);
+    assert_eq!(Value::from(id.clone()), Value::String("k57abc".into()));
+    assert_eq!(format!("{id} {id:?}"), "k57abc Id<notes>(\"k57abc\")");
+  }
+
+  #[test]
+  fn test_doc_splits_system_fields() {
+    let value = Value::Object(BTreeMap::from([
+      ("_id".to_string(), Value::String("k57abc".into())),
+      ("_creationTime".to_string(), Value::Float64(1_700_000_000_000.0)),
+      ("text".to_string(), Value::String("hello".into())),
+      ("parentId".to_string(), Value::String("k57xyz".into())),
+    ]));
+
+    let doc: Doc<Note> = from_value(value).unwrap();
+
+    assert_eq!(doc.id.as_str(), "k57abc");
+    assert_eq!(doc.creation_time, 1_700_000_000_000.0);
+    assert_eq!(doc.text, "hello");
+    assert_eq!(doc.parent_id, Some(Id::new("k57xyz")));
+  }
+}
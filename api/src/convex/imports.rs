@@ -0,0 +1,168 @@
+use std::iter::Peekable;
+
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+
+use crate::convex::bindings::{convex_args, convex_functions};
+use crate::convex::threads::Thread;
+use crate::convex::{ConvexClient, Doc, Id, Result};
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportRole {
+  User,
+  Assistant,
+  System,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportMessageArgs {
+  pub role: ImportRole,
+  pub text: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub created_at: Option<f64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub model: Option<String>,
+}
+
+/// a thread and all of its messages, stored by [`import_thread`]
+#[derive(Debug)]
+pub struct ImportThreadArgs {
+  pub user_id: String,
+  pub import_id: String,
+  pub title: Option<String>,
+  pub model: String,
+  pub created_at: f64,
+  pub updated_at: f64,
+  pub messages: Vec<ImportMessageArgs>,
+}
+
+convex_args! {
+  #[derive(Debug)]
+  struct StartImportArgs {
+    user_id: String,
+    import_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    model: String,
+    created_at: f64,
+    updated_at: f64,
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ImportProgress {
+  thread_id: Id<Thread>,
+  /// messages already stored, more than 0 when an earlier import of the conversation stopped halfway
+  imported_count: f64,
+}
+
+convex_functions! {
+  /// only finds threads whose import finished
+  pub query get_by_import_id(
+    "imports:apiGetByImportId",
+    user_id: String,
+    import_id: String,
+  ) -> Option<Doc<Thread>>;
+  idempotent mutation api_start_import("imports:apiStartImport", args: &StartImportArgs) -> ImportProgress;
+  /// `offset` is the number of messages stored before this batch, a batch that was already stored is ignored
+  idempotent mutation api_import_messages(
+    "imports:apiImportMessages",
+    thread_id: Id<Thread>,
+    offset: f64,
+    messages: Vec<ImportMessageArgs>,
+  ) -> ImportProgress;
+  idempotent mutation api_finish_import("imports:apiFinishImport", thread_id: Id<Thread>) -> IgnoredAny;
+}
+
+/// messages and bytes of text sent per mutation, convex limits how much a single mutation may write
+const BATCH_MESSAGES: usize = 100;
+const BATCH_BYTES: usize = 1024 * 1024;
+
+/// the next messages that fit in one mutation. a message larger than the byte limit still goes out, alone in its batch
+fn take_batch(messages: &mut Peekable<impl Iterator<Item = ImportMessageArgs>>) -> Vec<ImportMessageArgs> {
+  let mut batch = vec![];
+  let mut bytes = 0;
+
+  while let Some(message) = messages
+    .next_if(|message| batch.is_empty() || (batch.len() < BATCH_MESSAGES && bytes + message.text.len() <= BATCH_BYTES))
+  {
+    bytes += message.text.len();
+    batch.push(message);
+  }
+
+  batch
+}
+
+/// creates a thread with all of its messages, returns the new thread id.
+///
+/// messages are stored in batches and the thread stays hidden until the last one is stored. importing the same
+/// conversation again continues after the last stored batch
+pub async fn import_thread(client: &mut ConvexClient, args: ImportThreadArgs) -> Result<Id<Thread>> {
+  let ImportThreadArgs {
+    user_id,
+    import_id,
+    title,
+    model,
+    created_at,
+    updated_at,
+    messages,
+  } = args;
+
+  let start = StartImportArgs {
+    user_id,
+    import_id,
+    title,
+    model,
+    created_at,
+    updated_at,
+  };
+
+  let progress = api_start_import(client, &start).await?;
+  let thread_id = progress.thread_id;
+  let mut offset = progress.imported_count as usize;
+
+  let mut remaining = messages.into_iter().skip(offset).peekable();
+
+  while remaining.peek().is_some() {
+    let batch = take_batch(&mut remaining);
+    let count = batch.len();
+
+    api_import_messages(client, thread_id.clone(), offset as f64, batch).await?;
+    offset += count;
+  }
+
+  api_finish_import(client, thread_id.clone()).await?;
+
+  Ok(thread_id)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn message(bytes: usize) -> ImportMessageArgs {
+    ImportMessageArgs {
+      role: ImportRole::User,
+      text: "a".repeat(bytes),
+      created_at: None,
+      model: None,
+    }
+  }
+
+  #[test]
+  fn test_take_batch() {
+    let mut messages = (0..250).map(|_| message(10)).peekable();
+    let sizes = std::iter::from_fn(|| Some(take_batch(&mut messages).len()).filter(|len| *len > 0)).collect::<Vec<_>>();
+    assert_eq!(sizes, vec![100, 100, 50]);
+
+    let mut messages = [BATCH_BYTES / 2, BATCH_BYTES / 2, 1, BATCH_BYTES * 2, 1]
+      .into_iter()
+      .map(message)
+      .peekable();
+    let sizes = std::iter::from_fn(|| Some(take_batch(&mut messages).len()).filter(|len| *len > 0)).collect::<Vec<_>>();
+    assert_eq!(sizes, vec![2, 1, 1, 1]);
+  }
+}
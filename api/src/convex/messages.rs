@@ -1,11 +1,10 @@
-use std::collections::BTreeMap;
-
-use convex::Value;
 use serde::{Deserialize, Serialize};
 use specta::Type;
 
-use crate::convex::{ConvexClient, Result, convex_mutation, convex_query};
-use crate::convex_serde::to_map;
+use crate::convex::attachments::Attachment;
+use crate::convex::bindings::{convex_args, convex_functions};
+use crate::convex::threads::Thread;
+use crate::convex::{ConvexClient, Doc, Id, Result, Table};
 
 #[derive(Debug, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
@@ -13,6 +12,7 @@ pub enum MessageStatus {
   Pending,
   Complete,
   Cancelled,
+  Error,
 }
 
 #[derive(Debug, Deserialize)]
@@ -20,10 +20,10 @@ pub enum MessageStatus {
 #[serde(tag = "type")]
 pub enum MessagePart {
   Text { text: String },
-  Attachment { id: String },
+  Attachment { id: Id<Attachment> },
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub enum Role {
   User,
@@ -34,52 +34,45 @@ pub enum Role {
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Message {
-  #[serde(rename = "_id")]
-  pub id: String,
-  pub thread_id: String,
+  pub thread_id: Id<Thread>,
   pub status: Option<MessageStatus>,
   pub role: Role,
   pub parts: Vec<MessagePart>,
-}
 
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct MessageIdOnly {
-  #[serde(rename = "_id")]
-  pub _id: String,
-}
+  pub model: Option<String>,
+  pub served_model: Option<String>,
+  pub model_params: Option<ModelParams>,
+  pub reasoning: Option<String>,
+  /// json array of provider reasoning blocks
+  pub reasoning_details: Option<String>,
+  pub annotations: Option<Vec<Annotation>>,
 
-pub async fn get_by_id(client: &mut ConvexClient, id: String) -> Result<Option<Message>> {
-  const GET_BY_ID: &str = "messages:apiGetById";
+  pub prompt_token_count: Option<f64>,
+  pub token_count: Option<f64>,
+  pub duration_ms: Option<f64>,
+  pub tokens_per_second: Option<f64>,
+  pub time_to_first_token_ms: Option<f64>,
 
-  convex_query(
-    client,
-    GET_BY_ID,
-    BTreeMap::from([("id".to_string(), Value::String(id))]),
-  )
-  .await
+  /// instance generating the message
+  pub claimed_by: Option<String>,
+  /// unix ms, the claim lapses unless it is renewed before then
+  pub lease_expires_at: Option<f64>,
+  /// generations started for the message
+  pub attempts: Option<f64>,
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct MessagesResponse {
-  messages: Vec<Message>,
+impl Message {
+  /// true when nobody holds a lease on the message at `now_ms`
+  pub fn is_claimable(&self, now_ms: f64) -> bool {
+    self.claimed_by.is_none() || self.lease_expires_at.unwrap_or_default() <= now_ms
+  }
 }
 
-pub async fn get_by_thread_id(client: &mut ConvexClient, thread_id: String) -> Result<Vec<Message>> {
-  const GET_BY_THREAD_ID: &str = "messages:apiGetByThreadId";
-
-  // Use the correct parameter name that Convex expects (likely camelCase)
-  let response: MessagesResponse = convex_query(
-    client,
-    GET_BY_THREAD_ID,
-    BTreeMap::from([("threadId".to_string(), Value::String(thread_id))]),
-  )
-  .await?;
-
-  Ok(response.messages)
+impl Table for Message {
+  const NAME: &'static str = "messages";
 }
-#[derive(Serialize, Clone)]
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 #[serde(rename_all = "camelCase")]
 pub enum ReasoningEffort {
   Low,
@@ -87,68 +80,18 @@ pub enum ReasoningEffort {
   High,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ModelParams {
   pub reasoning_effort: Option<ReasoningEffort>,
   pub include_search: bool,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub reasoning_max_tokens: Option<f64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub exclude_reasoning: Option<bool>,
 }
 
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct CompleteMessageArgs {
-  pub message_id: String,
-  pub model: String,
-  pub model_params: Option<ModelParams>,
-  pub time_to_first_token_ms: f64,
-  pub prompt_token_count: f64,
-  pub token_count: f64,
-  pub duration_ms: f64,
-  pub tokens_per_second: f64,
-}
-
-pub async fn complete(client: &mut ConvexClient, args: &CompleteMessageArgs) -> Result<bool> {
-  const COMPLETE_MESSAGE: &str = "messages:apiComplete";
-
-  let result = convex_mutation::<Option<MessageIdOnly>>(client, COMPLETE_MESSAGE, to_map(args)?).await?;
-
-  Ok(result.is_some())
-}
-
-pub async fn append_text(client: &mut ConvexClient, message_id: String, text: String) -> Result<bool> {
-  const APPEND_TEXT: &str = "messages:apiAppendText";
-
-  let args = BTreeMap::from([
-    ("messageId".to_string(), Value::String(message_id)),
-    ("text".to_string(), Value::String(text)),
-  ]);
-
-  let result = convex_mutation::<Option<MessageIdOnly>>(client, APPEND_TEXT, args).await?;
-
-  Ok(result.is_some())
-}
-
-pub async fn append_reasoning(client: &mut ConvexClient, message_id: String, reasoning: String) -> Result<bool> {
-  const APPEND_REASONING: &str = "messages:apiAppendReasoning";
-
-  let args = BTreeMap::from([
-    ("messageId".to_string(), Value::String(message_id)),
-    ("reasoning".to_string(), Value::String(reasoning)),
-  ]);
-
-  let result = convex_mutation::<Option<MessageIdOnly>>(client, APPEND_REASONING, args).await?;
-
-  Ok(result.is_some())
-}
-
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct AnnotationArgs {
-  pub message_id: String,
-  pub annotations: Vec<Annotation>,
-}
-
-#[derive(Clone, Serialize, Type)]
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
 #[serde(rename_all = "camelCase")]
 #[specta(rename = "AnnotationResponse")]
 pub struct Annotation {
@@ -157,31 +100,118 @@ pub struct Annotation {
   pub content: String,
 }
 
-pub async fn append_annotations(client: &mut ConvexClient, args: AnnotationArgs) -> Result<bool> {
-  const APPEND_ANNOTATIONS: &str = "messages:apiAppendAnnotations";
-
-  let result = convex_mutation::<Option<MessageIdOnly>>(client, APPEND_ANNOTATIONS, to_map(&args)?).await?;
-
-  Ok(result.is_some())
+convex_args! {
+  pub struct CompleteMessageArgs {
+    pub message_id: Id<Message>,
+    /// instance holding the claim on the message, convex rejects the write from anyone else
+    pub claimed_by: String,
+    pub model: String,
+    pub model_params: Option<ModelParams>,
+    pub time_to_first_token_ms: f64,
+    pub prompt_token_count: f64,
+    pub token_count: f64,
+    pub duration_ms: f64,
+    pub tokens_per_second: f64,
+    /// model that actually answered, differs from `model` after a fallback
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub served_model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// part of `token_count`
+    pub reasoning_token_count: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning_details: Option<String>,
+    /// openrouter finish reason of the last request, `length` when the answer was cut off
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<String>,
+    /// follow-up requests appended after the answer was cut off
+    pub continuation_count: f64,
+  }
+}
+
+convex_args! {
+  #[derive(Debug)]
+  pub struct AppendArgs {
+    pub message_id: Id<Message>,
+    /// instance holding the claim on the message, convex rejects the write from anyone else
+    pub claimed_by: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<Vec<Annotation>>,
+  }
 }
 
-pub async fn cancel(client: &mut ConvexClient, message_id: String) -> Result<bool> {
-  const CANCEL_MESSAGE: &str = "messages:apiCancel";
-
-  let args = BTreeMap::from([("messageId".to_string(), Value::String(message_id))]);
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ClaimResult {
+  Claimed,
+  /// the message is no longer pending or another instance holds a lease on it
+  Taken,
+  /// the message ran out of attempts and was marked as failed
+  Failed,
+}
 
-  let result = convex_mutation::<Option<MessageIdOnly>>(client, CANCEL_MESSAGE, args).await?;
+/// mutations return the id of the message they changed, or null when it doesn't exist
+#[derive(Deserialize)]
+struct MessageIdOnly {
+  #[serde(rename = "_id")]
+  pub _id: Id<Message>,
+}
 
-  Ok(result.is_some())
+#[derive(Debug, Deserialize)]
+struct MessagesResponse {
+  messages: Vec<Doc<Message>>,
+}
+
+convex_functions! {
+  pub query get_by_id("messages:apiGetById", id: Id<Message>) -> Option<Doc<Message>>;
+  query api_get_by_thread_id("messages:apiGetByThreadId", thread_id: Id<Thread>) -> MessagesResponse;
+  pub query get_until(
+    "threads:apiGetMessagesUntil",
+    thread_id: Id<Thread>,
+    until_id: Id<Message>,
+  ) -> Option<Vec<Doc<Message>>>;
+  idempotent mutation api_complete("messages:apiComplete", args: &CompleteMessageArgs) -> Option<MessageIdOnly>;
+  mutation api_append("messages:apiAppend", args: &AppendArgs) -> Option<MessageIdOnly>;
+  idempotent mutation api_cancel("messages:apiCancel", message_id: Id<Message>) -> Option<MessageIdOnly>;
+  /// pending assistant messages, including the ones other instances are generating
+  pub subscription watch_pending("messages:apiListPending") -> Vec<Doc<Message>>;
+  pub mutation claim(
+    "messages:apiClaim",
+    message_id: Id<Message>,
+    claimed_by: String,
+    lease_ms: f64,
+    max_attempts: f64,
+  ) -> ClaimResult;
+  /// returns false when another instance took the message over
+  pub idempotent mutation renew_lease(
+    "messages:apiRenewLease",
+    message_id: Id<Message>,
+    claimed_by: String,
+    lease_ms: f64,
+  ) -> bool;
+  /// gives up the claim after a failed generation so it can be retried
+  pub idempotent mutation release("messages:apiRelease", message_id: Id<Message>, claimed_by: String) -> bool;
+  /// marks a message that can't be generated as failed, returns false when another instance holds it
+  pub idempotent mutation fail("messages:apiFail", message_id: Id<Message>, claimed_by: String) -> bool;
+}
+
+pub async fn get_by_thread_id(client: &mut ConvexClient, thread_id: Id<Thread>) -> Result<Vec<Doc<Message>>> {
+  Ok(api_get_by_thread_id(client, thread_id).await?.messages)
 }
 
-pub async fn get_until(client: &mut ConvexClient, thread_id: String, until_id: String) -> Result<Option<Vec<Message>>> {
-  const GET_UNTIL: &str = "threads:apiGetMessagesUntil";
+pub async fn complete(client: &mut ConvexClient, args: &CompleteMessageArgs) -> Result<bool> {
+  Ok(api_complete(client, args).await?.is_some())
+}
 
-  let args = BTreeMap::from([
-    ("threadId".to_string(), Value::String(thread_id)),
-    ("untilId".to_string(), Value::String(until_id)),
-  ]);
+/// appends text, reasoning and annotations in a single mutation
+pub async fn append(client: &mut ConvexClient, args: &AppendArgs) -> Result<bool> {
+  Ok(api_append(client, args).await?.is_some())
+}
 
-  convex_query(client, GET_UNTIL, args).await
+pub async fn cancel(client: &mut ConvexClient, message_id: Id<Message>) -> Result<bool> {
+  Ok(api_cancel(client, message_id).await?.is_some())
 }
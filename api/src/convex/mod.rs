@@ -1,22 +1,40 @@
 use std::collections::BTreeMap;
+use std::time::Instant;
 
 use anyhow::Context;
 use convex::{FunctionResult, Value};
+use futures::{Stream, StreamExt};
+use reqwest::Url;
 use secrecy::{ExposeSecret, SecretString};
 use serde::de::DeserializeOwned;
 
 use crate::config::ConvexConfig;
-use crate::convex_serde;
 use crate::prelude::*;
+use crate::retry::{self, RetryClass, RetryPolicy, Retryable};
+use crate::{convex_serde, metrics};
 
+pub mod api_tokens;
 pub mod attachments;
+pub mod bindings;
+pub mod health;
+pub mod id;
+pub mod imports;
 pub mod messages;
+pub mod openrouter_keys;
+pub mod sessions;
 pub mod threads;
+pub mod usage;
+pub mod writer;
+
+pub use id::{Doc, Id, Table};
 
 #[derive(Clone)]
 pub struct ConvexClient {
   pub client: convex::ConvexClient,
   pub api_key: SecretString,
+  /// deployment url for the http api, which takes the tokens of signed in users
+  pub url: Url,
+  pub http: reqwest::Client,
 }
 
 pub async fn create_convex_client(config: &ConvexConfig) -> anyhow::Result<ConvexClient> {
@@ -27,6 +45,8 @@ pub async fn create_convex_client(config: &ConvexConfig) -> anyhow::Result<Conve
   Ok(ConvexClient {
     client,
     api_key: config.api_key.clone(),
+    url: config.url.clone(),
+    http: reqwest::Client::new(),
   })
 }
 
@@ -45,6 +65,18 @@ pub enum ConvexError {
   Serialization(#[from] convex_serde::SerError),
 }
 
+impl Retryable for ConvexError {
+  fn classify(&self) -> RetryClass {
+    match self {
+      // the client lost its connection to the backend
+      ConvexError::Unexpected(_) => RetryClass::Transient,
+      // errors thrown by the function itself, e.g. argument validation
+      ConvexError::Query(_) | ConvexError::Message(_) => RetryClass::Fatal,
+      ConvexError::Deserialization(_) | ConvexError::Serialization(_) => RetryClass::Fatal,
+    }
+  }
+}
+
 type Result<T> = std::result::Result<T, ConvexError>;
 
 fn parse_result(result: FunctionResult) -> Result<Value> {
@@ -61,22 +93,81 @@ fn auth_args(api_key: &SecretString, args: &mut BTreeMap<String, Value>) {
   args.insert(API_KEY.into(), Value::String(api_key.expose_secret().to_string()));
 }
 
+#[tracing::instrument("convex query", skip_all, fields(otel.kind = "client", function = query))]
 pub async fn convex_query<T: DeserializeOwned>(
-  ConvexClient { client, api_key }: &mut ConvexClient,
+  ConvexClient { client, api_key, .. }: &mut ConvexClient,
   query: &'static str,
   mut args: BTreeMap<String, Value>,
 ) -> Result<T> {
   auth_args(api_key, &mut args);
-  let value = parse_result(client.query(query, args).await?)?;
+  let value = retry::retry(&RetryPolicy::default(), query, || {
+    let mut client = client.clone();
+    let args = args.clone();
+    async move {
+      let started = Instant::now();
+      let result = client
+        .query(query, args)
+        .await
+        .map_err(ConvexError::from)
+        .and_then(parse_result);
+      metrics::observe_convex(query, result.is_ok(), started.elapsed());
+      result
+    }
+  })
+  .await?;
   Ok(convex_serde::from_value(value)?)
 }
 
+/// runs a mutation, which is only retried when `idempotent`: after a lost connection it can't be told whether the
+/// first attempt was applied, and appending text or recording usage twice would duplicate it
+#[tracing::instrument("convex mutation", skip_all, fields(otel.kind = "client", function = mutation))]
 pub async fn convex_mutation<T: DeserializeOwned>(
-  ConvexClient { client, api_key }: &mut ConvexClient,
+  ConvexClient { client, api_key, .. }: &mut ConvexClient,
   mutation: &'static str,
   mut args: BTreeMap<String, Value>,
+  idempotent: bool,
 ) -> Result<T> {
   auth_args(api_key, &mut args);
-  let value = parse_result(client.mutation(mutation, args).await?)?;
+  let policy = if idempotent {
+    RetryPolicy::default()
+  } else {
+    RetryPolicy {
+      max_attempts: 1,
+      ..RetryPolicy::default()
+    }
+  };
+  let value = retry::retry(&policy, mutation, || {
+    let mut client = client.clone();
+    let args = args.clone();
+    async move {
+      let started = Instant::now();
+      let result = client
+        .mutation(mutation, args)
+        .await
+        .map_err(ConvexError::from)
+        .and_then(parse_result);
+      metrics::observe_convex(mutation, result.is_ok(), started.elapsed());
+      result
+    }
+  })
+  .await?;
   Ok(convex_serde::from_value(value)?)
 }
+
+/// watches a query, the stream yields its result again whenever it changes and ends when the connection is lost
+#[tracing::instrument("convex subscription", skip_all, fields(otel.kind = "client", function = query))]
+pub async fn convex_subscribe<T: DeserializeOwned>(
+  ConvexClient { client, api_key, .. }: &mut ConvexClient,
+  query: &'static str,
+  mut args: BTreeMap<String, Value>,
+) -> Result<impl Stream<Item = Result<T>>> {
+  auth_args(api_key, &mut args);
+  let subscription = retry::retry(&RetryPolicy::default(), query, || {
+    let mut client = client.clone();
+    let args = args.clone();
+    async move { client.subscribe(query, args).await.map_err(ConvexError::from) }
+  })
+  .await?;
+
+  Ok(subscription.map(|result| Ok(convex_serde::from_value(parse_result(result)?)?)))
+}
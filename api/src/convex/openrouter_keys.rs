@@ -0,0 +1,33 @@
+use serde::Deserialize;
+use serde::de::IgnoredAny;
+
+use crate::convex::bindings::convex_functions;
+use crate::convex::{ConvexClient, Doc, Result, Table};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredKey {
+  pub user_id: String,
+  /// see [`crate::keys::KeyCipher`]
+  pub encrypted_key: String,
+  pub created_at: f64,
+  pub updated_at: f64,
+}
+
+impl Table for StoredKey {
+  const NAME: &'static str = "openrouterKeys";
+}
+
+convex_functions! {
+  pub query get("openrouterKeys:apiGet", user_id: String) -> Option<Doc<StoredKey>>;
+  idempotent mutation api_set("openrouterKeys:apiSet", user_id: String, encrypted_key: String) -> IgnoredAny;
+  /// returns false when the user had no stored key
+  pub idempotent mutation remove("openrouterKeys:apiRemove", user_id: String) -> bool;
+}
+
+/// replaces the stored key of the user
+pub async fn set(client: &mut ConvexClient, user_id: String, encrypted_key: String) -> Result<()> {
+  api_set(client, user_id, encrypted_key).await?;
+
+  Ok(())
+}
@@ -0,0 +1,57 @@
+use std::time::Instant;
+
+use anyhow::Context;
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+use crate::convex::{ConvexClient, ConvexError, Result};
+use crate::metrics;
+
+const VIEWER_QUERY: &str = "users:viewer";
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+enum QueryResponse {
+  Success {
+    value: Option<String>,
+  },
+  #[serde(rename_all = "camelCase")]
+  Error {
+    error_message: String,
+  },
+}
+
+/// the user id convex stores on threads for the holder of a session token, the same token the frontend gives convex.
+/// `None` when convex does not accept the token
+#[tracing::instrument("convex session", skip_all, fields(otel.kind = "client"))]
+pub async fn viewer(client: &ConvexClient, token: &str) -> Result<Option<String>> {
+  let url = client.url.join("api/query").context("invalid convex url")?;
+
+  let started = Instant::now();
+  let response = client
+    .http
+    .post(url)
+    .bearer_auth(token)
+    .json(&serde_json::json!({ "path": VIEWER_QUERY, "args": {}, "format": "json" }))
+    .send()
+    .await
+    .context("failed to reach convex")?;
+  metrics::observe_convex(VIEWER_QUERY, response.status().is_success(), started.elapsed());
+
+  // expired or forged tokens are rejected before the query runs
+  if matches!(response.status(), StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+    return Ok(None);
+  }
+
+  let response = response
+    .error_for_status()
+    .context("convex rejected the session query")?
+    .json::<QueryResponse>()
+    .await
+    .context("invalid convex response")?;
+
+  match response {
+    QueryResponse::Success { value } => Ok(value),
+    QueryResponse::Error { error_message } => Err(ConvexError::Message(error_message)),
+  }
+}
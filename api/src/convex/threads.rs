@@ -1,45 +1,34 @@
-use std::collections::BTreeMap;
-
-use convex::Value;
 use serde::Deserialize;
 
-use crate::convex::{ConvexClient, Result, convex_mutation, convex_query};
+use crate::convex::bindings::convex_functions;
+use crate::convex::{ConvexClient, Doc, Id, Result, Table};
 
-#[derive(Deserialize)]
+#[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Thread {
-  #[serde(rename = "_id")]
-  pub id: String,
   pub title: Option<String>,
+  pub user_id: String,
+}
+
+impl Table for Thread {
+  const NAME: &'static str = "threads";
 }
 
 #[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
 struct ThreadIdOnly {
   #[serde(rename = "_id")]
-  pub _id: String,
+  pub _id: Id<Thread>,
 }
 
-pub async fn get_by_id(client: &mut ConvexClient, id: String) -> Result<Option<Thread>> {
-  const GET_BY_ID: &str = "threads:apiGetById";
-
-  convex_query(
-    client,
-    GET_BY_ID,
-    BTreeMap::from([("id".to_string(), Value::String(id))]),
-  )
-  .await
+convex_functions! {
+  pub query get_by_id("threads:apiGetById", id: Id<Thread>) -> Option<Doc<Thread>>;
+  idempotent mutation api_set_title(
+    "threads:apiSetTitle",
+    thread_id: Id<Thread>,
+    title: String,
+  ) -> Option<ThreadIdOnly>;
 }
 
-pub async fn set_title(client: &mut ConvexClient, thread_id: String, title: String) -> Result<bool> {
-  const SET_TITLE: &str = "threads:apiSetTitle";
-
-  let args = BTreeMap::from([
-    ("threadId".to_string(), Value::String(thread_id)),
-    ("title".to_string(), Value::String(title)),
-  ]);
-
-  let result = convex_mutation::<Option<ThreadIdOnly>>(client, SET_TITLE, args).await?;
-
-  Ok(result.is_some())
+pub async fn set_title(client: &mut ConvexClient, thread_id: Id<Thread>, title: String) -> Result<bool> {
+  Ok(api_set_title(client, thread_id, title).await?.is_some())
 }
@@ -0,0 +1,31 @@
+use serde::de::IgnoredAny;
+
+use crate::convex::api_tokens::ApiToken;
+use crate::convex::bindings::{convex_args, convex_functions};
+use crate::convex::{ConvexClient, Id, Result};
+
+convex_args! {
+  #[derive(Debug)]
+  pub struct RecordUsageArgs {
+    pub user_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_id: Option<Id<ApiToken>>,
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub served_model: Option<String>,
+    pub prompt_tokens: f64,
+    pub completion_tokens: f64,
+  }
+}
+
+convex_functions! {
+  mutation api_record("usage:apiRecord", args: &RecordUsageArgs) -> IgnoredAny;
+  /// prompt and completion tokens recorded for the user since `since` (unix ms)
+  pub query total_since("usage:apiTotalSince", user_id: String, since: f64) -> f64;
+}
+
+pub async fn record(client: &mut ConvexClient, args: &RecordUsageArgs) -> Result<()> {
+  api_record(client, args).await?;
+
+  Ok(())
+}
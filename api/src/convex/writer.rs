@@ -0,0 +1,329 @@
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::Instrument;
+
+use crate::convex::messages::{Annotation, AppendArgs, Message};
+use crate::convex::{ConvexClient, ConvexError, Id, messages};
+use crate::prelude::*;
+
+/// controls how often streamed output is written to convex
+#[derive(Debug, Clone)]
+pub struct FlushPolicy {
+  /// shortest time between two writes
+  pub min_interval: Duration,
+  /// longest time between two writes, even if convex is slow
+  pub max_interval: Duration,
+  /// buffered bytes that trigger a write before the interval has passed
+  pub max_bytes: usize,
+  /// buffered bytes at which pushing waits for the next write, so a stalled convex can't grow the buffer forever
+  pub max_buffered: usize,
+}
+
+impl Default for FlushPolicy {
+  fn default() -> Self {
+    Self {
+      min_interval: Duration::from_millis(150),
+      max_interval: Duration::from_secs(2),
+      max_bytes: 4096,
+      max_buffered: 1024 * 1024,
+    }
+  }
+}
+
+impl FlushPolicy {
+  /// waits roughly twice as long as the last write took, so a slow convex gets fewer, larger writes
+  fn next_interval(&self, last_write: Duration) -> Duration {
+    (last_write * 2).clamp(self.min_interval, self.max_interval)
+  }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WriterError {
+  #[error("convex error: {0}")]
+  Convex(#[from] ConvexError),
+  #[error("message no longer exists")]
+  MessageNotFound,
+  /// returned to pushes after a write failed, the failure itself is returned by the writer task
+  #[error("message writer stopped after a failed write")]
+  Stopped,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WriterStats {
+  pub writes: u32,
+  pub bytes: usize,
+}
+
+#[derive(Default)]
+struct PendingWrite {
+  text: String,
+  reasoning: String,
+  annotations: Vec<Annotation>,
+  closed: bool,
+  failed: bool,
+}
+
+impl PendingWrite {
+  fn len(&self) -> usize {
+    self.text.len() + self.reasoning.len()
+  }
+
+  fn is_empty(&self) -> bool {
+    self.text.is_empty() && self.reasoning.is_empty() && self.annotations.is_empty()
+  }
+
+  fn take(&mut self, message_id: &Id<Message>, claimed_by: &str) -> AppendArgs {
+    let text = mem::take(&mut self.text);
+    let reasoning = mem::take(&mut self.reasoning);
+    let annotations = mem::take(&mut self.annotations);
+
+    AppendArgs {
+      message_id: message_id.clone(),
+      claimed_by: claimed_by.to_string(),
+      text: (!text.is_empty()).then_some(text),
+      reasoning: (!reasoning.is_empty()).then_some(reasoning),
+      annotations: (!annotations.is_empty()).then_some(annotations),
+    }
+  }
+}
+
+/// Buffers streamed output for a message and persists it from a background task.
+///
+/// Pushing only waits on convex once `max_buffered` bytes are waiting, so token forwarding isn't stalled by a slow
+/// write. While a write is in flight new output keeps accumulating and is sent as one merged write afterwards, so there
+/// is at most one outstanding mutation per message. Once a write fails every push returns [`WriterError::Stopped`].
+#[derive(Clone)]
+pub struct MessageWriter {
+  pending: Arc<Mutex<PendingWrite>>,
+  notify: Arc<Notify>,
+  /// woken when the writer took the buffer or stopped
+  drained: Arc<Notify>,
+  max_buffered: usize,
+}
+
+impl MessageWriter {
+  pub fn spawn(
+    client: ConvexClient,
+    message_id: Id<Message>,
+    claimed_by: String,
+    policy: FlushPolicy,
+  ) -> (Self, JoinHandle<Result<WriterStats, WriterError>>) {
+    let writer = Self::new(policy.max_buffered);
+
+    let handle = tokio::spawn(run_writer(writer.clone(), client, message_id, claimed_by, policy).in_current_span());
+
+    (writer, handle)
+  }
+
+  fn new(max_buffered: usize) -> Self {
+    Self {
+      pending: Arc::new(Mutex::new(PendingWrite::default())),
+      notify: Arc::new(Notify::new()),
+      drained: Arc::new(Notify::new()),
+      max_buffered,
+    }
+  }
+
+  /// adds to the buffer once it has room, fails when the writer stopped
+  async fn push(&self, f: impl FnOnce(&mut PendingWrite)) -> Result<(), WriterError> {
+    loop {
+      // registered before looking at the buffer so a write finishing in between isn't missed
+      let drained = self.drained.notified();
+
+      {
+        let mut pending = self.pending.lock().expect("writer lock poisoned");
+
+        if pending.failed {
+          return Err(WriterError::Stopped);
+        }
+
+        if pending.len() < self.max_buffered {
+          f(&mut pending);
+          break;
+        }
+      }
+
+      self.notify.notify_one();
+      drained.await;
+    }
+
+    self.notify.notify_one();
+    Ok(())
+  }
+
+  pub async fn text(&self, text: &str) -> Result<(), WriterError> {
+    self.push(|pending| pending.text.push_str(text)).await
+  }
+
+  pub async fn reasoning(&self, reasoning: &str) -> Result<(), WriterError> {
+    self.push(|pending| pending.reasoning.push_str(reasoning)).await
+  }
+
+  pub async fn annotations(&self, annotations: Vec<Annotation>) -> Result<(), WriterError> {
+    self.push(|pending| pending.annotations.extend(annotations)).await
+  }
+
+  /// flushes everything that is still buffered and stops the background task
+  pub fn close(&self) {
+    self.pending.lock().expect("writer lock poisoned").closed = true;
+    self.notify.notify_one();
+  }
+
+  /// rejects further pushes and wakes the ones waiting for room
+  fn stop(&self) {
+    self.pending.lock().expect("writer lock poisoned").failed = true;
+    self.drained.notify_waiters();
+  }
+}
+
+async fn run_writer(
+  writer: MessageWriter,
+  client: ConvexClient,
+  message_id: Id<Message>,
+  claimed_by: String,
+  policy: FlushPolicy,
+) -> Result<WriterStats, WriterError> {
+  let result = write_pending(&writer, client, &message_id, &claimed_by, &policy).await;
+
+  if let Err(err) = &result {
+    error!("Failed to write message {message_id}: {:?}", err);
+    writer.stop();
+  }
+
+  result
+}
+
+async fn write_pending(
+  writer: &MessageWriter,
+  mut client: ConvexClient,
+  message_id: &Id<Message>,
+  claimed_by: &str,
+  policy: &FlushPolicy,
+) -> Result<WriterStats, WriterError> {
+  let mut stats = WriterStats::default();
+  let mut interval = policy.min_interval;
+  // when the writer first saw output waiting, the next write is due an interval after that
+  let mut buffered_since = None;
+
+  loop {
+    match buffered_since {
+      // an empty buffer has no deadline, sleeping until a past one would return right away on every pass
+      None => writer.notify.notified().await,
+      Some(since) => tokio::select! {
+        _ = writer.notify.notified() => {},
+        _ = tokio::time::sleep_until(since + interval) => {},
+      },
+    }
+
+    let (args, closed) = {
+      let mut pending = writer.pending.lock().expect("writer lock poisoned");
+
+      if pending.is_empty() {
+        buffered_since = None;
+        (None, pending.closed)
+      } else {
+        let since = *buffered_since.get_or_insert_with(Instant::now);
+        let due = pending.closed || pending.len() >= policy.max_bytes || Instant::now() >= since + interval;
+
+        if due {
+          buffered_since = None;
+          (Some(pending.take(message_id, claimed_by)), pending.closed)
+        } else {
+          (None, pending.closed)
+        }
+      }
+    };
+
+    if let Some(args) = args {
+      writer.drained.notify_waiters();
+      let started = Instant::now();
+
+      if !messages::append(&mut client, &args).await? {
+        return Err(WriterError::MessageNotFound);
+      }
+
+      let elapsed = started.elapsed();
+      interval = policy.next_interval(elapsed);
+
+      stats.writes += 1;
+      stats.bytes +=
+        args.text.map(|t| t.len()).unwrap_or_default() + args.reasoning.map(|r| r.len()).unwrap_or_default();
+
+      debug!("Wrote message {message_id} in {elapsed:?}, next write in {interval:?}");
+    }
+
+    // everything pushed before close has been written once the buffer is empty
+    if closed && writer.pending.lock().expect("writer lock poisoned").is_empty() {
+      break;
+    }
+  }
+
+  Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_next_interval_is_clamped() {
+    let policy = FlushPolicy::default();
+
+    assert_eq!(policy.next_interval(Duration::from_millis(10)), policy.min_interval);
+    assert_eq!(
+      policy.next_interval(Duration::from_millis(400)),
+      Duration::from_millis(800)
+    );
+    assert_eq!(policy.next_interval(Duration::from_secs(30)), policy.max_interval);
+  }
+
+  #[test]
+  fn test_take_merges_pending_output() {
+    let mut pending = PendingWrite::default();
+    pending.text.push_str("hello ");
+    pending.text.push_str("world");
+    pending.reasoning.push_str("thinking");
+
+    let args = pending.take(&Id::new("message1"), "instance1");
+
+    assert_eq!(args.text.as_deref(), Some("hello world"));
+    assert_eq!(args.reasoning.as_deref(), Some("thinking"));
+    assert!(args.annotations.is_none());
+    assert!(pending.is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_push_fails_after_writer_stopped() {
+    let writer = MessageWriter::new(1024);
+
+    writer.text("hello").await.unwrap();
+    writer.stop();
+
+    assert!(matches!(writer.text("world").await, Err(WriterError::Stopped)));
+  }
+
+  #[tokio::test]
+  async fn test_push_waits_while_buffer_is_full() {
+    let writer = MessageWriter::new(4);
+    writer.text("full").await.unwrap();
+
+    let waiting = tokio::spawn({
+      let writer = writer.clone();
+      async move { writer.text("more").await }
+    });
+
+    tokio::task::yield_now().await;
+    assert!(!waiting.is_finished());
+
+    writer.pending.lock().unwrap().take(&Id::new("message1"), "instance1");
+    writer.drained.notify_waiters();
+
+    waiting.await.unwrap().unwrap();
+    assert_eq!(writer.pending.lock().unwrap().text, "more");
+  }
+}
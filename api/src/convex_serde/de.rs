@@ -3,11 +3,14 @@ use std::collections::btree_map::Iter;
 use std::fmt::Display;
 
 use convex::Value;
+use serde::de::value::{BorrowedStrDeserializer, SeqDeserializer};
 use serde::de::{
   Deserialize, DeserializeOwned, DeserializeSeed, Deserializer, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
   VariantAccess, Visitor,
 };
 
+use super::{Segment, prepend_path};
+
 pub fn from_value_ref<'de, T: Deserialize<'de>>(value: &'de Value) -> Result<T, Error> {
   let mut deserializer = ValueDeserializer::from_value(value);
   T::deserialize(&mut deserializer)
@@ -40,6 +43,31 @@ pub enum Error {
   ExpectedEnum(Value),
   #[error("{0}")]
   Custom(String),
+  #[error("{path}: {error}")]
+  At { path: String, error: Box<Error> },
+}
+
+impl Error {
+  fn at(self, segment: Segment) -> Self {
+    match self {
+      Error::At { path, error } => Error::At {
+        path: prepend_path(segment, &path),
+        error,
+      },
+      error => Error::At {
+        path: prepend_path(segment, ""),
+        error: Box::new(error),
+      },
+    }
+  }
+
+  /// where in the value deserialization failed, e.g. `messages[3].parts[0].type`
+  pub fn path(&self) -> Option<&str> {
+    match self {
+      Error::At { path, .. } => Some(path),
+      _ => None,
+    }
+  }
 }
 
 impl serde::de::Error for Error {
@@ -61,10 +89,10 @@ impl<'de> ValueDeserializer<'de> {
 impl<'de: 'a, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
   type Error = Error;
 
+  // the visitors of narrower types check the range themselves
   serde::forward_to_deserialize_any! {
-    i8 i16 i32 u8 u16 u32 u64 f32 char
-    tuple tuple_struct ignored_any byte_buf
-    map
+    i8 i16 i32 i128 u8 u16 u32 u64 u128 f32 char
+    tuple tuple_struct
   }
 
   fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
@@ -94,9 +122,11 @@ impl<'de: 'a, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     }
   }
 
+  /// NaN and infinities are valid convex numbers and passed on as is
   fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
     match self.input {
       Value::Float64(f) => visitor.visit_f64(*f),
+      Value::Int64(i) => visitor.visit_i64(*i),
       value => Err(Self::Error::ExpectedFloat(value.clone())),
     }
   }
@@ -115,13 +145,19 @@ impl<'de: 'a, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     }
   }
 
+  /// arrays of numbers are accepted too, that is how byte slices without `serde_bytes` are serialized
   fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
     match self.input {
       Value::Bytes(b) => visitor.visit_borrowed_bytes(b),
+      Value::Array(values) => visitor.visit_seq(SeqValues::new(values)),
       value => Err(Self::Error::ExpectedBytes(value.clone())),
     }
   }
 
+  fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+    self.deserialize_bytes(visitor)
+  }
+
   fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
     match self.input {
       Value::Null => visitor.visit_none(),
@@ -129,13 +165,22 @@ impl<'de: 'a, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     }
   }
 
+  /// bytes can be read as a sequence of numbers, e.g. into a `Vec<u8>`
   fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
     match self.input {
       Value::Array(values) => visitor.visit_seq(SeqValues::new(values)),
+      Value::Bytes(bytes) => visitor.visit_seq(SeqDeserializer::new(bytes.iter().copied())),
       value => Err(Self::Error::ExpectedSeq(value.clone())),
     }
   }
 
+  fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+    match self.input {
+      Value::Object(map) => ObjectDeserializer::new(map).deserialize_any(visitor),
+      value => Err(Self::Error::ExpectedObject(value.clone())),
+    }
+  }
+
   fn deserialize_newtype_struct<V: Visitor<'de>>(
     self,
     _name: &'static str,
@@ -150,7 +195,7 @@ impl<'de: 'a, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     _fields: &'static [&'static str],
     visitor: V,
   ) -> Result<V::Value, Self::Error> {
-    self.deserialize_any(visitor)
+    self.deserialize_map(visitor)
   }
 
   fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
@@ -161,6 +206,7 @@ impl<'de: 'a, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     visitor.visit_unit()
   }
 
+  /// unit variants are strings, the others objects with the variant as their only key
   fn deserialize_enum<V: Visitor<'de>>(
     self,
     _name: &'static str,
@@ -168,8 +214,11 @@ impl<'de: 'a, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     visitor: V,
   ) -> Result<V::Value, Self::Error> {
     match self.input {
-      Value::String(s) => visitor.visit_enum(s.clone().into_deserializer()),
-      Value::Object(_) => visitor.visit_enum(EnumValue::new(self)),
+      Value::String(s) => visitor.visit_enum(s.as_str().into_deserializer()),
+      Value::Object(map) => match map.first_key_value() {
+        Some((variant, value)) if map.len() == 1 => visitor.visit_enum(EnumValue::new(variant, value)),
+        _ => Err(Self::Error::ExpectedEnum(self.input.clone())),
+      },
       value => Err(Self::Error::ExpectedEnum(value.clone())),
     }
   }
@@ -177,40 +226,55 @@ impl<'de: 'a, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
   fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
     self.deserialize_str(visitor)
   }
+
+  fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+    visitor.visit_unit()
+  }
 }
 
-pub struct EnumValue<'a, 'de: 'a> {
-  de: &'a mut ValueDeserializer<'de>,
+pub struct EnumValue<'de> {
+  variant: &'de str,
+  value: &'de Value,
 }
 
-impl<'de: 'a, 'a> EnumValue<'a, 'de> {
-  pub fn new(de: &'a mut ValueDeserializer<'de>) -> Self {
-    Self { de }
+impl<'de> EnumValue<'de> {
+  pub fn new(variant: &'de str, value: &'de Value) -> Self {
+    Self { variant, value }
+  }
+
+  fn deserializer(&self) -> ValueDeserializer<'de> {
+    ValueDeserializer::from_value(self.value)
   }
 }
 
-impl<'de, 'a> EnumAccess<'de> for EnumValue<'a, 'de> {
+impl<'de> EnumAccess<'de> for EnumValue<'de> {
   type Error = Error;
   type Variant = Self;
 
   fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error> {
-    Ok((seed.deserialize(&mut *self.de)?, self))
+    let variant = seed.deserialize(BorrowedStrDeserializer::<Error>::new(self.variant))?;
+    Ok((variant, self))
   }
 }
 
-impl<'de, 'a> VariantAccess<'de> for EnumValue<'a, 'de> {
+impl<'de> VariantAccess<'de> for EnumValue<'de> {
   type Error = Error;
 
   fn unit_variant(self) -> Result<(), Self::Error> {
-    Err(Error::ExpectedString(self.de.input.clone()))
+    match self.value {
+      Value::Null => Ok(()),
+      value => Err(Error::ExpectedString(value.clone())),
+    }
   }
 
   fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Self::Error> {
-    seed.deserialize(self.de)
+    seed
+      .deserialize(&mut self.deserializer())
+      .map_err(|err| err.at(Segment::Key(self.variant)))
   }
 
   fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error> {
-    Deserializer::deserialize_seq(self.de, visitor)
+    Deserializer::deserialize_seq(&mut self.deserializer(), visitor).map_err(|err| err.at(Segment::Key(self.variant)))
   }
 
   fn struct_variant<V: Visitor<'de>>(
@@ -218,7 +282,7 @@ impl<'de, 'a> VariantAccess<'de> for EnumValue<'a, 'de> {
     _fields: &'static [&'static str],
     visitor: V,
   ) -> Result<V::Value, Self::Error> {
-    Deserializer::deserialize_map(self.de, visitor)
+    Deserializer::deserialize_map(&mut self.deserializer(), visitor).map_err(|err| err.at(Segment::Key(self.variant)))
   }
 }
 
@@ -238,10 +302,13 @@ impl<'de> SeqAccess<'de> for SeqValues<'de> {
 
   fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error> {
     if self.index < self.values.len() {
-      let value = &self.values[self.index];
+      let index = self.index;
       self.index += 1;
-      let mut deserializer = ValueDeserializer::from_value(value);
-      seed.deserialize(&mut deserializer).map(Some)
+      let mut deserializer = ValueDeserializer::from_value(&self.values[index]);
+      seed
+        .deserialize(&mut deserializer)
+        .map(Some)
+        .map_err(|err| err.at(Segment::Index(index)))
     } else {
       Ok(None)
     }
@@ -266,17 +333,17 @@ impl<'de> Deserializer<'de> for &mut KeyDeserializer<'de> {
   type Error = Error;
 
   serde::forward_to_deserialize_any! {
-    bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char
+    bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char
     bytes byte_buf option unit unit_struct newtype_struct
     seq tuple tuple_struct map struct enum ignored_any
   }
 
   fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
-    visitor.visit_str(self.input)
+    visitor.visit_borrowed_str(self.input)
   }
 
   fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
-    visitor.visit_str(self.input)
+    visitor.visit_borrowed_str(self.input)
   }
 
   fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
@@ -284,7 +351,7 @@ impl<'de> Deserializer<'de> for &mut KeyDeserializer<'de> {
   }
 
   fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
-    visitor.visit_str(self.input)
+    visitor.visit_borrowed_str(self.input)
   }
 }
 
@@ -302,7 +369,7 @@ impl<'de> Deserializer<'de> for ObjectDeserializer<'de> {
   type Error = Error;
 
   serde::forward_to_deserialize_any! {
-    bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char
+    bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char
     bytes byte_buf option unit unit_struct newtype_struct
     seq tuple tuple_struct map struct enum ignored_any
     str string identifier
@@ -315,13 +382,13 @@ impl<'de> Deserializer<'de> for ObjectDeserializer<'de> {
 
 struct ObjectMapAccess<'de> {
   iter: Iter<'de, String, Value>,
-  value: Option<&'de Value>,
+  entry: Option<(&'de str, &'de Value)>,
 }
 
 impl<'de> ObjectMapAccess<'de> {
   fn new(deserializer: ObjectDeserializer<'de>) -> Self {
     let iter = deserializer.input.iter();
-    Self { iter, value: None }
+    Self { iter, entry: None }
   }
 }
 
@@ -332,7 +399,7 @@ impl<'de> MapAccess<'de> for ObjectMapAccess<'de> {
     if let Some((k, v)) = self.iter.next() {
       let mut deserializer = KeyDeserializer::new(k);
       let ret = seed.deserialize(&mut deserializer);
-      self.value = Some(v);
+      self.entry = Some((k, v));
       ret.map(Some)
     } else {
       Ok(None)
@@ -340,13 +407,19 @@ impl<'de> MapAccess<'de> for ObjectMapAccess<'de> {
   }
 
   fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Self::Error> {
-    if let Some(value) = self.value.take() {
+    if let Some((key, value)) = self.entry.take() {
       let mut deserializer = ValueDeserializer::from_value(value);
-      seed.deserialize(&mut deserializer)
+      seed
+        .deserialize(&mut deserializer)
+        .map_err(|err| err.at(Segment::Key(key)))
     } else {
       Err(Self::Error::ExpectedValue)
     }
   }
+
+  fn size_hint(&self) -> Option<usize> {
+    Some(self.iter.len())
+  }
 }
 
 #[cfg(test)]
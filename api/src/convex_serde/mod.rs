@@ -3,3 +3,446 @@ mod ser;
 
 pub use de::{Error as DeError, from_value, from_value_ref};
 pub use ser::{Error as SerError, to_map, to_value};
+
+/// one step into a value, errors collect them on the way out to point at the field that failed
+#[derive(Debug, Clone, Copy)]
+enum Segment<'a> {
+  Key(&'a str),
+  Index(usize),
+}
+
+/// `messages` and `[3].parts` become `messages[3].parts`
+fn prepend_path(segment: Segment, path: &str) -> String {
+  let segment = match segment {
+    Segment::Key(key) => key.to_string(),
+    Segment::Index(index) => format!("[{index}]"),
+  };
+
+  if path.is_empty() || path.starts_with('[') {
+    format!("{segment}{path}")
+  } else {
+    format!("{segment}.{path}")
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::BTreeMap;
+
+  use convex::Value;
+  use proptest::prelude::*;
+  use serde::de::{MapAccess, SeqAccess, Visitor};
+  use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+  use super::*;
+
+  /// every convex value passed through the serde data model and back, without knowing its shape
+  #[derive(Debug)]
+  enum Any {
+    Null,
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Bytes(Vec<u8>),
+    Array(Vec<Any>),
+    Object(BTreeMap<String, Any>),
+  }
+
+  impl Serialize for Any {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+      match self {
+        Any::Null => serializer.serialize_unit(),
+        Any::Int(value) => serializer.serialize_i64(*value),
+        Any::Float(value) => serializer.serialize_f64(*value),
+        Any::Bool(value) => serializer.serialize_bool(*value),
+        Any::String(value) => serializer.serialize_str(value),
+        Any::Bytes(value) => serializer.serialize_bytes(value),
+        Any::Array(values) => values.serialize(serializer),
+        Any::Object(map) => map.serialize(serializer),
+      }
+    }
+  }
+
+  impl<'de> Deserialize<'de> for Any {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+      struct AnyVisitor;
+
+      impl<'de> Visitor<'de> for AnyVisitor {
+        type Value = Any;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+          formatter.write_str("any convex value")
+        }
+
+        fn visit_unit<E>(self) -> Result<Any, E> {
+          Ok(Any::Null)
+        }
+
+        fn visit_none<E>(self) -> Result<Any, E> {
+          Ok(Any::Null)
+        }
+
+        fn visit_i64<E>(self, value: i64) -> Result<Any, E> {
+          Ok(Any::Int(value))
+        }
+
+        fn visit_f64<E>(self, value: f64) -> Result<Any, E> {
+          Ok(Any::Float(value))
+        }
+
+        fn visit_bool<E>(self, value: bool) -> Result<Any, E> {
+          Ok(Any::Bool(value))
+        }
+
+        fn visit_str<E>(self, value: &str) -> Result<Any, E> {
+          Ok(Any::String(value.to_string()))
+        }
+
+        fn visit_bytes<E>(self, value: &[u8]) -> Result<Any, E> {
+          Ok(Any::Bytes(value.to_vec()))
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Any, A::Error> {
+          let mut values = vec![];
+          while let Some(value) = seq.next_element()? {
+            values.push(value);
+          }
+          Ok(Any::Array(values))
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Any, A::Error> {
+          let mut values = BTreeMap::new();
+          while let Some((key, value)) = map.next_entry()? {
+            values.insert(key, value);
+          }
+          Ok(Any::Object(values))
+        }
+      }
+
+      deserializer.deserialize_any(AnyVisitor)
+    }
+  }
+
+  #[derive(Debug, Clone, Serialize, Deserialize)]
+  #[serde(tag = "type", rename_all = "camelCase")]
+  enum Part {
+    Text { text: String },
+    Attachment { id: String, size: Option<i64> },
+    Empty,
+  }
+
+  #[derive(Debug, Clone, Serialize, Deserialize)]
+  #[serde(tag = "t", content = "c")]
+  enum Adjacent {
+    Unit,
+    Newtype(i64),
+    Tuple(i64, String),
+    Struct { flag: bool },
+  }
+
+  #[derive(Debug, Clone, Serialize, Deserialize)]
+  enum External {
+    Unit,
+    Newtype(f64),
+    Tuple(i32, bool),
+    Struct { name: String },
+  }
+
+  #[derive(Debug, Clone, Serialize, Deserialize)]
+  #[serde(untagged)]
+  enum Untagged {
+    Number(i64),
+    Text(String),
+    List(Vec<bool>),
+  }
+
+  #[derive(Debug, Clone, Serialize, Deserialize)]
+  struct Extra {
+    note: Option<String>,
+    count: u32,
+  }
+
+  /// bytes instead of an array of numbers, like `serde_bytes`
+  #[derive(Debug, Clone)]
+  struct Buffer(Vec<u8>);
+
+  impl Serialize for Buffer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+      serializer.serialize_bytes(&self.0)
+    }
+  }
+
+  impl<'de> Deserialize<'de> for Buffer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+      struct BufferVisitor;
+
+      impl Visitor<'_> for BufferVisitor {
+        type Value = Buffer;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+          formatter.write_str("bytes")
+        }
+
+        fn visit_bytes<E>(self, value: &[u8]) -> Result<Buffer, E> {
+          Ok(Buffer(value.to_vec()))
+        }
+      }
+
+      deserializer.deserialize_byte_buf(BufferVisitor)
+    }
+  }
+
+  #[derive(Debug, Clone, Serialize, Deserialize)]
+  struct Document {
+    id: String,
+    #[serde(flatten)]
+    extra: Extra,
+    parts: Vec<Part>,
+    adjacent: Adjacent,
+    external: Vec<External>,
+    untagged: Untagged,
+    data: Buffer,
+    raw: Vec<u8>,
+    wide: i128,
+    unsigned: u128,
+    ratio: f32,
+    unit: (),
+    pair: (u8, char),
+  }
+
+  #[derive(Deserialize)]
+  struct Borrowed<'a> {
+    name: &'a str,
+    data: &'a [u8],
+    #[serde(borrow)]
+    tags: BTreeMap<&'a str, i64>,
+  }
+
+  /// equality where NaN equals NaN, since convex stores it as is
+  fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+      (Value::Float64(a), Value::Float64(b)) => a.to_bits() == b.to_bits(),
+      (Value::Array(a), Value::Array(b)) => a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b)),
+      (Value::Object(a), Value::Object(b)) => {
+        a.len() == b.len() && a.iter().zip(b).all(|((ka, va), (kb, vb))| ka == kb && same(va, vb))
+      }
+      (a, b) => a == b,
+    }
+  }
+
+  fn value() -> impl Strategy<Value = Value> {
+    let leaf = prop_oneof![
+      Just(Value::Null),
+      any::<i64>().prop_map(Value::Int64),
+      any::<f64>().prop_map(Value::Float64),
+      any::<bool>().prop_map(Value::Boolean),
+      ".{0,12}".prop_map(Value::String),
+      prop::collection::vec(any::<u8>(), 0..16).prop_map(Value::Bytes),
+    ];
+
+    leaf.prop_recursive(4, 64, 8, |inner| {
+      prop_oneof![
+        prop::collection::vec(inner.clone(), 0..8).prop_map(Value::Array),
+        prop::collection::btree_map("[a-zA-Z_][a-zA-Z0-9_]{0,8}", inner, 0..8).prop_map(Value::Object),
+      ]
+    })
+  }
+
+  fn part() -> impl Strategy<Value = Part> {
+    prop_oneof![
+      ".{0,12}".prop_map(|text| Part::Text { text }),
+      ("[a-z0-9]{1,8}", any::<Option<i64>>()).prop_map(|(id, size)| Part::Attachment { id, size }),
+      Just(Part::Empty),
+    ]
+  }
+
+  fn adjacent() -> impl Strategy<Value = Adjacent> {
+    prop_oneof![
+      Just(Adjacent::Unit),
+      any::<i64>().prop_map(Adjacent::Newtype),
+      (any::<i64>(), ".{0,8}").prop_map(|(number, text)| Adjacent::Tuple(number, text)),
+      any::<bool>().prop_map(|flag| Adjacent::Struct { flag }),
+    ]
+  }
+
+  fn external() -> impl Strategy<Value = External> {
+    prop_oneof![
+      Just(External::Unit),
+      any::<f64>().prop_map(External::Newtype),
+      (any::<i32>(), any::<bool>()).prop_map(|(number, flag)| External::Tuple(number, flag)),
+      ".{0,8}".prop_map(|name| External::Struct { name }),
+    ]
+  }
+
+  fn untagged() -> impl Strategy<Value = Untagged> {
+    prop_oneof![
+      any::<i64>().prop_map(Untagged::Number),
+      ".{0,8}".prop_map(Untagged::Text),
+      prop::collection::vec(any::<bool>(), 0..4).prop_map(Untagged::List),
+    ]
+  }
+
+  prop_compose! {
+    fn document()(
+      id in "[a-z0-9]{1,8}",
+      note in proptest::option::of(".{0,8}"),
+      count in any::<u32>(),
+      parts in prop::collection::vec(part(), 0..4),
+      adjacent in adjacent(),
+      external in prop::collection::vec(external(), 0..4),
+      untagged in untagged(),
+      data in prop::collection::vec(any::<u8>(), 0..16),
+      raw in prop::collection::vec(any::<u8>(), 0..16),
+      wide in any::<i64>(),
+      unsigned in 0..=i64::MAX as u128,
+      ratio in any::<f32>(),
+      pair in any::<(u8, char)>(),
+    ) -> Document {
+      Document {
+        id,
+        extra: Extra { note, count },
+        parts,
+        adjacent,
+        external,
+        untagged,
+        data: Buffer(data),
+        raw,
+        wide: wide as i128,
+        unsigned,
+        ratio,
+        unit: (),
+        pair,
+      }
+    }
+  }
+
+  proptest! {
+    #[test]
+    fn test_value_round_trip(value in value()) {
+      let any: Any = from_value_ref(&value).unwrap();
+      let serialized = to_value(&any).unwrap();
+
+      prop_assert!(same(&serialized, &value), "{serialized:?} != {value:?}");
+    }
+
+    #[test]
+    fn test_document_round_trip(document in document()) {
+      let value = to_value(&document).unwrap();
+      let deserialized: Document = from_value(value.clone()).unwrap();
+      let serialized = to_value(&deserialized).unwrap();
+
+      prop_assert!(same(&serialized, &value), "{serialized:?} != {value:?}");
+    }
+
+    #[test]
+    fn test_borrowed_round_trip(
+      name in ".{0,12}",
+      data in prop::collection::vec(any::<u8>(), 0..16),
+      tags in prop::collection::btree_map("[a-z]{1,8}", any::<i64>(), 0..4),
+    ) {
+      let value = Value::Object(BTreeMap::from([
+        ("name".to_string(), Value::String(name.clone())),
+        ("data".to_string(), Value::Bytes(data.clone())),
+        (
+          "tags".to_string(),
+          Value::Object(tags.iter().map(|(tag, count)| (tag.clone(), Value::Int64(*count))).collect()),
+        ),
+      ]));
+
+      let borrowed: Borrowed = from_value_ref(&value).unwrap();
+
+      prop_assert_eq!(borrowed.name, name);
+      prop_assert_eq!(borrowed.data, &data[..]);
+      prop_assert_eq!(borrowed.tags, tags.iter().map(|(tag, count)| (tag.as_str(), *count)).collect());
+    }
+  }
+
+  #[test]
+  fn test_enum_representations() {
+    let value = to_value(&External::Tuple(1, true)).unwrap();
+    assert_eq!(
+      value,
+      Value::Object(BTreeMap::from([(
+        "Tuple".to_string(),
+        Value::Array(vec![Value::Int64(1), Value::Boolean(true)])
+      )]))
+    );
+
+    let value = to_value(&Adjacent::Struct { flag: true }).unwrap();
+    assert_eq!(
+      value,
+      Value::Object(BTreeMap::from([
+        ("t".to_string(), Value::String("Struct".into())),
+        (
+          "c".to_string(),
+          Value::Object(BTreeMap::from([("flag".to_string(), Value::Boolean(true))]))
+        ),
+      ]))
+    );
+  }
+
+  #[test]
+  fn test_special_floats() {
+    for float in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, -0.0] {
+      let value = to_value(&float).unwrap();
+      assert!(same(&value, &Value::Float64(float)));
+
+      let deserialized: f64 = from_value(value).unwrap();
+      assert_eq!(deserialized.to_bits(), float.to_bits());
+    }
+  }
+
+  #[test]
+  fn test_wide_integers() {
+    assert_eq!(to_value(&(i64::MIN as i128)).unwrap(), Value::Int64(i64::MIN));
+    assert!(to_value(&(i64::MAX as i128 + 1)).is_err());
+    assert!(to_value(&u128::MAX).is_err());
+
+    assert_eq!(from_value::<i128>(Value::Int64(i64::MIN)).unwrap(), i64::MIN as i128);
+    assert_eq!(from_value::<u128>(Value::Int64(7)).unwrap(), 7);
+    assert!(from_value::<u128>(Value::Int64(-1)).is_err());
+    assert!(from_value::<u8>(Value::Int64(256)).is_err());
+  }
+
+  #[derive(Debug, Deserialize)]
+  #[allow(dead_code)]
+  struct Thread {
+    messages: Vec<Message>,
+  }
+
+  #[derive(Debug, Deserialize)]
+  #[allow(dead_code)]
+  struct Message {
+    parts: Vec<Part>,
+  }
+
+  #[test]
+  fn test_error_paths() {
+    let message = |parts: Vec<Value>| Value::Object(BTreeMap::from([("parts".to_string(), Value::Array(parts))]));
+    let part = |kind: &str| Value::Object(BTreeMap::from([("type".to_string(), Value::String(kind.into()))]));
+
+    let thread = Value::Object(BTreeMap::from([(
+      "messages".to_string(),
+      Value::Array(vec![
+        message(vec![]),
+        message(vec![part("empty")]),
+        message(vec![]),
+        message(vec![part("video")]),
+      ]),
+    )]));
+
+    let err = from_value::<Thread>(thread).unwrap_err();
+    assert_eq!(err.path(), Some("messages[3].parts[0].type"));
+    assert_eq!(
+      err.to_string(),
+      "messages[3].parts[0].type: unknown variant `video`, expected one of `text`, `attachment`, `empty`"
+    );
+
+    let err = to_value(&BTreeMap::from([("sizes", vec![1, u64::MAX])])).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "sizes[1]: cannot serialize integer outside of i64 range: 18446744073709551615"
+    );
+  }
+}
@@ -8,6 +8,8 @@ use serde::ser::{
 };
 use serde::{Serialize, Serializer};
 
+use super::{Segment, prepend_path};
+
 pub fn to_value<T: Serialize>(value: &T) -> Result<Value, Error> {
   value.serialize(ValueSerializer)
 }
@@ -21,17 +23,40 @@ pub fn to_map<T: Serialize>(value: &T) -> Result<BTreeMap<String, Value>, Error>
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
-  #[error("cannot serialize u64 values outside of i64 range: {0}")]
-  IntOutOfRange(u64),
+  #[error("cannot serialize integer outside of i64 range: {0}")]
+  IntOutOfRange(String),
   #[error("cannot serialize key that is not a string: {0}")]
   KeyNotString(String),
   #[error("cannot serialize map that is not an object: {0}")]
   MapNotObject(String),
   #[error("{0}")]
   Custom(String),
+  #[error("{path}: {error}")]
+  At { path: String, error: Box<Error> },
 }
 
 impl Error {
+  fn at(self, segment: Segment) -> Self {
+    match self {
+      Error::At { path, error } => Error::At {
+        path: prepend_path(segment, &path),
+        error,
+      },
+      error => Error::At {
+        path: prepend_path(segment, ""),
+        error: Box::new(error),
+      },
+    }
+  }
+
+  /// where in the value serialization failed, e.g. `messages[3].parts[0].type`
+  pub fn path(&self) -> Option<&str> {
+    match self {
+      Error::At { path, .. } => Some(path),
+      _ => None,
+    }
+  }
+
   fn key_not_string<T: Display, R>(value: T) -> Result<R, Self> {
     Err(Self::KeyNotString(value.to_string()))
   }
@@ -55,10 +80,10 @@ impl Serializer for ValueSerializer {
   type SerializeMap = SerializeValueMap;
   type SerializeSeq = SerializeValueArray;
   type SerializeStruct = SerializeValueMap;
-  type SerializeStructVariant = SerializeValueMap;
+  type SerializeStructVariant = SerializeVariant<SerializeValueMap>;
   type SerializeTuple = SerializeValueArray;
   type SerializeTupleStruct = SerializeValueArray;
-  type SerializeTupleVariant = SerializeValueArray;
+  type SerializeTupleVariant = SerializeVariant<SerializeValueArray>;
 
   fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
     Ok(Value::Boolean(v))
@@ -93,10 +118,20 @@ impl Serializer for ValueSerializer {
   }
 
   fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
-    if v <= i64::MAX as u64 {
-      Ok(Value::Int64(v as i64))
-    } else {
-      Err(Error::IntOutOfRange(v))
+    self.serialize_i128(v as i128)
+  }
+
+  fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
+    match i64::try_from(v) {
+      Ok(v) => Ok(Value::Int64(v)),
+      Err(_) => Err(Error::IntOutOfRange(v.to_string())),
+    }
+  }
+
+  fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
+    match i64::try_from(v) {
+      Ok(v) => Ok(Value::Int64(v)),
+      Err(_) => Err(Error::IntOutOfRange(v.to_string())),
     }
   }
 
@@ -104,6 +139,7 @@ impl Serializer for ValueSerializer {
     self.serialize_f64(v as f64)
   }
 
+  /// convex stores NaN and infinities, unlike json
   fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
     Ok(Value::Float64(v))
   }
@@ -160,9 +196,8 @@ impl Serializer for ValueSerializer {
     variant: &'static str,
     value: &T,
   ) -> Result<Self::Ok, Self::Error> {
-    let mut map = BTreeMap::new();
-    map.insert(variant.to_string(), value.serialize(self)?);
-    Ok(Value::Object(map))
+    let value = value.serialize(self).map_err(|err| err.at(Segment::Key(variant)))?;
+    Ok(Value::Object(BTreeMap::from([(variant.to_string(), value)])))
   }
 
   fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
@@ -183,10 +218,13 @@ impl Serializer for ValueSerializer {
     self,
     _name: &'static str,
     _variant_index: u32,
-    _variant: &'static str,
+    variant: &'static str,
     len: usize,
   ) -> Result<Self::SerializeTupleVariant, Self::Error> {
-    self.serialize_tuple(len)
+    Ok(SerializeVariant {
+      variant,
+      inner: self.serialize_tuple(len)?,
+    })
   }
 
   fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
@@ -201,10 +239,13 @@ impl Serializer for ValueSerializer {
     self,
     _name: &'static str,
     _variant_index: u32,
-    _variant: &'static str,
+    variant: &'static str,
     _len: usize,
   ) -> Result<Self::SerializeStructVariant, Self::Error> {
-    self.serialize_map(None)
+    Ok(SerializeVariant {
+      variant,
+      inner: self.serialize_map(None)?,
+    })
   }
 }
 
@@ -217,7 +258,10 @@ impl SerializeSeq for SerializeValueArray {
   type Ok = Value;
 
   fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
-    self.values.push(value.serialize(ValueSerializer)?);
+    let value = value
+      .serialize(ValueSerializer)
+      .map_err(|err| err.at(Segment::Index(self.values.len())))?;
+    self.values.push(value);
 
     Ok(())
   }
@@ -253,16 +297,23 @@ impl SerializeTupleStruct for SerializeValueArray {
   }
 }
 
-impl SerializeTupleVariant for SerializeValueArray {
+/// externally tagged tuple and struct variants, `{ variant: contents }`
+pub struct SerializeVariant<S> {
+  variant: &'static str,
+  inner: S,
+}
+
+impl SerializeTupleVariant for SerializeVariant<SerializeValueArray> {
   type Error = Error;
   type Ok = Value;
 
   fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
-    SerializeSeq::serialize_element(self, value)
+    SerializeSeq::serialize_element(&mut self.inner, value).map_err(|err| err.at(Segment::Key(self.variant)))
   }
 
   fn end(self) -> Result<Self::Ok, Self::Error> {
-    SerializeSeq::end(self)
+    let contents = SerializeSeq::end(self.inner)?;
+    Ok(Value::Object(BTreeMap::from([(self.variant.to_string(), contents)])))
   }
 }
 
@@ -447,7 +498,10 @@ impl SerializeMap for SerializeBTreeMap {
       .key
       .take()
       .ok_or_else(|| Error::Custom("Key not set before value serialization".to_string()))?;
-    self.map.insert(key, value.serialize(ValueSerializer)?);
+    let value = value
+      .serialize(ValueSerializer)
+      .map_err(|err| err.at(Segment::Key(&key)))?;
+    self.map.insert(key, value);
     Ok(())
   }
 
@@ -474,17 +528,17 @@ impl SerializeStruct for SerializeBTreeMap {
   }
 }
 
-impl SerializeStructVariant for SerializeBTreeMap {
+impl SerializeStructVariant for SerializeVariant<SerializeBTreeMap> {
   type Error = Error;
   type Ok = BTreeMap<String, Value>;
 
   fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error> {
-    self.key = Some(key.to_string());
-    SerializeMap::serialize_value(self, value)
+    SerializeStruct::serialize_field(&mut self.inner, key, value).map_err(|err| err.at(Segment::Key(self.variant)))
   }
 
   fn end(self) -> Result<Self::Ok, Self::Error> {
-    SerializeMap::end(self)
+    let contents = SerializeMap::end(self.inner)?;
+    Ok(BTreeMap::from([(self.variant.to_string(), Value::Object(contents))]))
   }
 }
 
@@ -530,16 +584,17 @@ impl SerializeStruct for SerializeValueMap {
   }
 }
 
-impl SerializeStructVariant for SerializeValueMap {
+impl SerializeStructVariant for SerializeVariant<SerializeValueMap> {
   type Error = Error;
   type Ok = Value;
 
   fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error> {
-    SerializeStructVariant::serialize_field(&mut self.inner, key, value)
+    SerializeStruct::serialize_field(&mut self.inner, key, value).map_err(|err| err.at(Segment::Key(self.variant)))
   }
 
   fn end(self) -> Result<Self::Ok, Self::Error> {
-    Ok(Value::Object(SerializeStructVariant::end(self.inner)?))
+    let contents = SerializeStruct::end(self.inner)?;
+    Ok(Value::Object(BTreeMap::from([(self.variant.to_string(), contents)])))
   }
 }
 
@@ -549,7 +604,7 @@ impl Serializer for SerializeBTreeMap {
   type SerializeMap = SerializeBTreeMap;
   type SerializeSeq = Impossible<Self::Ok, Self::Error>;
   type SerializeStruct = SerializeBTreeMap;
-  type SerializeStructVariant = SerializeBTreeMap;
+  type SerializeStructVariant = SerializeVariant<SerializeBTreeMap>;
   type SerializeTuple = Impossible<Self::Ok, Self::Error>;
   type SerializeTupleStruct = Impossible<Self::Ok, Self::Error>;
   type SerializeTupleVariant = Impossible<Self::Ok, Self::Error>;
@@ -687,10 +742,13 @@ impl Serializer for SerializeBTreeMap {
     self,
     _name: &'static str,
     _variant_index: u32,
-    _variant: &'static str,
+    variant: &'static str,
     _len: usize,
   ) -> Result<Self::SerializeStructVariant, Self::Error> {
-    self.serialize_map(None)
+    Ok(SerializeVariant {
+      variant,
+      inner: self.serialize_map(None)?,
+    })
   }
 }
 
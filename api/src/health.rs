@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::convex::health;
+use crate::prelude::*;
+
+/// how long a single dependency check may take before it counts as failed
+const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+/// probes arriving within this window reuse the previous result instead of hitting the dependencies again
+const CACHE_TTL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckResult {
+  pub ok: bool,
+  pub latency_ms: u64,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DependencyChecks {
+  pub convex: CheckResult,
+  pub openrouter: CheckResult,
+}
+
+impl DependencyChecks {
+  pub fn ok(&self) -> bool {
+    self.convex.ok && self.openrouter.ok
+  }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildInfo {
+  pub version: &'static str,
+  /// set by the build environment, e.g. `GIT_SHA=$(git rev-parse HEAD) cargo build`
+  pub git_sha: Option<&'static str>,
+}
+
+pub const BUILD_INFO: BuildInfo = BuildInfo {
+  version: env!("CARGO_PKG_VERSION"),
+  git_sha: option_env!("GIT_SHA"),
+};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Readiness {
+  pub ready: bool,
+  pub checks: DependencyChecks,
+  pub checked_at: Timestamp,
+  pub active_generations: usize,
+  pub build: BuildInfo,
+}
+
+/// runs `check` with a timeout and records how long it took
+pub async fn run_check<F>(check: F, timeout: Duration) -> CheckResult
+where
+  F: Future<Output = anyhow::Result<()>>,
+{
+  let started = Instant::now();
+
+  let error = match tokio::time::timeout(timeout, check).await {
+    Ok(Ok(())) => None,
+    Ok(Err(err)) => Some(format!("{err:#}")),
+    Err(_) => Some(format!("timed out after {timeout:?}")),
+  };
+
+  CheckResult {
+    ok: error.is_none(),
+    latency_ms: started.elapsed().as_millis() as u64,
+    error,
+  }
+}
+
+async fn check_dependencies(state: &AppState) -> DependencyChecks {
+  let mut convex = state.convex.clone();
+
+  let convex_check = run_check(
+    async { health::ping(&mut convex).await.context("convex ping failed") },
+    CHECK_TIMEOUT,
+  );
+
+  let openrouter_check = run_check(
+    async {
+      let request = state.openrouter.lock().await.models_request();
+      let response = request.send().await.context("failed to reach openrouter")?;
+
+      if !response.status().is_success() {
+        bail!("openrouter returned {}", response.status());
+      }
+
+      Ok(())
+    },
+    CHECK_TIMEOUT,
+  );
+
+  let (convex, openrouter) = tokio::join!(convex_check, openrouter_check);
+
+  DependencyChecks { convex, openrouter }
+}
+
+#[derive(Default)]
+pub struct ReadinessCache {
+  last: Mutex<Option<(Instant, DependencyChecks, Timestamp)>>,
+}
+
+impl ReadinessCache {
+  /// returns cached checks while they are fresh, concurrent probes wait for a single run
+  async fn checks(&self, state: &AppState) -> (DependencyChecks, Timestamp) {
+    let mut last = self.last.lock().await;
+
+    if let Some((checked, checks, checked_at)) = last.as_ref()
+      && checked.elapsed() < CACHE_TTL
+    {
+      return (checks.clone(), *checked_at);
+    }
+
+    let checks = check_dependencies(state).await;
+    let checked_at = chrono::Utc::now();
+
+    if !checks.ok() {
+      warn!("Readiness check failed: {checks:?}");
+    }
+
+    *last = Some((Instant::now(), checks.clone(), checked_at));
+
+    (checks, checked_at)
+  }
+}
+
+pub async fn readiness(state: &AppState) -> Readiness {
+  let (checks, checked_at) = state.readiness.checks(state).await;
+
+  Readiness {
+    ready: checks.ok(),
+    checks,
+    checked_at,
+    active_generations: state.active_threads.lock().await.len(),
+    build: BUILD_INFO,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_run_check_times_out() {
+    let result = run_check(
+      async {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        Ok(())
+      },
+      Duration::from_millis(10),
+    )
+    .await;
+
+    assert!(!result.ok);
+    assert!(result.error.unwrap().starts_with("timed out"));
+  }
+
+  #[tokio::test]
+  async fn test_run_check_reports_error_chain() {
+    let result = run_check(
+      async { Err(anyhow!("connection refused")).context("convex ping failed") },
+      CHECK_TIMEOUT,
+    )
+    .await;
+
+    assert!(!result.ok);
+    assert_eq!(result.error.as_deref(), Some("convex ping failed: connection refused"));
+  }
+}
@@ -0,0 +1,214 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Deserialize;
+
+use crate::convex::imports::ImportRole;
+use crate::import::{Conversation, ConversationMessage, join_blocks};
+
+#[derive(Deserialize)]
+struct ChatgptConversation {
+  id: Option<String>,
+  conversation_id: Option<String>,
+  title: Option<String>,
+  /// unix timestamp in seconds
+  create_time: Option<f64>,
+  /// unix timestamp in seconds
+  update_time: Option<f64>,
+  #[serde(default)]
+  mapping: HashMap<String, ChatgptNode>,
+  current_node: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatgptNode {
+  message: Option<ChatgptMessage>,
+  parent: Option<String>,
+  #[serde(default)]
+  children: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatgptMessage {
+  author: ChatgptAuthor,
+  create_time: Option<f64>,
+  content: ChatgptContent,
+  #[serde(default)]
+  metadata: ChatgptMetadata,
+}
+
+#[derive(Deserialize)]
+struct ChatgptAuthor {
+  role: String,
+}
+
+#[derive(Deserialize)]
+struct ChatgptContent {
+  content_type: String,
+  #[serde(default)]
+  parts: Vec<serde_json::Value>,
+  text: Option<String>,
+  language: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct ChatgptMetadata {
+  model_slug: Option<String>,
+  is_visually_hidden_from_conversation: Option<bool>,
+}
+
+/// `None` for conversations without an id
+pub fn parse_conversation(value: serde_json::Value) -> Result<Option<Conversation>, serde_json::Error> {
+  Ok(convert_conversation(serde_json::from_value(value)?))
+}
+
+fn seconds_to_ms(seconds: f64) -> f64 {
+  (seconds * 1000.0).round()
+}
+
+fn convert_conversation(conversation: ChatgptConversation) -> Option<Conversation> {
+  let source_id = conversation.conversation_id.clone().or(conversation.id.clone())?;
+
+  let messages = active_branch(&conversation)
+    .into_iter()
+    .filter_map(convert_message)
+    .collect();
+
+  Some(Conversation {
+    source_id,
+    title: conversation.title.filter(|title| !title.trim().is_empty()),
+    created_at: conversation.create_time.map(seconds_to_ms),
+    updated_at: conversation.update_time.map(seconds_to_ms),
+    messages,
+  })
+}
+
+/// the mapping is a tree of every edit and regeneration, walk up from the current node to get the visible branch
+fn active_branch(conversation: &ChatgptConversation) -> Vec<&ChatgptMessage> {
+  let mapping = &conversation.mapping;
+
+  let leaf = conversation
+    .current_node
+    .clone()
+    .filter(|node| mapping.contains_key(node))
+    .or_else(|| last_leaf(mapping));
+
+  let mut branch = vec![];
+  let mut visited = HashSet::new();
+  let mut current = leaf;
+
+  while let Some(id) = current {
+    // guard against malformed exports with cycles
+    if !visited.insert(id.clone()) {
+      break;
+    }
+
+    let Some(node) = mapping.get(&id) else {
+      break;
+    };
+
+    if let Some(message) = &node.message {
+      branch.push(message);
+    }
+
+    current = node.parent.clone();
+  }
+
+  branch.reverse();
+  branch
+}
+
+/// follows the latest child from the root when the export has no current node
+fn last_leaf(mapping: &HashMap<String, ChatgptNode>) -> Option<String> {
+  let (mut id, mut node) = mapping.iter().find(|(_, node)| node.parent.is_none())?;
+
+  for _ in 0..mapping.len() {
+    let Some(child) = node.children.last() else {
+      break;
+    };
+    let Some(child_node) = mapping.get(child) else {
+      break;
+    };
+
+    id = child;
+    node = child_node;
+  }
+
+  Some(id.clone())
+}
+
+fn convert_message(message: &ChatgptMessage) -> Option<ConversationMessage> {
+  if message.metadata.is_visually_hidden_from_conversation == Some(true) {
+    return None;
+  }
+
+  let role = match message.author.role.as_str() {
+    "user" => ImportRole::User,
+    "assistant" => ImportRole::Assistant,
+    "system" => ImportRole::System,
+    // tool calls and browsing results are not part of the visible conversation
+    _ => return None,
+  };
+
+  let text = message_text(&message.content)?;
+
+  Some(ConversationMessage {
+    role,
+    text,
+    created_at: message.create_time.map(seconds_to_ms),
+    model: message.metadata.model_slug.clone(),
+  })
+}
+
+fn message_text(content: &ChatgptContent) -> Option<String> {
+  let text = match content.content_type.as_str() {
+    "text" | "multimodal_text" => join_blocks(content.parts.iter().map(|part| match part {
+      serde_json::Value::String(text) => text.clone(),
+      // image asset pointers and audio references are not included in the export text
+      _ => "[attachment not imported]".to_string(),
+    })),
+    "code" => {
+      let code = content.text.clone()?;
+      let language = content.language.clone().filter(|l| l != "unknown").unwrap_or_default();
+      format!("```{language}\n{code}\n```")
+    }
+    _ => return None,
+  };
+
+  if text.trim().is_empty() { None } else { Some(text) }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::import::{ImportFormat, parse};
+
+  #[test]
+  fn test_parse_chatgpt_export() {
+    let json = include_str!("../../test_data/chatgpt-conversations.json");
+
+    let (format, conversations) = parse(None, json.as_bytes()).unwrap();
+    assert_eq!(format, ImportFormat::Chatgpt);
+    assert_eq!(conversations.len(), 1);
+
+    let conversation = &conversations[0];
+    assert_eq!(conversation.source_id, "conv-1");
+    assert_eq!(conversation.title.as_deref(), Some("Borrow checker"));
+    assert_eq!(conversation.created_at, Some(1_700_000_000_000.0));
+
+    // hidden system message and the abandoned branch are skipped
+    let texts = conversation
+      .messages
+      .iter()
+      .map(|message| message.text.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(
+      texts,
+      vec!["What does the borrow checker do?", "It enforces ownership rules."]
+    );
+
+    assert_eq!(conversation.messages[0].role, ImportRole::User);
+    assert_eq!(conversation.messages[1].role, ImportRole::Assistant);
+    assert_eq!(conversation.messages[1].model.as_deref(), Some("gpt-4o"));
+  }
+}
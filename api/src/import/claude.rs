@@ -0,0 +1,146 @@
+use serde::Deserialize;
+
+use crate::convex::imports::ImportRole;
+use crate::import::{Conversation, ConversationMessage, join_blocks};
+
+#[derive(Deserialize)]
+struct ClaudeConversation {
+  uuid: String,
+  name: Option<String>,
+  created_at: Option<String>,
+  updated_at: Option<String>,
+  #[serde(default)]
+  chat_messages: Vec<ClaudeMessage>,
+}
+
+#[derive(Deserialize)]
+struct ClaudeMessage {
+  sender: String,
+  #[serde(default)]
+  text: String,
+  #[serde(default)]
+  content: Vec<ClaudeContent>,
+  created_at: Option<String>,
+  #[serde(default)]
+  attachments: Vec<ClaudeAttachment>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClaudeContent {
+  Text {
+    text: String,
+  },
+  #[serde(other)]
+  Other,
+}
+
+#[derive(Deserialize)]
+struct ClaudeAttachment {
+  file_name: String,
+  #[serde(default)]
+  extracted_content: String,
+}
+
+pub fn parse_conversation(value: serde_json::Value) -> Result<Conversation, serde_json::Error> {
+  Ok(convert_conversation(serde_json::from_value(value)?))
+}
+
+fn parse_timestamp(timestamp: &str) -> Option<f64> {
+  chrono::DateTime::parse_from_rfc3339(timestamp)
+    .ok()
+    .map(|date| date.timestamp_millis() as f64)
+}
+
+fn convert_conversation(conversation: ClaudeConversation) -> Conversation {
+  let messages = conversation
+    .chat_messages
+    .into_iter()
+    .filter_map(convert_message)
+    .collect();
+
+  Conversation {
+    source_id: conversation.uuid,
+    title: conversation.name.filter(|name| !name.trim().is_empty()),
+    created_at: conversation.created_at.as_deref().and_then(parse_timestamp),
+    updated_at: conversation.updated_at.as_deref().and_then(parse_timestamp),
+    messages,
+  }
+}
+
+fn convert_message(message: ClaudeMessage) -> Option<ConversationMessage> {
+  let role = match message.sender.as_str() {
+    "human" => ImportRole::User,
+    "assistant" => ImportRole::Assistant,
+    _ => return None,
+  };
+
+  let content_text = join_blocks(message.content.into_iter().filter_map(|content| match content {
+    ClaudeContent::Text { text } => Some(text),
+    ClaudeContent::Other => None,
+  }));
+
+  // older exports only have the flat text field
+  let text = if content_text.is_empty() {
+    message.text
+  } else {
+    content_text
+  };
+
+  // pasted files are exported with their extracted text, keep it inline so the context is not lost
+  let attachments = message.attachments.into_iter().map(|attachment| {
+    format!(
+      "Attachment: {}\n\n```\n{}\n```",
+      attachment.file_name,
+      attachment.extracted_content.trim()
+    )
+  });
+
+  let text = join_blocks(std::iter::once(text).chain(attachments));
+
+  if text.is_empty() {
+    return None;
+  }
+
+  Some(ConversationMessage {
+    role,
+    text,
+    created_at: message.created_at.as_deref().and_then(parse_timestamp),
+    // claude exports do not record which model answered
+    model: None,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::import::{ImportFormat, parse};
+
+  #[test]
+  fn test_parse_claude_export() {
+    let json = include_str!("../../test_data/claude-conversations.json");
+
+    let (format, conversations) = parse(None, json.as_bytes()).unwrap();
+    assert_eq!(format, ImportFormat::Claude);
+    assert_eq!(conversations.len(), 2);
+
+    let conversation = &conversations[0];
+    assert_eq!(conversation.source_id, "6d1c7a5e-0000-4000-8000-000000000001");
+    assert_eq!(conversation.title.as_deref(), Some("Lifetimes"));
+    assert_eq!(conversation.created_at, Some(1_700_000_000_000.0));
+    assert_eq!(conversation.messages.len(), 2);
+
+    let user = &conversation.messages[0];
+    assert_eq!(user.role, ImportRole::User);
+    assert!(user.text.starts_with("Explain this:"));
+    assert!(user.text.contains("Attachment: main.rs"));
+
+    let assistant = &conversation.messages[1];
+    assert_eq!(assistant.role, ImportRole::Assistant);
+    assert_eq!(assistant.text, "The reference outlives the value.");
+
+    // untitled conversation without messages
+    assert_eq!(conversations[1].title, None);
+    assert!(conversations[1].messages.is_empty());
+  }
+}
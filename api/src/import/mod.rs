@@ -0,0 +1,256 @@
+use std::fmt;
+use std::io::{BufReader, Read};
+
+use serde::Deserializer as _;
+use serde::de::{Error as _, IgnoredAny, SeqAccess, Visitor};
+
+use crate::convex::imports::{ImportMessageArgs, ImportRole, ImportThreadArgs};
+use crate::convex::threads::Thread;
+use crate::convex::{ConvexClient, ConvexError, Id, imports};
+use crate::prelude::*;
+
+mod chatgpt;
+mod claude;
+
+/// model recorded on imported threads when the export does not name one
+const UNKNOWN_MODEL: &str = "imported";
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Type, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportFormat {
+  /// `conversations.json` from a ChatGPT data export
+  Chatgpt,
+  /// `conversations.json` from a Claude data export
+  Claude,
+}
+
+impl ImportFormat {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      ImportFormat::Chatgpt => "chatgpt",
+      ImportFormat::Claude => "claude",
+    }
+  }
+
+  /// guesses the format from the shape of a conversation
+  pub fn detect(conversation: &serde_json::Value) -> Option<Self> {
+    if conversation.get("mapping").is_some() {
+      Some(ImportFormat::Chatgpt)
+    } else if conversation.get("chat_messages").is_some() {
+      Some(ImportFormat::Claude)
+    } else {
+      None
+    }
+  }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImportError {
+  #[error("could not detect export format")]
+  UnknownFormat,
+  #[error("failed to parse export: {0}")]
+  Parse(#[from] serde_json::Error),
+  #[error("convex error: {0}")]
+  Convex(#[from] ConvexError),
+}
+
+#[derive(Debug)]
+pub struct Conversation {
+  pub source_id: String,
+  pub title: Option<String>,
+  /// unix timestamp in milliseconds
+  pub created_at: Option<f64>,
+  /// unix timestamp in milliseconds
+  pub updated_at: Option<f64>,
+  pub messages: Vec<ConversationMessage>,
+}
+
+#[derive(Debug)]
+pub struct ConversationMessage {
+  pub role: ImportRole,
+  pub text: String,
+  /// unix timestamp in milliseconds
+  pub created_at: Option<f64>,
+  pub model: Option<String>,
+}
+
+/// reads the conversations array of an export, the format is detected from the first conversation when not given.
+/// conversations are converted one at a time, so the export never has to be held as json all at once
+pub fn parse(
+  format: Option<ImportFormat>,
+  reader: impl Read,
+) -> Result<(ImportFormat, Vec<Conversation>), ImportError> {
+  let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(reader));
+
+  let (format, conversations) = deserializer.deserialize_seq(ExportVisitor { format })?;
+  deserializer.end()?;
+
+  Ok((format.ok_or(ImportError::UnknownFormat)?, conversations))
+}
+
+struct ExportVisitor {
+  format: Option<ImportFormat>,
+}
+
+impl<'de> Visitor<'de> for ExportVisitor {
+  type Value = (Option<ImportFormat>, Vec<Conversation>);
+
+  fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    formatter.write_str("an array of conversations")
+  }
+
+  fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+    let mut format = self.format;
+    let mut conversations = vec![];
+
+    while let Some(value) = seq.next_element::<serde_json::Value>()? {
+      format = format.or_else(|| ImportFormat::detect(&value));
+
+      let conversation = match format {
+        Some(ImportFormat::Chatgpt) => chatgpt::parse_conversation(value),
+        Some(ImportFormat::Claude) => claude::parse_conversation(value).map(Some),
+        None => {
+          // the rest still has to be read for the array to end
+          while seq.next_element::<IgnoredAny>()?.is_some() {}
+          return Ok((None, vec![]));
+        }
+      };
+
+      conversations.extend(conversation.map_err(A::Error::custom)?);
+    }
+
+    Ok((format, conversations))
+  }
+}
+
+pub struct ImportOptions {
+  pub user_id: String,
+  /// only report what would be imported
+  pub dry_run: bool,
+  /// import conversations even if a thread with the same source id already exists
+  pub allow_duplicates: bool,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportStatus {
+  Imported,
+  WouldImport,
+  Duplicate,
+  Empty,
+}
+
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportResult {
+  pub source_id: String,
+  pub title: Option<String>,
+  pub message_count: usize,
+  pub status: ImportStatus,
+  #[specta(type = Option<String>)]
+  pub thread_id: Option<Id<Thread>>,
+}
+
+fn conversation_to_args(user_id: String, import_id: String, conversation: Conversation) -> ImportThreadArgs {
+  let now = chrono::Utc::now().timestamp_millis() as f64;
+
+  let created_at = conversation
+    .created_at
+    .or_else(|| conversation.messages.first().and_then(|m| m.created_at))
+    .unwrap_or(now);
+
+  let updated_at = conversation
+    .updated_at
+    .or_else(|| conversation.messages.last().and_then(|m| m.created_at))
+    .unwrap_or(created_at);
+
+  let model = conversation
+    .messages
+    .iter()
+    .rev()
+    .find_map(|message| message.model.clone())
+    .unwrap_or_else(|| UNKNOWN_MODEL.to_string());
+
+  let messages = conversation
+    .messages
+    .into_iter()
+    .map(|message| ImportMessageArgs {
+      role: message.role,
+      text: message.text,
+      created_at: message.created_at,
+      model: message.model,
+    })
+    .collect();
+
+  ImportThreadArgs {
+    user_id,
+    import_id,
+    title: conversation.title,
+    model,
+    created_at,
+    updated_at,
+    messages,
+  }
+}
+
+pub async fn import_conversations(
+  client: &mut ConvexClient,
+  format: ImportFormat,
+  conversations: Vec<Conversation>,
+  options: &ImportOptions,
+) -> Result<Vec<ImportResult>, ImportError> {
+  let mut results = Vec::with_capacity(conversations.len());
+
+  for conversation in conversations {
+    let import_id = format!("{}:{}", format.as_str(), conversation.source_id);
+
+    let mut result = ImportResult {
+      source_id: conversation.source_id.clone(),
+      title: conversation.title.clone(),
+      message_count: conversation.messages.len(),
+      status: ImportStatus::WouldImport,
+      thread_id: None,
+    };
+
+    if conversation.messages.is_empty() {
+      result.status = ImportStatus::Empty;
+      results.push(result);
+      continue;
+    }
+
+    if !options.allow_duplicates {
+      let existing = imports::get_by_import_id(client, options.user_id.clone(), import_id.clone()).await?;
+
+      if let Some(thread) = existing {
+        result.status = ImportStatus::Duplicate;
+        result.thread_id = Some(thread.id);
+        results.push(result);
+        continue;
+      }
+    }
+
+    if !options.dry_run {
+      let args = conversation_to_args(options.user_id.clone(), import_id, conversation);
+      let thread_id = imports::import_thread(client, args).await?;
+
+      info!("Imported {} as thread {thread_id}", result.source_id);
+
+      result.status = ImportStatus::Imported;
+      result.thread_id = Some(thread_id);
+    }
+
+    results.push(result);
+  }
+
+  Ok(results)
+}
+
+/// joins non empty text blocks with blank lines
+fn join_blocks<I: IntoIterator<Item = String>>(blocks: I) -> String {
+  blocks
+    .into_iter()
+    .map(|block| block.trim().to_string())
+    .filter(|block| !block.is_empty())
+    .collect::<Vec<_>>()
+    .join("\n\n")
+}
@@ -0,0 +1,347 @@
+use std::fmt;
+use std::str::FromStr;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use secrecy::ExposeSecret;
+
+use crate::config::KeysConfig;
+use crate::convex::{ConvexClient, ConvexError, openrouter_keys, usage};
+use crate::prelude::*;
+
+/// which openrouter key pays for a generation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyPolicy {
+  /// every user brings their own key
+  User,
+  /// the server key, limited by the daily token quota
+  Server,
+  /// the user's key when they have one, the server key with its quota otherwise
+  UserWithFallback,
+}
+
+impl KeyPolicy {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      KeyPolicy::User => "user",
+      KeyPolicy::Server => "server",
+      KeyPolicy::UserWithFallback => "user_with_fallback",
+    }
+  }
+}
+
+impl fmt::Display for KeyPolicy {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl FromStr for KeyPolicy {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "user" => Ok(KeyPolicy::User),
+      "server" => Ok(KeyPolicy::Server),
+      "user_with_fallback" => Ok(KeyPolicy::UserWithFallback),
+      _ => Err("expected one of user, server or user_with_fallback".into()),
+    }
+  }
+}
+
+/// bumped when the format changes so old keys can still be read
+const CIPHER_VERSION: &str = "v1";
+const NONCE_LEN: usize = 12;
+
+/// encrypts user keys with the server master key, the user id is bound as associated data so a stored key can not
+/// be moved to another user
+pub struct KeyCipher(ChaCha20Poly1305);
+
+impl KeyCipher {
+  /// `master_key` is 32 base64 encoded bytes, e.g. from `openssl rand -base64 32`
+  pub fn from_base64(master_key: &str) -> anyhow::Result<Self> {
+    let bytes = BASE64.decode(master_key.trim()).context("not valid base64")?;
+
+    let cipher =
+      ChaCha20Poly1305::new_from_slice(&bytes).map_err(|_| anyhow!("must be 32 bytes, got {}", bytes.len()))?;
+
+    Ok(Self(cipher))
+  }
+
+  /// `v1.<base64 nonce + ciphertext>`
+  pub fn encrypt(&self, user_id: &str, key: &str) -> anyhow::Result<String> {
+    let mut nonce = [0u8; NONCE_LEN];
+    getrandom::fill(&mut nonce).map_err(|err| anyhow!("failed to generate nonce: {err}"))?;
+
+    let payload = Payload {
+      msg: key.as_bytes(),
+      aad: user_id.as_bytes(),
+    };
+
+    let ciphertext = self
+      .0
+      .encrypt(Nonce::from_slice(&nonce), payload)
+      .map_err(|_| anyhow!("failed to encrypt key"))?;
+
+    let mut bytes = nonce.to_vec();
+    bytes.extend(ciphertext);
+
+    Ok(format!("{CIPHER_VERSION}.{}", BASE64.encode(bytes)))
+  }
+
+  pub fn decrypt(&self, user_id: &str, encrypted: &str) -> anyhow::Result<String> {
+    let Some(encoded) = encrypted
+      .strip_prefix(CIPHER_VERSION)
+      .and_then(|rest| rest.strip_prefix('.'))
+    else {
+      bail!("unknown key format");
+    };
+
+    let bytes = BASE64.decode(encoded).context("stored key is not valid base64")?;
+
+    if bytes.len() <= NONCE_LEN {
+      bail!("stored key is too short");
+    }
+
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+
+    let payload = Payload {
+      msg: ciphertext,
+      aad: user_id.as_bytes(),
+    };
+
+    let key = self
+      .0
+      .decrypt(Nonce::from_slice(nonce), payload)
+      .map_err(|_| anyhow!("stored key does not match the master key or user"))?;
+
+    String::from_utf8(key).context("stored key is not valid utf-8")
+  }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeyError {
+  #[error("no openrouter key stored for this user")]
+  NoKey,
+  #[error("daily server key quota exceeded")]
+  QuotaExceeded,
+  #[error("key storage is not configured")]
+  StorageDisabled,
+  #[error("key encryption failed: {0}")]
+  Cipher(anyhow::Error),
+  #[error("convex error: {0}")]
+  Convex(#[from] ConvexError),
+}
+
+/// picks the openrouter key for a request according to the configured [`KeyPolicy`]
+pub struct KeyManager {
+  policy: KeyPolicy,
+  cipher: Option<KeyCipher>,
+  server_daily_tokens: Option<u64>,
+}
+
+impl KeyManager {
+  pub fn new(config: &KeysConfig) -> anyhow::Result<Self> {
+    let cipher = config
+      .encryption_key
+      .as_ref()
+      .map(|key| KeyCipher::from_base64(key.expose_secret()))
+      .transpose()
+      .context("invalid key encryption key")?;
+
+    Ok(Self {
+      policy: config.policy,
+      cipher,
+      server_daily_tokens: config.server_daily_tokens,
+    })
+  }
+
+  pub fn policy(&self) -> KeyPolicy {
+    self.policy
+  }
+
+  pub fn storage_enabled(&self) -> bool {
+    self.cipher.is_some()
+  }
+
+  fn cipher(&self) -> Result<&KeyCipher, KeyError> {
+    self.cipher.as_ref().ok_or(KeyError::StorageDisabled)
+  }
+
+  pub async fn has_key(&self, convex: &mut ConvexClient, user_id: &str) -> Result<bool, KeyError> {
+    Ok(openrouter_keys::get(convex, user_id.to_string()).await?.is_some())
+  }
+
+  pub async fn store(&self, convex: &mut ConvexClient, user_id: &str, key: &str) -> Result<(), KeyError> {
+    let encrypted = self.cipher()?.encrypt(user_id, key).map_err(KeyError::Cipher)?;
+
+    openrouter_keys::set(convex, user_id.to_string(), encrypted).await?;
+
+    Ok(())
+  }
+
+  pub async fn remove(&self, convex: &mut ConvexClient, user_id: &str) -> Result<bool, KeyError> {
+    Ok(openrouter_keys::remove(convex, user_id.to_string()).await?)
+  }
+
+  pub async fn stored_key(&self, convex: &mut ConvexClient, user_id: &str) -> Result<Option<String>, KeyError> {
+    // without a master key nothing can have been stored
+    let Some(cipher) = &self.cipher else {
+      return Ok(None);
+    };
+
+    let Some(stored) = openrouter_keys::get(convex, user_id.to_string()).await? else {
+      return Ok(None);
+    };
+
+    let key = cipher
+      .decrypt(user_id, &stored.encrypted_key)
+      .map_err(KeyError::Cipher)?;
+
+    Ok(Some(key))
+  }
+
+  /// tokens the user generated today (utc) count against the server key quota
+  async fn check_quota(&self, convex: &mut ConvexClient, user_id: &str) -> Result<(), KeyError> {
+    let Some(limit) = self.server_daily_tokens else {
+      return Ok(());
+    };
+
+    let today = chrono::Utc::now()
+      .date_naive()
+      .and_time(chrono::NaiveTime::MIN)
+      .and_utc();
+    let used = usage::total_since(convex, user_id.to_string(), today.timestamp_millis() as f64).await?;
+
+    if used >= limit as f64 {
+      return Err(KeyError::QuotaExceeded);
+    }
+
+    Ok(())
+  }
+
+  /// the key a request of `user_id` should use, `None` means the server key
+  ///
+  /// `supplied` is a key sent with the request, it takes precedence over the stored one. `is_owner` is set when the
+  /// request comes from the user themselves or the worker acting for them, only then may the stored key or the server
+  /// key be used, since the server key is charged to the quota of `user_id`
+  pub async fn resolve(
+    &self,
+    convex: &mut ConvexClient,
+    user_id: &str,
+    supplied: Option<String>,
+    is_owner: bool,
+  ) -> Result<Option<String>, KeyError> {
+    match self.policy.key_source(supplied, is_owner)? {
+      KeySource::Supplied(key) => Ok(Some(key)),
+      KeySource::Stored { fallback } => match self.stored_key(convex, user_id).await? {
+        Some(key) => Ok(Some(key)),
+        None if fallback => {
+          self.check_quota(convex, user_id).await?;
+          Ok(None)
+        }
+        None => Err(KeyError::NoKey),
+      },
+      KeySource::Server => {
+        self.check_quota(convex, user_id).await?;
+        Ok(None)
+      }
+    }
+  }
+}
+
+/// where the key of a request comes from, decided before anything is looked up
+#[derive(Debug, PartialEq, Eq)]
+enum KeySource {
+  Supplied(String),
+  /// the key stored for the user, or the server key when `fallback` is set and there is none
+  Stored {
+    fallback: bool,
+  },
+  Server,
+}
+
+impl KeyPolicy {
+  /// anyone else than the owner has to bring their own key
+  fn key_source(self, supplied: Option<String>, is_owner: bool) -> Result<KeySource, KeyError> {
+    match (self, supplied) {
+      (KeyPolicy::Server, _) if is_owner => Ok(KeySource::Server),
+      (KeyPolicy::User | KeyPolicy::UserWithFallback, Some(key)) => Ok(KeySource::Supplied(key)),
+      (KeyPolicy::User, None) if is_owner => Ok(KeySource::Stored { fallback: false }),
+      (KeyPolicy::UserWithFallback, None) if is_owner => Ok(KeySource::Stored { fallback: true }),
+      _ => Err(KeyError::NoKey),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const MASTER_KEY: &str = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=";
+
+  #[test]
+  fn test_key_round_trip() {
+    let cipher = KeyCipher::from_base64(MASTER_KEY).unwrap();
+
+    let encrypted = cipher.encrypt("user-1", "sk-or-v1-secret").unwrap();
+
+    assert!(encrypted.starts_with("v1."));
+    assert!(!encrypted.contains("secret"));
+    assert_ne!(encrypted, cipher.encrypt("user-1", "sk-or-v1-secret").unwrap());
+    assert_eq!(cipher.decrypt("user-1", &encrypted).unwrap(), "sk-or-v1-secret");
+  }
+
+  #[test]
+  fn test_key_is_bound_to_user() {
+    let cipher = KeyCipher::from_base64(MASTER_KEY).unwrap();
+
+    let encrypted = cipher.encrypt("user-1", "sk-or-v1-secret").unwrap();
+
+    assert!(cipher.decrypt("user-2", &encrypted).is_err());
+  }
+
+  #[test]
+  fn test_anonymous_requests_never_get_the_server_key() {
+    for policy in [KeyPolicy::Server, KeyPolicy::UserWithFallback, KeyPolicy::User] {
+      assert!(matches!(policy.key_source(None, false), Err(KeyError::NoKey)));
+    }
+
+    assert!(matches!(
+      KeyPolicy::Server.key_source(Some("sk-or-v1-own".into()), false),
+      Err(KeyError::NoKey)
+    ));
+    assert_eq!(
+      KeyPolicy::UserWithFallback
+        .key_source(Some("sk-or-v1-own".into()), false)
+        .unwrap(),
+      KeySource::Supplied("sk-or-v1-own".into())
+    );
+  }
+
+  #[test]
+  fn test_owner_key_source() {
+    assert_eq!(KeyPolicy::Server.key_source(None, true).unwrap(), KeySource::Server);
+    assert_eq!(
+      KeyPolicy::Server.key_source(Some("sk-or-v1-own".into()), true).unwrap(),
+      KeySource::Server
+    );
+    assert_eq!(
+      KeyPolicy::User.key_source(None, true).unwrap(),
+      KeySource::Stored { fallback: false }
+    );
+    assert_eq!(
+      KeyPolicy::UserWithFallback.key_source(None, true).unwrap(),
+      KeySource::Stored { fallback: true }
+    );
+  }
+
+  #[test]
+  fn test_invalid_master_key() {
+    assert!(KeyCipher::from_base64("c2hvcnQ=").is_err());
+    assert!(KeyCipher::from_base64("not base64!").is_err());
+  }
+}
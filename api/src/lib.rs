@@ -1,13 +1,25 @@
+pub mod auth;
+pub mod cli;
 pub mod config;
 pub mod convex;
 pub mod convex_serde;
 pub mod error;
+pub mod health;
+pub mod import;
+pub mod keys;
 pub mod logger;
+pub mod metrics;
 pub mod openrouter;
+pub mod proxy;
+pub mod retry;
 pub mod routes;
 pub mod setup;
 pub mod state;
+pub mod telemetry;
+pub mod terminal;
+pub mod transcript;
 pub mod types;
+pub mod worker;
 
 pub mod prelude {
   pub use anyhow::{Context as _, anyhow, bail};
@@ -1,9 +1,23 @@
 use std::env;
+use std::sync::OnceLock;
 
+use anyhow::Context;
 use colog::format::CologStyle;
 use env_logger::Builder;
 use log::Level;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{KeyValue, global};
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tracing_subscriber::filter::LevelFilter;
 use tracing_subscriber::fmt::time::ChronoLocal;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// kept around so buffered spans can be flushed on shutdown
+static TRACER_PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
 
 pub struct LogStyle;
 
@@ -86,6 +100,53 @@ pub fn init_tracing_json() -> anyhow::Result<()> {
   Ok(())
 }
 
+/// json logs plus span export to an OTLP collector over http
+///
+/// the collector is configured with the standard `OTEL_EXPORTER_OTLP_ENDPOINT` (default `http://localhost:4318`)
+/// and `OTEL_SERVICE_NAME` variables
+pub fn init_tracing_otlp() -> anyhow::Result<()> {
+  set_env_log_level();
+
+  let exporter = SpanExporter::builder()
+    .with_http()
+    .build()
+    .context("failed to create OTLP exporter")?;
+
+  let mut resource = Resource::builder().with_attribute(KeyValue::new("service.version", env!("CARGO_PKG_VERSION")));
+  if env::var_os("OTEL_SERVICE_NAME").is_none() {
+    resource = resource.with_service_name(env!("CARGO_PKG_NAME"));
+  }
+
+  let provider = SdkTracerProvider::builder()
+    .with_batch_exporter(exporter)
+    .with_resource(resource.build())
+    .build();
+
+  let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+
+  // incoming and outgoing requests carry W3C `traceparent` headers
+  global::set_text_map_propagator(TraceContextPropagator::new());
+  global::set_tracer_provider(provider.clone());
+  let _ = TRACER_PROVIDER.set(provider);
+
+  tracing_subscriber::registry()
+    .with(tracing_subscriber::fmt::layer().json().flatten_event(true))
+    .with(tracing_opentelemetry::layer().with_tracer(tracer))
+    .with(LevelFilter::from_level(tracing_level_from_env()))
+    .try_init()?;
+
+  Ok(())
+}
+
+/// flushes spans that have not been exported yet, no-op unless OTLP export is enabled
+pub fn shutdown() {
+  if let Some(provider) = TRACER_PROVIDER.get()
+    && let Err(err) = provider.shutdown()
+  {
+    eprintln!("failed to flush spans: {err}");
+  }
+}
+
 pub fn init_human_readable() -> anyhow::Result<()> {
   set_env_log_level();
 
@@ -102,6 +163,7 @@ pub fn init_from_env() -> anyhow::Result<()> {
     "0" | "human" => init_human_readable(),
     "1" | "detailed" => init_tracing_human(),
     "2" | "json" => init_tracing_json(),
+    "3" | "otlp" => init_tracing_otlp(),
     _ => {
       eprintln!("invalid RUST_LOG_DETAIL value, defaulting to 0");
       init_human_readable()
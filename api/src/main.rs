@@ -1,40 +1,249 @@
+use std::path::Path;
+
 use anyhow::Context;
-use api::config::Config;
+use api::cli::{Cli, Command, ExportTypesArgs, ImportArgs, ModelsCommand, ModelsListArgs, ReplayArgs, TokensCommand};
+use api::config::{self, Config, ConfigError};
+use api::convex::api_tokens::{self, CreateTokenArgs};
+use api::convex::{Id, create_convex_client};
+use api::import::{self, ImportOptions};
 use api::logger;
+use api::openrouter::completions::OpenrouterEvent;
+use api::openrouter::create_openrouter_client;
+use api::openrouter::replay::replay_fixture;
 use api::prelude::*;
-use api::setup::Application;
-#[cfg(debug_assertions)]
-use api::types::export_types;
+use api::proxy::tokens::{generate_token, hash_token};
+use api::setup::{Application, app_router};
+use api::terminal::run_chat;
+use api::types::{ExportOptions, export_types};
+use clap::Parser;
+use futures::StreamExt;
+
+fn load_config(path: Option<&Path>) -> anyhow::Result<Config> {
+  Config::load_from(path).context("failed to load config")
+}
+
+async fn serve(config: Config) -> anyhow::Result<()> {
+  let app = Application::build(config).await?;
+
+  // keep the frontend types in sync while developing
+  #[cfg(debug_assertions)]
+  if let Some(path) = std::env::var_os("TYPES_PATH").filter(|path| !path.is_empty()) {
+    let options = ExportOptions {
+      path: path.into(),
+      format: Default::default(),
+      debug_location: false,
+    };
+    let routes = app.routes();
+
+    std::thread::spawn(move || {
+      if let Err(err) = export_types(&options, routes) {
+        error!("Failed to export types: {err:?}");
+      }
+    });
+  }
+
+  info!("Server starting on port {}", app.port());
+
+  app.run_until_stopped().await?;
+
+  Ok(())
+}
+
+fn run_export_types(args: ExportTypesArgs) -> anyhow::Result<()> {
+  let options = ExportOptions {
+    path: args.output,
+    format: args.format,
+    debug_location: args.debug_locations,
+  };
+
+  export_types(&options, app_router().routes)?;
+  info!("Types exported");
+
+  Ok(())
+}
+
+/// loads the config like the server would and lists every problem
+fn check_config(path: Option<&Path>) -> anyhow::Result<()> {
+  match path {
+    Some(path) => println!("Checking {} with environment overrides", path.display()),
+    None => println!("No config file found, checking environment only"),
+  }
+
+  match Config::load_from(path) {
+    Ok(config) => {
+      println!("{config:#?}");
+      println!("Config is valid");
+      Ok(())
+    }
+    Err(ConfigError::Invalid(problems)) => {
+      for problem in &problems {
+        println!("  - {problem}");
+      }
+      bail!("found {} config problems", problems.len());
+    }
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn list_models(config: &Config, args: ModelsListArgs) -> anyhow::Result<()> {
+  let openrouter = create_openrouter_client(&config.openrouter)?;
+  let mut models = openrouter.get_models().await.context("failed to list models")?.data;
+
+  if let Some(filter) = &args.filter {
+    models.retain(|model| model.id.contains(filter.as_str()));
+  }
+
+  models.sort_by(|a, b| a.id.cmp(&b.id));
+
+  let width = models.iter().map(|model| model.id.len()).max().unwrap_or_default();
+
+  println!(
+    "{:<width$}  {:>9}  {:>12}  {:>12}",
+    "model", "context", "prompt", "completion"
+  );
+
+  for model in models {
+    println!(
+      "{:<width$}  {:>9}  {:>12}  {:>12}",
+      model.id, model.context_length, model.pricing.prompt, model.pricing.completion
+    );
+  }
+
+  Ok(())
+}
+
+async fn replay(args: ReplayArgs) -> anyhow::Result<()> {
+  let fixture =
+    std::fs::read_to_string(&args.fixture).with_context(|| format!("failed to read {}", args.fixture.display()))?;
+
+  let (_trigger, stream) = replay_fixture(fixture).await?;
+  let mut stream = std::pin::pin!(stream);
+
+  let mut count = 0;
+
+  while let Some(event) = stream.next().await {
+    count += 1;
+
+    match event {
+      OpenrouterEvent::Completion(completion) => println!("{completion:?}"),
+      event => println!("{event:?}"),
+    }
+  }
+
+  info!("Replayed {count} events");
+
+  Ok(())
+}
+
+async fn run_import(config: &Config, args: ImportArgs) -> anyhow::Result<()> {
+  let path = args.path.display();
+
+  let data = std::fs::read(&args.path).with_context(|| format!("failed to read {path}"))?;
+  let (format, conversations) = import::parse(args.format, &data)?;
+
+  info!(
+    "Found {} {} conversations in {path}",
+    conversations.len(),
+    format.as_str()
+  );
+
+  let options = ImportOptions {
+    user_id: args.user,
+    dry_run: args.dry_run,
+    allow_duplicates: args.allow_duplicates,
+  };
+
+  let mut convex = create_convex_client(&config.convex).await?;
+  let results = import::import_conversations(&mut convex, format, conversations, &options).await?;
+
+  for result in results {
+    println!(
+      "{:<12} {:>4} messages  {}  {}",
+      format!("{:?}", result.status),
+      result.message_count,
+      result.source_id,
+      result.title.as_deref().unwrap_or("(untitled)")
+    );
+  }
+
+  Ok(())
+}
+
+async fn run_tokens(config: &Config, command: TokensCommand) -> anyhow::Result<()> {
+  let mut convex = create_convex_client(&config.convex).await?;
+
+  match command {
+    TokensCommand::Create(args) => {
+      let token = generate_token()?;
+
+      let args = CreateTokenArgs {
+        name: args.name,
+        token_hash: hash_token(&token),
+        user_id: args.user,
+      };
+
+      let id = api_tokens::create(&mut convex, &args).await?;
+
+      println!("Created token {id}, it will not be shown again:");
+      println!("{token}");
+    }
+    TokensCommand::List => {
+      for token in api_tokens::list(&mut convex).await? {
+        println!(
+          "{:<34} {:<24} {:<34} {}",
+          token.id,
+          token.name,
+          token.user_id.as_deref().unwrap_or("-"),
+          if token.revoked_at.is_some() {
+            "revoked"
+          } else {
+            "active"
+          }
+        );
+      }
+    }
+    TokensCommand::Revoke(args) => {
+      if !api_tokens::revoke(&mut convex, Id::new(args.id.clone())).await? {
+        bail!("no token with id {}", args.id);
+      }
+
+      println!("Revoked token {}", args.id);
+    }
+  }
+
+  Ok(())
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
   let _ = dotenvy::dotenv();
 
+  // parsed after loading `.env` so flags can fall back to it
+  let cli = Cli::parse();
+
   logger::init_from_env().context("failed to initialize logger")?;
 
   tracing::info!("hello awa");
 
-  let config = Config::from_env().context("failed to load config from env")?;
+  let result = run(cli).await;
 
-  let app = Application::build(config).await?;
-
-  #[cfg(debug_assertions)]
-  {
-    use std::env::args;
-    let show_location = args().any(|arg| arg == "--debug-types");
-    let routes = app.routes().clone();
-    let thread = std::thread::spawn(move || export_types(show_location, routes));
-
-    if args().any(|arg| arg == "--export-types") {
-      thread.join().expect("failed to join thread")?;
-      info!("Types exported");
-      return Ok(());
-    }
-  }
+  logger::shutdown();
 
-  info!("Server starting on port {}", app.port());
+  result
+}
 
-  app.run_until_stopped().await?;
+async fn run(cli: Cli) -> anyhow::Result<()> {
+  let config_path = cli.config.or_else(config::config_path);
+  let config_path = config_path.as_deref();
 
-  Ok(())
+  match cli.command.unwrap_or(Command::Serve) {
+    Command::Serve => serve(load_config(config_path)?).await,
+    Command::ExportTypes(args) => run_export_types(args),
+    Command::CheckConfig => check_config(config_path),
+    Command::Models(ModelsCommand::List(args)) => list_models(&load_config(config_path)?, args).await,
+    Command::Replay(args) => replay(args).await,
+    Command::Import(args) => run_import(&load_config(config_path)?, args).await,
+    Command::Chat(args) => run_chat(&load_config(config_path)?, args).await,
+    Command::Tokens(command) => run_tokens(&load_config(config_path)?, command).await,
+  }
 }
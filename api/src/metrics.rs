@@ -0,0 +1,174 @@
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+
+/// buckets in seconds for time to first token and request latencies
+const LATENCY_BUCKETS: &[f64] = &[0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+/// buckets in seconds for full generations, reasoning models can take minutes
+const DURATION_BUCKETS: &[f64] = &[1.0, 2.5, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0, 300.0];
+const TOKENS_PER_SECOND_BUCKETS: &[f64] = &[5.0, 10.0, 20.0, 40.0, 60.0, 80.0, 120.0, 160.0, 250.0, 500.0];
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub struct Metrics {
+  registry: Registry,
+
+  pub time_to_first_token: HistogramVec,
+  pub generation_duration: HistogramVec,
+  pub tokens_per_second: HistogramVec,
+
+  pub completions: IntCounterVec,
+  pub cancellations: IntCounterVec,
+  pub refusals: IntCounterVec,
+  /// labelled with the `StreamChatError` variant, or the stream event that ended the generation
+  pub generation_errors: IntCounterVec,
+  pub active_generations: IntGauge,
+
+  pub convex_request_duration: HistogramVec,
+  pub openrouter_request_duration: HistogramVec,
+}
+
+fn histogram(registry: &Registry, name: &str, help: &str, buckets: &[f64], labels: &[&str]) -> HistogramVec {
+  let histogram = HistogramVec::new(HistogramOpts::new(name, help).buckets(buckets.to_vec()), labels)
+    .expect("invalid histogram definition");
+  registry
+    .register(Box::new(histogram.clone()))
+    .expect("histogram registered twice");
+  histogram
+}
+
+fn counter(registry: &Registry, name: &str, help: &str, labels: &[&str]) -> IntCounterVec {
+  let counter = IntCounterVec::new(Opts::new(name, help), labels).expect("invalid counter definition");
+  registry
+    .register(Box::new(counter.clone()))
+    .expect("counter registered twice");
+  counter
+}
+
+impl Metrics {
+  fn new() -> Self {
+    let registry = Registry::new_custom(Some("chat".into()), None).expect("invalid registry prefix");
+
+    let time_to_first_token = histogram(
+      &registry,
+      "time_to_first_token_seconds",
+      "Time from starting a generation to the first streamed token",
+      LATENCY_BUCKETS,
+      &["model"],
+    );
+    let generation_duration = histogram(
+      &registry,
+      "generation_duration_seconds",
+      "Total duration of completed generations",
+      DURATION_BUCKETS,
+      &["model"],
+    );
+    let tokens_per_second = histogram(
+      &registry,
+      "generation_tokens_per_second",
+      "Output tokens per second of completed generations",
+      TOKENS_PER_SECOND_BUCKETS,
+      &["model"],
+    );
+
+    let completions = counter(
+      &registry,
+      "completions_total",
+      "Generations that finished normally",
+      &["model"],
+    );
+    let cancellations = counter(
+      &registry,
+      "cancellations_total",
+      "Generations cancelled by the user",
+      &["model"],
+    );
+    let refusals = counter(
+      &registry,
+      "refusals_total",
+      "Generations refused by the model",
+      &["model"],
+    );
+    let generation_errors = counter(&registry, "generation_errors_total", "Failed generations", &["error"]);
+
+    let active_generations =
+      IntGauge::new("active_generations", "Generations currently streaming").expect("invalid gauge definition");
+    registry
+      .register(Box::new(active_generations.clone()))
+      .expect("gauge registered twice");
+
+    let convex_request_duration = histogram(
+      &registry,
+      "convex_request_duration_seconds",
+      "Latency of Convex queries and mutations",
+      LATENCY_BUCKETS,
+      &["function", "outcome"],
+    );
+    let openrouter_request_duration = histogram(
+      &registry,
+      "openrouter_request_duration_seconds",
+      "Latency of OpenRouter requests until the response headers arrive",
+      LATENCY_BUCKETS,
+      &["operation", "outcome"],
+    );
+
+    Self {
+      registry,
+      time_to_first_token,
+      generation_duration,
+      tokens_per_second,
+      completions,
+      cancellations,
+      refusals,
+      generation_errors,
+      active_generations,
+      convex_request_duration,
+      openrouter_request_duration,
+    }
+  }
+
+  /// prometheus text exposition format
+  pub fn encode(&self) -> Result<String, prometheus::Error> {
+    let mut buffer = vec![];
+    TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+  }
+}
+
+fn outcome(ok: bool) -> &'static str {
+  if ok { "ok" } else { "error" }
+}
+
+pub fn observe_convex(function: &str, ok: bool, elapsed: Duration) {
+  METRICS
+    .convex_request_duration
+    .with_label_values(&[function, outcome(ok)])
+    .observe(elapsed.as_secs_f64());
+}
+
+pub fn observe_openrouter(operation: &str, ok: bool, elapsed: Duration) {
+  METRICS
+    .openrouter_request_duration
+    .with_label_values(&[operation, outcome(ok)])
+    .observe(elapsed.as_secs_f64());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_encode_metrics() {
+    METRICS.completions.with_label_values(&["openai/gpt-4o"]).inc();
+    observe_convex("messages:apiAppend", true, Duration::from_millis(30));
+
+    let text = METRICS.encode().unwrap();
+
+    assert!(text.contains(r#"chat_completions_total{model="openai/gpt-4o"}"#));
+    assert!(text.contains(
+      r#"chat_convex_request_duration_seconds_bucket{function="messages:apiAppend",outcome="ok",le="0.05"} 1"#
+    ));
+  }
+}
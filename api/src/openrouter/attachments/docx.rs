@@ -0,0 +1,71 @@
+use std::io::{Cursor, Read};
+
+use anyhow::Context;
+use quick_xml::Reader;
+use quick_xml::events::Event;
+
+/// plain text of the document body, one line per paragraph or table row with cells separated by tabs
+pub fn extract_text(bytes: &[u8]) -> anyhow::Result<String> {
+  let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).context("not a docx file")?;
+
+  let mut xml = String::new();
+  archive
+    .by_name("word/document.xml")
+    .context("docx has no document body")?
+    .read_to_string(&mut xml)?;
+
+  let mut reader = Reader::from_str(&xml);
+  let mut text = String::new();
+  let mut in_text = false;
+  let mut in_cell = false;
+
+  loop {
+    match reader.read_event()? {
+      Event::Start(tag) => match tag.local_name().as_ref() {
+        b"t" => in_text = true,
+        b"tc" => in_cell = true,
+        _ => {}
+      },
+      Event::End(tag) => match tag.local_name().as_ref() {
+        b"t" => in_text = false,
+        b"p" if in_cell => text.push(' '),
+        b"p" => text.push('\n'),
+        b"tc" => {
+          in_cell = false;
+          text.truncate(text.trim_end_matches(' ').len());
+          text.push('\t');
+        }
+        b"tr" => {
+          text.pop();
+          text.push('\n');
+        }
+        _ => {}
+      },
+      Event::Empty(tag) => match tag.local_name().as_ref() {
+        b"tab" => text.push('\t'),
+        b"br" | b"cr" => text.push('\n'),
+        _ => {}
+      },
+      Event::Text(content) if in_text => text.push_str(&content.unescape()?),
+      Event::Eof => break,
+      _ => {}
+    }
+  }
+
+  Ok(text.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_extract_docx_text() {
+    let docx = include_bytes!("../../../test_data/document.docx");
+
+    assert_eq!(
+      extract_text(docx).unwrap(),
+      "Quarterly report\nRevenue grew by 12% & costs fell.\nRegion\tTotal\nEU\t42"
+    );
+  }
+}
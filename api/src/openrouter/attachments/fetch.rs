@@ -0,0 +1,316 @@
+use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+
+use anyhow::Context;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect::Policy;
+use reqwest::{Client, Url};
+
+use super::AttachmentError;
+use crate::config::AttachmentsConfig;
+
+/// why a download was refused before anything was requested from the host
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum Blocked {
+  #[error("only http and https links are allowed")]
+  Scheme,
+  #[error("{0} is not an allowed host")]
+  Host(String),
+  #[error("{0} points to a private address")]
+  PrivateAddress(String),
+  #[error("it redirects more than {0} times")]
+  Redirects(usize),
+}
+
+/// downloads attachments from the allowed hosts only, every redirect and resolved address is checked as well
+#[derive(Debug, Clone)]
+pub struct AttachmentFetcher {
+  client: Client,
+  pub config: Arc<AttachmentsConfig>,
+}
+
+impl AttachmentFetcher {
+  pub fn new(config: AttachmentsConfig) -> anyhow::Result<Self> {
+    let config = Arc::new(config);
+    let redirect_config = config.clone();
+
+    let client = Client::builder()
+      .connect_timeout(config.connect_timeout)
+      .read_timeout(config.read_timeout)
+      // a proxy would resolve hosts itself and skip the address check
+      .no_proxy()
+      .dns_resolver(Arc::new(PublicResolver { config: config.clone() }))
+      .redirect(Policy::custom(move |attempt| {
+        // the first url is the original request
+        if attempt.previous().len() > redirect_config.max_redirects {
+          return attempt.error(Blocked::Redirects(redirect_config.max_redirects));
+        }
+
+        match check_url(&redirect_config, attempt.url()) {
+          Ok(()) => attempt.follow(),
+          Err(err) => attempt.error(err),
+        }
+      }))
+      .build()
+      .context("failed to create HTTP client for attachments")?;
+
+    Ok(Self { client, config })
+  }
+
+  /// streams the response and stops as soon as it turns out to be larger than `limit`
+  pub async fn download(&self, url: &str, limit: u64) -> Result<Vec<u8>, AttachmentError> {
+    let url = Url::parse(url).map_err(|err| AttachmentError::Download(err.to_string()))?;
+    check_url(&self.config, &url)?;
+
+    let mut response = self.client.get(url).send().await.map_err(download_error)?;
+
+    if !response.status().is_success() {
+      return Err(AttachmentError::Download(format!("status {}", response.status())));
+    }
+
+    if response.content_length().is_some_and(|length| length > limit) {
+      return Err(AttachmentError::TooLarge { limit });
+    }
+
+    let mut bytes = Vec::with_capacity(response.content_length().unwrap_or_default() as usize);
+
+    while let Some(chunk) = response.chunk().await.map_err(download_error)? {
+      if (bytes.len() + chunk.len()) as u64 > limit {
+        return Err(AttachmentError::TooLarge { limit });
+      }
+
+      bytes.extend_from_slice(&chunk);
+    }
+
+    Ok(bytes)
+  }
+}
+
+/// blocks raised by the resolver or the redirect policy come back wrapped in the request error
+fn download_error(err: reqwest::Error) -> AttachmentError {
+  let mut source: Option<&(dyn Error + 'static)> = Some(&err);
+  let mut blocked = None;
+
+  while let Some(current) = source {
+    if let Some(found) = current.downcast_ref::<Blocked>() {
+      blocked = Some(found.clone());
+      break;
+    }
+
+    source = current.source();
+  }
+
+  match blocked {
+    Some(blocked) => blocked.into(),
+    None if err.is_timeout() => AttachmentError::Download("timed out".into()),
+    None => AttachmentError::Download(err.without_url().to_string()),
+  }
+}
+
+/// hosts given as ip addresses never reach the resolver, so they are checked here
+fn check_url(config: &AttachmentsConfig, url: &Url) -> Result<(), Blocked> {
+  if !matches!(url.scheme(), "http" | "https") {
+    return Err(Blocked::Scheme);
+  }
+
+  let name = url.host_str().unwrap_or_default();
+
+  if !config.allows_host(name) {
+    return Err(Blocked::Host(name.into()));
+  }
+
+  // ipv6 hosts keep their brackets
+  let Ok(ip) = name.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() else {
+    return Ok(());
+  };
+
+  if !is_public(ip) && !config.allows_private_address(name) {
+    return Err(Blocked::PrivateAddress(name.into()));
+  }
+
+  Ok(())
+}
+
+/// rejects names with any non public address, so a second record can't point back inside
+struct PublicResolver {
+  config: Arc<AttachmentsConfig>,
+}
+
+impl Resolve for PublicResolver {
+  fn resolve(&self, name: Name) -> Resolving {
+    let host = name.as_str().to_string();
+    let allows_private = self.config.allows_private_address(&host);
+
+    Box::pin(async move {
+      let addresses = tokio::net::lookup_host((host.as_str(), 0)).await?.collect::<Vec<_>>();
+
+      if !allows_private && addresses.iter().any(|address| !is_public(address.ip())) {
+        return Err(Blocked::PrivateAddress(host).into());
+      }
+
+      Ok(Box::new(addresses.into_iter()) as Addrs)
+    })
+  }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+  match ip {
+    IpAddr::V4(ip) => is_public_v4(ip),
+    IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+      Some(ip) => is_public_v4(ip),
+      None => is_public_v6(ip),
+    },
+  }
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+  let segments = ip.segments();
+
+  // nat64 64:ff9b::/96 reaches arbitrary v4 addresses through the translator
+  if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+    return false;
+  }
+
+  // 6to4 2002::/16 embeds the v4 address of the relay in the next 32 bits
+  if segments[0] == 0x2002 {
+    let [high, low] = [segments[1], segments[2]];
+    return is_public_v4(Ipv4Addr::from((u32::from(high) << 16) | u32::from(low)));
+  }
+
+  !(ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() || ip.is_unique_local() || ip.is_unicast_link_local())
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+  let [first, second, ..] = ip.octets();
+
+  // 0.0.0.0/8, the carrier grade nat range 100.64.0.0/10, the benchmarking range 198.18.0.0/15 and the
+  // reserved 240.0.0.0/4 (which includes broadcast)
+  let reserved =
+    first == 0 || (first == 100 && second & 0xc0 == 64) || (first == 198 && second & 0xfe == 18) || first >= 240;
+
+  !(reserved
+    || ip.is_private()
+    || ip.is_loopback()
+    || ip.is_link_local()
+    || ip.is_broadcast()
+    || ip.is_multicast()
+    || ip.is_documentation())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::BTreeMap;
+  use std::time::Duration;
+
+  use super::*;
+
+  fn config(allowed_hosts: &[&str], private_hosts: &[&str]) -> AttachmentsConfig {
+    AttachmentsConfig {
+      max_bytes: 1024,
+      limits: BTreeMap::new(),
+      max_image_dimension: 2048,
+      image_dimensions: BTreeMap::new(),
+      allowed_hosts: allowed_hosts.iter().map(|host| host.to_string()).collect(),
+      private_hosts: private_hosts.iter().map(|host| host.to_string()).collect(),
+      connect_timeout: Duration::from_secs(1),
+      read_timeout: Duration::from_secs(1),
+      max_redirects: 3,
+      max_concurrent_downloads: 1,
+    }
+  }
+
+  fn check(config: &AttachmentsConfig, url: &str) -> Result<(), Blocked> {
+    check_url(config, &url.parse().unwrap())
+  }
+
+  #[test]
+  fn test_check_url() {
+    let config = config(&["*.convex.cloud", "127.0.0.1", "10.0.0.8"], &["10.0.0.8"]);
+
+    assert!(check(&config, "https://happy-otter-123.convex.cloud/api/storage/1").is_ok());
+    assert!(check(&config, "http://10.0.0.8/file").is_ok());
+    assert!(matches!(
+      check(&config, "ftp://happy-otter-123.convex.cloud/file"),
+      Err(Blocked::Scheme)
+    ));
+    assert!(matches!(
+      check(&config, "https://example.com/file"),
+      Err(Blocked::Host(host)) if host == "example.com"
+    ));
+    assert!(matches!(
+      check(&config, "http://127.0.0.1:3210/file"),
+      Err(Blocked::PrivateAddress(_))
+    ));
+  }
+
+  #[test]
+  fn test_is_public() {
+    for ip in ["8.8.8.8", "2606:4700::1111"] {
+      assert!(is_public(ip.parse().unwrap()), "{ip}");
+    }
+
+    for ip in [
+      "127.0.0.1",
+      "10.1.2.3",
+      "172.16.0.1",
+      "192.168.1.1",
+      "169.254.169.254",
+      "100.64.0.1",
+      "0.0.0.0",
+      "::1",
+      "fd00::1",
+      "fe80::1",
+      "::ffff:127.0.0.1",
+    ] {
+      assert!(!is_public(ip.parse().unwrap()), "{ip}");
+    }
+  }
+
+  #[test]
+  fn test_is_public_nat64() {
+    assert!(!is_public("64:ff9b::a9fe:a9fe".parse().unwrap()));
+    assert!(!is_public("64:ff9b::808:808".parse().unwrap()));
+  }
+
+  #[test]
+  fn test_is_public_6to4() {
+    // 2002:7f00:1:: embeds 127.0.0.1, 2002:a9fe:a9fe:: embeds 169.254.169.254
+    assert!(!is_public("2002:7f00:1::1".parse().unwrap()));
+    assert!(!is_public("2002:a9fe:a9fe::".parse().unwrap()));
+    assert!(is_public("2002:808:808::1".parse().unwrap()));
+  }
+
+  #[test]
+  fn test_is_public_benchmarking() {
+    assert!(!is_public("198.18.0.1".parse().unwrap()));
+    assert!(!is_public("198.19.255.254".parse().unwrap()));
+    assert!(is_public("198.20.0.1".parse().unwrap()));
+  }
+
+  #[test]
+  fn test_is_public_reserved() {
+    assert!(!is_public("240.0.0.1".parse().unwrap()));
+    assert!(!is_public("255.255.255.255".parse().unwrap()));
+    assert!(!is_public("::ffff:250.1.2.3".parse().unwrap()));
+  }
+
+  #[tokio::test]
+  async fn test_resolver_blocks_private_addresses() {
+    let resolver = PublicResolver {
+      config: Arc::new(config(&["localhost"], &[])),
+    };
+
+    let err = resolver.resolve("localhost".parse().unwrap()).await.err().unwrap();
+    assert!(matches!(
+      err.downcast_ref::<Blocked>(),
+      Some(Blocked::PrivateAddress(_))
+    ));
+
+    let resolver = PublicResolver {
+      config: Arc::new(config(&["localhost"], &["localhost"])),
+    };
+
+    assert!(resolver.resolve("localhost".parse().unwrap()).await.is_ok());
+  }
+}
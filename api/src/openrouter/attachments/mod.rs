@@ -0,0 +1,293 @@
+use std::io::Cursor;
+
+use anyhow::Context;
+use base64::Engine;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{ImageFormat, ImageReader};
+
+use crate::openrouter::types::{ContentPart, File, ImageUrl, InputAudio};
+
+mod docx;
+mod fetch;
+mod spreadsheet;
+
+pub use fetch::{AttachmentFetcher, Blocked};
+
+const DOCX: &str = "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+const XLSX: &str = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+const XLS: &str = "application/vnd.ms-excel";
+
+/// quality of re-encoded photos, visually lossless for models at a fraction of the size
+const JPEG_QUALITY: u8 = 85;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AttachmentError {
+  #[error("{0} files are not supported")]
+  Unsupported(String),
+  #[error("the selected model does not accept audio")]
+  AudioNotAccepted,
+  #[error("the file is larger than the {} limit for its type", format_bytes(*.limit))]
+  TooLarge { limit: u64 },
+  #[error("the file details could not be loaded: {0}")]
+  Lookup(String),
+  #[error("the file could not be downloaded: {0}")]
+  Download(String),
+  #[error("the file was not downloaded, {0}")]
+  Blocked(#[from] Blocked),
+  #[error("the file could not be read: {0}")]
+  Extract(#[from] anyhow::Error),
+}
+
+impl AttachmentError {
+  /// stable label for clients
+  pub fn code(&self) -> &'static str {
+    match self {
+      AttachmentError::Unsupported(_) => "unsupported",
+      AttachmentError::AudioNotAccepted => "audio_not_accepted",
+      AttachmentError::TooLarge { .. } => "too_large",
+      AttachmentError::Lookup(_) => "lookup",
+      AttachmentError::Download(_) => "download",
+      AttachmentError::Blocked(_) => "blocked",
+      AttachmentError::Extract(_) => "extract",
+    }
+  }
+}
+
+fn format_bytes(bytes: u64) -> String {
+  match bytes {
+    0..1024 => format!("{bytes} B"),
+    1024..1_048_576 => format!("{:.1} KB", bytes as f64 / 1024.0),
+    _ => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ExtractOptions {
+  /// longest side images are downscaled to
+  pub max_image_dimension: u32,
+}
+
+/// turns an attachment into something the model can read, picked by mime type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Extractor {
+  /// png, jpeg and webp, downscaled when larger than the model uses
+  Image,
+  /// first frame, downscaled like other images
+  Gif,
+  Pdf,
+  /// plain text and json
+  Text,
+  Docx,
+  /// xlsx and xls, every sheet rendered as a markdown table
+  Spreadsheet,
+  Csv,
+  /// converted to markdown
+  Html,
+  /// wav and mp3, only for models that list audio input
+  Audio,
+}
+
+impl Extractor {
+  pub fn for_mime_type(mime_type: &str) -> Option<Self> {
+    let extractor = match mime_type {
+      "image/png" | "image/jpeg" | "image/webp" => Extractor::Image,
+      "image/gif" => Extractor::Gif,
+      "application/pdf" => Extractor::Pdf,
+      DOCX => Extractor::Docx,
+      XLSX | XLS => Extractor::Spreadsheet,
+      "text/csv" => Extractor::Csv,
+      "text/html" | "application/xhtml+xml" => Extractor::Html,
+      "audio/wav" | "audio/x-wav" | "audio/wave" | "audio/mpeg" | "audio/mp3" => Extractor::Audio,
+      "application/json" => Extractor::Text,
+      _ if mime_type.starts_with("text/") => Extractor::Text,
+      _ => return None,
+    };
+
+    Some(extractor)
+  }
+
+  pub fn extract(
+    self,
+    name: String,
+    mime_type: &str,
+    bytes: &[u8],
+    options: &ExtractOptions,
+  ) -> Result<ContentPart, AttachmentError> {
+    let part = match self {
+      Extractor::Image | Extractor::Gif => {
+        let url = match fit_image(mime_type, bytes, options.max_image_dimension)? {
+          Some((mime_type, bytes)) => data_url(mime_type, &bytes),
+          None => data_url(mime_type, bytes),
+        };
+
+        ContentPart::Image {
+          image_url: ImageUrl { url },
+        }
+      }
+      Extractor::Pdf => ContentPart::File {
+        file: File {
+          filename: name,
+          file_data: data_url(mime_type, bytes),
+        },
+      },
+      Extractor::Text => text(String::from_utf8_lossy(bytes).to_string()),
+      Extractor::Docx => text(docx::extract_text(bytes)?),
+      Extractor::Spreadsheet => text(spreadsheet::workbook_to_markdown(bytes)?),
+      Extractor::Csv => text(spreadsheet::csv_to_markdown(bytes)?),
+      Extractor::Html => text(html2md::parse_html(&String::from_utf8_lossy(bytes))),
+      Extractor::Audio => ContentPart::InputAudio {
+        input_audio: InputAudio {
+          data: base64::engine::general_purpose::STANDARD.encode(bytes),
+          format: if mime_type == "audio/mpeg" || mime_type == "audio/mp3" {
+            "mp3"
+          } else {
+            "wav"
+          }
+          .into(),
+        },
+      },
+    };
+
+    Ok(part)
+  }
+}
+
+fn text(text: String) -> ContentPart {
+  ContentPart::Text { text }
+}
+
+fn data_url(mime_type: &str, bytes: &[u8]) -> String {
+  let base64 = base64::engine::general_purpose::STANDARD.encode(bytes);
+
+  format!("data:{mime_type};base64,{base64}")
+}
+
+/// re-encodes images larger than `max_dimension` and gifs, which models only take as a still frame.
+/// returns `None` when the original can be sent as is
+fn fit_image(mime_type: &str, bytes: &[u8], max_dimension: u32) -> anyhow::Result<Option<(&'static str, Vec<u8>)>> {
+  let format = ImageFormat::from_mime_type(mime_type).context("unknown image type")?;
+
+  // only the header is read to decide
+  let (width, height) = ImageReader::with_format(Cursor::new(bytes), format).into_dimensions()?;
+  let oversized = width.max(height) > max_dimension;
+
+  if !oversized && format != ImageFormat::Gif {
+    return Ok(None);
+  }
+
+  // the first frame for animations
+  let mut image = image::load_from_memory_with_format(bytes, format)?;
+
+  if oversized {
+    image = image.resize(max_dimension, max_dimension, FilterType::Triangle);
+  }
+
+  let mut encoded = Cursor::new(vec![]);
+
+  // transparency needs png, everything else is smaller as jpeg
+  let mime_type = if image.color().has_alpha() {
+    image.write_to(&mut encoded, ImageFormat::Png)?;
+    "image/png"
+  } else {
+    image
+      .to_rgb8()
+      .write_with_encoder(JpegEncoder::new_with_quality(&mut encoded, JPEG_QUALITY))?;
+    "image/jpeg"
+  };
+
+  Ok(Some((mime_type, encoded.into_inner())))
+}
+
+pub fn attachment_part(
+  name: String,
+  mime_type: &str,
+  bytes: &[u8],
+  options: &ExtractOptions,
+) -> Result<ContentPart, AttachmentError> {
+  let extractor = Extractor::for_mime_type(mime_type).ok_or_else(|| AttachmentError::Unsupported(mime_type.into()))?;
+
+  extractor.extract(name, mime_type, bytes, options)
+}
+
+/// text shown to the model in place of an attachment it can't read, so it can tell the user
+pub fn skipped_notice(name: &str, err: &AttachmentError) -> ContentPart {
+  text(format!("[Attachment \"{name}\" was not included: {err}]"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const OPTIONS: ExtractOptions = ExtractOptions {
+    max_image_dimension: 2048,
+  };
+
+  #[test]
+  fn test_extractor_for_mime_type() {
+    assert_eq!(Extractor::for_mime_type("text/markdown"), Some(Extractor::Text));
+    assert_eq!(Extractor::for_mime_type("text/csv"), Some(Extractor::Csv));
+    assert_eq!(Extractor::for_mime_type(DOCX), Some(Extractor::Docx));
+    assert_eq!(Extractor::for_mime_type("audio/mpeg"), Some(Extractor::Audio));
+    assert_eq!(Extractor::for_mime_type("application/zip"), None);
+  }
+
+  #[test]
+  fn test_gif_first_frame() {
+    let gif = include_bytes!("../../../test_data/animation.gif");
+
+    let part = attachment_part("animation.gif".into(), "image/gif", gif, &OPTIONS).unwrap();
+    let ContentPart::Image { image_url } = part else {
+      panic!("expected an image part");
+    };
+
+    assert!(image_url.url.starts_with("data:image/png;base64,"));
+  }
+
+  #[test]
+  fn test_html_to_markdown() {
+    let part = attachment_part(
+      "page.html".into(),
+      "text/html",
+      b"<h1>Title</h1><p>Some <b>bold</b> text</p>",
+      &OPTIONS,
+    )
+    .unwrap();
+    let ContentPart::Text { text } = part else {
+      panic!("expected a text part");
+    };
+
+    assert!(text.contains("Title\n=========="), "{text}");
+    assert!(text.contains("Some **bold** text"), "{text}");
+  }
+
+  #[test]
+  fn test_fit_image() {
+    let mut png = Cursor::new(vec![]);
+    image::RgbImage::new(3000, 1000)
+      .write_to(&mut png, ImageFormat::Png)
+      .unwrap();
+    let png = png.into_inner();
+
+    let (mime_type, bytes) = fit_image("image/png", &png, 2048).unwrap().unwrap();
+    let image = image::load_from_memory(&bytes).unwrap();
+
+    assert_eq!(mime_type, "image/jpeg");
+    assert_eq!((image.width(), image.height()), (2048, 683));
+
+    // small enough images are sent unchanged
+    assert!(fit_image("image/png", &png, 3000).unwrap().is_none());
+  }
+
+  #[test]
+  fn test_too_large_message() {
+    let err = AttachmentError::TooLarge {
+      limit: 10 * 1024 * 1024,
+    };
+
+    assert_eq!(
+      err.to_string(),
+      "the file is larger than the 10.0 MB limit for its type"
+    );
+  }
+}
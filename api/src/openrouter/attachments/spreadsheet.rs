@@ -0,0 +1,91 @@
+use std::io::Cursor;
+
+use anyhow::Context;
+use calamine::Reader;
+
+/// every sheet as a markdown table under its name, the first row is used as the header
+pub fn workbook_to_markdown(bytes: &[u8]) -> anyhow::Result<String> {
+  let mut workbook = calamine::open_workbook_auto_from_rs(Cursor::new(bytes)).context("not a spreadsheet")?;
+
+  let sheets = workbook
+    .worksheets()
+    .into_iter()
+    .map(|(name, range)| {
+      let rows = range
+        .rows()
+        .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+        .collect::<Vec<_>>();
+
+      format!("## {name}\n\n{}", markdown_table(&rows))
+    })
+    .collect::<Vec<_>>();
+
+  Ok(sheets.join("\n\n"))
+}
+
+pub fn csv_to_markdown(bytes: &[u8]) -> anyhow::Result<String> {
+  let rows = csv::ReaderBuilder::new()
+    .has_headers(false)
+    .flexible(true)
+    .from_reader(bytes)
+    .records()
+    .map(|record| record.map(|record| record.iter().map(str::to_string).collect()))
+    .collect::<Result<Vec<_>, _>>()
+    .context("invalid csv")?;
+
+  Ok(markdown_table(&rows))
+}
+
+fn markdown_table(rows: &[Vec<String>]) -> String {
+  let Some((header, rows)) = rows.split_first() else {
+    return String::new();
+  };
+
+  let columns = std::iter::once(header)
+    .chain(rows)
+    .map(Vec::len)
+    .max()
+    .unwrap_or_default();
+
+  let line = |row: &Vec<String>| {
+    let cells = (0..columns)
+      .map(|i| row.get(i).map(|cell| escape_cell(cell)).unwrap_or_default())
+      .collect::<Vec<_>>();
+
+    format!("| {} |", cells.join(" | "))
+  };
+
+  let mut lines = vec![line(header), format!("|{}", " --- |".repeat(columns))];
+  lines.extend(rows.iter().map(line));
+
+  lines.join("\n")
+}
+
+fn escape_cell(cell: &str) -> String {
+  cell.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_csv_to_markdown() {
+    let csv = b"name,amount\nwidget,3\n\"pipe | and\nnewline\",4,extra\n";
+
+    assert_eq!(
+      csv_to_markdown(csv).unwrap(),
+      "| name | amount |  |\n| --- | --- | --- |\n| widget | 3 |  |\n| pipe \\| and newline | 4 | extra |"
+    );
+  }
+
+  #[test]
+  fn test_xlsx_to_markdown() {
+    let xlsx = include_bytes!("../../../test_data/sheet.xlsx");
+
+    assert_eq!(
+      workbook_to_markdown(xlsx).unwrap(),
+      "## Sales\n\n| Region | Total |\n| --- | --- |\n| EU | 42 |\n| US | 17.5 |"
+    );
+  }
+}
@@ -1,18 +1,22 @@
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::Context;
-use futures::Stream;
-use reqwest::Method;
+use futures::{Stream, StreamExt};
+use reqwest::{Method, RequestBuilder};
+use reqwest_eventsource::retry::RetryPolicy as EventSourceRetryPolicy;
 use reqwest_eventsource::{Error as EventSourceError, Event as EventSourceEvent, EventSource};
 use stream_cancel::{Trigger, Valved};
 use tokio::sync::Mutex;
 
+use crate::metrics;
 use crate::openrouter::types::{
   ChatCompletion, CompletionRequest, CompletionResponse, MessageRequest, PdfOptions, PluginRequest, ReasoningEffort,
   ReasoningRequest, UsageRequest,
 };
-use crate::openrouter::{OpenrouterClient, OpenrouterError};
+use crate::openrouter::{ModelRouting, OpenrouterClient, OpenrouterError};
 use crate::prelude::*;
+use crate::retry::{self, RetryClass, RetryPolicy, Retryable};
 
 const COMPLETIONS_PATH: &str = "chat/completions";
 
@@ -23,37 +27,82 @@ fn pdf_plugin() -> PluginRequest {
   }
 }
 
-pub async fn get_completions(
+#[derive(Clone, Default)]
+pub struct CompletionOptions {
+  pub max_tokens: Option<u32>,
+  pub reasoning: Option<ReasoningEffort>,
+  /// reasoning token budget, replaces `reasoning` for models that take a budget
+  pub reasoning_max_tokens: Option<u32>,
+  /// reason without returning the reasoning
+  pub exclude_reasoning: bool,
+  pub enable_pdf: bool,
+  pub routing: ModelRouting,
+}
+
+async fn completion_request(
   client: Arc<Mutex<OpenrouterClient>>,
   model: &str,
   messages: Vec<MessageRequest>,
   custom_key: Option<String>,
-  max_tokens: Option<u32>,
-  reasoning: Option<ReasoningEffort>,
-  enable_pdf: bool,
-) -> Result<CompletionResponse, OpenrouterError> {
+  options: CompletionOptions,
+  stream: bool,
+) -> Result<RequestBuilder, OpenrouterError> {
   let client = client.lock().await;
   let builder = client.request_builder(Method::POST, COMPLETIONS_PATH, custom_key)?;
+  let (models, provider) = client.routing.resolve(model, options.routing);
   drop(client);
 
-  let plugins = if enable_pdf { vec![pdf_plugin()] } else { vec![] };
+  let plugins = if options.enable_pdf { vec![pdf_plugin()] } else { vec![] };
+
+  let reasoning = (options.reasoning.is_some() || options.reasoning_max_tokens.is_some() || options.exclude_reasoning)
+    .then(|| ReasoningRequest {
+      effort: options.reasoning.filter(|_| options.reasoning_max_tokens.is_none()),
+      max_tokens: options.reasoning_max_tokens,
+      exclude: options.exclude_reasoning,
+    });
 
   let request = CompletionRequest {
     model: model.to_string(),
+    models,
     messages,
-    max_tokens,
-    reasoning: reasoning.map(|effort| ReasoningRequest { effort }),
-    stream: false,
+    provider,
+    max_tokens: options.max_tokens,
+    reasoning,
+    stream,
     usage: Some(UsageRequest { include: true }),
     plugins,
   };
-  let response = builder.json(&request).send().await?;
 
-  if !response.status().is_success() {
-    return Err(OpenrouterError::NotOk(response.status(), response.text().await.ok()));
-  }
+  Ok(builder.json(&request))
+}
+
+#[tracing::instrument("openrouter completions", skip_all, fields(otel.kind = "client", model = model))]
+pub async fn get_completions(
+  client: Arc<Mutex<OpenrouterClient>>,
+  model: &str,
+  messages: Vec<MessageRequest>,
+  custom_key: Option<String>,
+  options: CompletionOptions,
+) -> Result<CompletionResponse, OpenrouterError> {
+  let request = completion_request(client, model, messages, custom_key, options, false).await?;
+
+  retry::retry(&RetryPolicy::default(), "openrouter completions", || async {
+    let started = Instant::now();
+    let response = request.try_clone().context("request is not cloneable")?.send().await;
+    metrics::observe_openrouter(
+      "completions",
+      response.as_ref().is_ok_and(|r| r.status().is_success()),
+      started.elapsed(),
+    );
+    let response = response?;
+
+    if !response.status().is_success() {
+      return Err(OpenrouterError::from_response(response).await);
+    }
 
-  Ok(response.json().await?)
+    Ok(response.json().await?)
+  })
+  .await
 }
 
 pub async fn stream_completions(
@@ -61,31 +110,43 @@ pub async fn stream_completions(
   model: &str,
   messages: Vec<MessageRequest>,
   custom_key: Option<String>,
-  max_tokens: Option<u32>,
-  reasoning: Option<ReasoningEffort>,
-  enable_pdf: bool,
-) -> Result<EventSource, OpenrouterError> {
-  let client = client.lock().await;
-  let builder = client.request_builder(Method::POST, COMPLETIONS_PATH, custom_key)?;
-  drop(client);
+  options: CompletionOptions,
+) -> Result<RequestBuilder, OpenrouterError> {
+  completion_request(client, model, messages, custom_key, options, true).await
+}
 
-  let plugins = if enable_pdf { vec![pdf_plugin()] } else { vec![] };
+/// the event source would otherwise reconnect on its own and send the whole request again
+struct NoReconnect;
 
-  let request = CompletionRequest {
-    model: model.to_string(),
-    messages,
-    max_tokens,
-    reasoning: reasoning.map(|effort| ReasoningRequest { effort }),
-    stream: true,
-    usage: Some(UsageRequest { include: true }),
-    plugins,
-  };
+impl EventSourceRetryPolicy for NoReconnect {
+  fn retry(&self, _error: &EventSourceError, _last_retry: Option<(usize, Duration)>) -> Option<Duration> {
+    None
+  }
+
+  fn set_reconnection_time(&mut self, _duration: Duration) {}
+}
+
+impl Retryable for EventSourceError {
+  fn classify(&self) -> RetryClass {
+    match self {
+      EventSourceError::Transport(err) => retry::classify_reqwest(err),
+      EventSourceError::InvalidStatusCode(status, response) => {
+        retry::classify_status(*status, Some(response.headers()))
+      }
+      _ => RetryClass::Fatal,
+    }
+  }
+}
 
-  let request = builder.json(&request);
+fn connect(request: &RequestBuilder) -> anyhow::Result<EventSource> {
+  let mut source = EventSource::new(request.try_clone().context("request is not cloneable")?)
+    .context("failed to create event source")?;
+  source.set_retry_policy(Box::new(NoReconnect));
 
-  Ok(EventSource::new(request).context("failed to create event source")?)
+  Ok(source)
 }
 
+#[derive(Debug)]
 pub enum OpenrouterEvent {
   Completion(ChatCompletion),
   Error,
@@ -94,13 +155,53 @@ pub enum OpenrouterEvent {
 
 #[tracing::instrument("stream openrouter chat", skip_all, err)]
 pub async fn stream_openrouter_chat(
-  source: EventSource,
+  request: RequestBuilder,
   using_custom_key: bool,
 ) -> Result<(Trigger, impl Stream<Item = OpenrouterEvent>), anyhow::Error> {
+  let mut source = connect(&request)?;
+  let mut connected_at = Instant::now();
+  let policy = RetryPolicy::default();
+
   let stream = async_stream::stream! {
-    for await value in source {
+    let mut retry = 0;
+    // once output was forwarded a retry would duplicate it
+    let mut received_output = false;
+
+    while let Some(value) = source.next().await {
+      match &value {
+        Ok(EventSourceEvent::Open) => metrics::observe_openrouter("stream", true, connected_at.elapsed()),
+        Err(_) if !received_output => metrics::observe_openrouter("stream", false, connected_at.elapsed()),
+        _ => {}
+      }
+
+      if let Err(err) = &value
+        && !received_output
+        && let Some(delay) = policy.delay(retry, err)
+      {
+        warn!("OpenRouter stream failed before the first token (attempt {}), retrying in {delay:?}: {err}", retry + 1);
+
+        tokio::time::sleep(delay).await;
+        retry += 1;
+
+        match connect(&request) {
+          Ok(new_source) => {
+            source = new_source;
+            connected_at = Instant::now();
+          }
+          Err(err) => {
+            error!("Failed to reconnect to OpenRouter: {err:?}");
+            yield OpenrouterEvent::Error;
+            break;
+          }
+        }
+
+        continue;
+      }
+
       match value {
         Ok(EventSourceEvent::Message(data)) => {
+          received_output = true;
+
           // info!("raw data: {}", data.data);
 
           if data.data == "[DONE]" || data.data.is_empty() {
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use reqwest::{Method, RequestBuilder};
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+use crate::metrics;
+use crate::openrouter::types::{Credits, CreditsResponse, KeyInfo, KeyInfoResponse};
+use crate::openrouter::{InvalidKeyReason, OpenrouterClient, OpenrouterError};
+use crate::prelude::*;
+
+const KEY_PATH: &str = "key";
+const CREDITS_PATH: &str = "credits";
+
+/// checks of the same key within this window reuse the previous result
+const CACHE_TTL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitStatus {
+  pub requests: u32,
+  pub interval: String,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyStatus {
+  pub valid: bool,
+  /// set when the key is not valid
+  pub reason: Option<InvalidKeyReason>,
+  pub message: Option<String>,
+  pub label: Option<String>,
+  /// credit limit of the key, unlimited when not set
+  pub limit: Option<f64>,
+  pub limit_remaining: Option<f64>,
+  /// credits used by the key
+  pub usage: Option<f64>,
+  pub is_free_tier: Option<bool>,
+  pub rate_limit: Option<RateLimitStatus>,
+  /// credits of the account, not every key may read them
+  pub total_credits: Option<f64>,
+  pub total_usage: Option<f64>,
+  /// unix ms
+  pub checked_at: f64,
+}
+
+impl KeyStatus {
+  fn invalid(reason: InvalidKeyReason, message: String) -> Self {
+    Self {
+      valid: false,
+      reason: Some(reason),
+      message: Some(message),
+      label: None,
+      limit: None,
+      limit_remaining: None,
+      usage: None,
+      is_free_tier: None,
+      rate_limit: None,
+      total_credits: None,
+      total_usage: None,
+      checked_at: chrono::Utc::now().timestamp_millis() as f64,
+    }
+  }
+
+  fn from_info(info: KeyInfo, credits: Option<Credits>) -> Self {
+    let limit_reached = info.limit_remaining.is_some_and(|remaining| remaining <= 0.0);
+
+    Self {
+      valid: !limit_reached,
+      reason: limit_reached.then_some(InvalidKeyReason::LimitReached),
+      message: limit_reached.then(|| "the credit limit of this key is used up".to_string()),
+      label: Some(info.label),
+      limit: info.limit,
+      limit_remaining: info.limit_remaining,
+      usage: Some(info.usage),
+      is_free_tier: Some(info.is_free_tier),
+      rate_limit: info.rate_limit.map(|rate_limit| RateLimitStatus {
+        requests: rate_limit.requests,
+        interval: rate_limit.interval,
+      }),
+      total_credits: credits.as_ref().map(|credits| credits.total_credits),
+      total_usage: credits.as_ref().map(|credits| credits.total_usage),
+      checked_at: chrono::Utc::now().timestamp_millis() as f64,
+    }
+  }
+}
+
+/// keys always fail to build a request when they are not valid header values
+fn is_malformed(key: &str) -> bool {
+  key.is_empty() || !key.bytes().all(|byte| byte.is_ascii_graphic())
+}
+
+async fn send<T: DeserializeOwned>(operation: &str, request: RequestBuilder) -> Result<T, OpenrouterError> {
+  let started = Instant::now();
+  let response = request.send().await;
+  metrics::observe_openrouter(
+    operation,
+    response.as_ref().is_ok_and(|r| r.status().is_success()),
+    started.elapsed(),
+  );
+  let response = response?;
+
+  if !response.status().is_success() {
+    return Err(OpenrouterError::from_response(response).await);
+  }
+
+  Ok(response.json().await?)
+}
+
+/// asks openrouter about `key`, an invalid key is a status and not an error
+#[tracing::instrument("openrouter key check", skip_all, fields(otel.kind = "client"))]
+pub async fn check_key(client: Arc<Mutex<OpenrouterClient>>, key: &str) -> Result<KeyStatus, OpenrouterError> {
+  if is_malformed(key) {
+    return Ok(KeyStatus::invalid(
+      InvalidKeyReason::Malformed,
+      "key contains invalid characters".into(),
+    ));
+  }
+
+  let (key_request, credits_request) = {
+    let client = client.lock().await;
+    (
+      client.request_builder(Method::GET, KEY_PATH, Some(key.to_string()))?,
+      client.request_builder(Method::GET, CREDITS_PATH, Some(key.to_string()))?,
+    )
+  };
+
+  let (info, credits) = tokio::join!(
+    send::<KeyInfoResponse>("key", key_request),
+    send::<CreditsResponse>("credits", credits_request)
+  );
+
+  let info = match info {
+    Ok(info) => info.data,
+    Err(OpenrouterError::InvalidKey { reason, message }) => return Ok(KeyStatus::invalid(reason, message)),
+    Err(err) => return Err(err),
+  };
+
+  let credits = match credits {
+    Ok(credits) => Some(credits.data),
+    Err(err) => {
+      debug!("Failed to get credits for key: {err}");
+      None
+    }
+  };
+
+  Ok(KeyStatus::from_info(info, credits))
+}
+
+/// recent key checks by the sha256 of the key, so a form that validates on every keystroke does not hit openrouter
+/// each time
+#[derive(Default)]
+pub struct KeyCheckCache {
+  entries: Mutex<HashMap<String, (Instant, KeyStatus)>>,
+}
+
+impl KeyCheckCache {
+  pub async fn check(&self, client: Arc<Mutex<OpenrouterClient>>, key: &str) -> Result<KeyStatus, OpenrouterError> {
+    let hash = Sha256::digest(key.as_bytes())
+      .iter()
+      .map(|byte| format!("{byte:02x}"))
+      .collect::<String>();
+
+    if let Some((checked, status)) = self.entries.lock().await.get(&hash)
+      && checked.elapsed() < CACHE_TTL
+    {
+      return Ok(status.clone());
+    }
+
+    // upstream errors are not cached so the next check retries
+    let status = check_key(client, key).await?;
+
+    let mut entries = self.entries.lock().await;
+    entries.retain(|_, (checked, _)| checked.elapsed() < CACHE_TTL);
+    entries.insert(hash, (Instant::now(), status.clone()));
+
+    Ok(status)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_key_status_from_info() {
+    let json = include_str!("../../test_data/openrouter-key.json");
+    let info = serde_json::from_str::<KeyInfoResponse>(json).unwrap().data;
+
+    let status = KeyStatus::from_info(info.clone(), None);
+
+    assert!(status.valid);
+    assert_eq!(status.limit_remaining, Some(7.5));
+    assert_eq!(status.rate_limit.unwrap().interval, "10s");
+
+    let exhausted = KeyInfo {
+      limit_remaining: Some(0.0),
+      ..info
+    };
+    let status = KeyStatus::from_info(exhausted, None);
+
+    assert!(!status.valid);
+    assert_eq!(status.reason, Some(InvalidKeyReason::LimitReached));
+  }
+
+  #[test]
+  fn test_malformed_key() {
+    assert!(is_malformed(""));
+    assert!(is_malformed("sk-or-v1-abc def"));
+    assert!(!is_malformed("sk-or-v1-0123456789abcdef"));
+  }
+}
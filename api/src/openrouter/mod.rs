@@ -1,14 +1,21 @@
+use std::time::{Duration, Instant};
+
 use anyhow::Context;
 use axum::http::header::AUTHORIZATION;
 use axum::http::{HeaderMap, HeaderValue};
-use reqwest::{Client, Method, RequestBuilder, StatusCode, Url};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode, Url};
 use secrecy::ExposeSecret;
 
 use crate::config::OpenrouterConfig;
-use crate::openrouter::types::ListModelsResponse;
+use crate::openrouter::types::{DataCollection, ListModelsResponse, ProviderPreferences};
 use crate::prelude::*;
+use crate::retry::{self, RetryClass, RetryPolicy, Retryable};
+use crate::{metrics, telemetry};
 
+pub mod attachments;
 pub mod completions;
+pub mod key_check;
+pub mod replay;
 pub mod title;
 pub mod types;
 
@@ -16,6 +23,75 @@ pub struct OpenrouterClient {
   pub base_url: Url,
   pub model_base_url: Url,
   pub client: Client,
+  /// server wide defaults, merged into every completion request
+  pub routing: ModelRouting,
+}
+
+/// which models and providers may serve a completion
+#[derive(Debug, Default, Clone)]
+pub struct ModelRouting {
+  pub fallback_models: Vec<String>,
+  pub provider: Option<ProviderPreferences>,
+}
+
+impl ModelRouting {
+  /// merges request preferences with the server defaults, returns the fallback models and provider preferences
+  ///
+  /// request fallbacks replace the default ones, a server wide `deny` for data collection can not be overridden
+  pub fn resolve(&self, model: &str, request: ModelRouting) -> (Vec<String>, Option<ProviderPreferences>) {
+    let fallback_models = if request.fallback_models.is_empty() {
+      &self.fallback_models
+    } else {
+      &request.fallback_models
+    };
+
+    let mut models: Vec<String> = vec![];
+    for fallback in fallback_models {
+      if fallback != model && !models.contains(fallback) {
+        models.push(fallback.clone());
+      }
+    }
+
+    let mut provider = request.provider.or_else(|| self.provider.clone());
+
+    let server_data_collection = self.provider.as_ref().and_then(|provider| provider.data_collection);
+    if server_data_collection == Some(DataCollection::Deny) {
+      provider.get_or_insert_default().data_collection = Some(DataCollection::Deny);
+    }
+
+    (models, provider)
+  }
+}
+
+/// why openrouter rejected a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum InvalidKeyReason {
+  /// can not be an openrouter key, rejected without asking openrouter
+  Malformed,
+  /// unknown, disabled or deleted key
+  Unauthorized,
+  /// the account has no credits left
+  InsufficientCredits,
+  /// the credit limit set on the key is used up
+  LimitReached,
+}
+
+impl InvalidKeyReason {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      InvalidKeyReason::Malformed => "malformed",
+      InvalidKeyReason::Unauthorized => "unauthorized",
+      InvalidKeyReason::InsufficientCredits => "insufficient credits",
+      InvalidKeyReason::LimitReached => "limit reached",
+    }
+  }
+}
+
+impl std::fmt::Display for InvalidKeyReason {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(self.as_str())
+  }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -24,10 +100,68 @@ pub enum OpenrouterError {
   BuildRequest(#[from] anyhow::Error),
   #[error("failed to send request: {0}")]
   Request(#[from] reqwest::Error),
-  #[error("response not ok ({0}): {1:?}")]
-  NotOk(StatusCode, Option<String>),
+  #[error("response not ok ({status}): {body:?}")]
+  NotOk {
+    status: StatusCode,
+    body: Option<String>,
+    retry_after: Option<Duration>,
+  },
   #[error("failed to parse response: {0}")]
   Parse(#[from] serde_json::Error),
+  #[error("invalid key ({reason}): {message}")]
+  InvalidKey { reason: InvalidKeyReason, message: String },
+}
+
+/// `error.message` of an openrouter error body
+fn error_message(body: &str) -> Option<String> {
+  let body = serde_json::from_str::<serde_json::Value>(body).ok()?;
+
+  body.get("error")?.get("message")?.as_str().map(str::to_string)
+}
+
+impl OpenrouterError {
+  pub async fn from_response(response: Response) -> Self {
+    let status = response.status();
+    let retry_after = retry::retry_after(response.headers());
+    let body = response.text().await.ok();
+
+    let reason = match status {
+      StatusCode::UNAUTHORIZED => Some(InvalidKeyReason::Unauthorized),
+      StatusCode::PAYMENT_REQUIRED => Some(InvalidKeyReason::InsufficientCredits),
+      _ => None,
+    };
+
+    if let Some(reason) = reason {
+      let message = body
+        .as_deref()
+        .and_then(error_message)
+        .unwrap_or_else(|| status.to_string());
+      return OpenrouterError::InvalidKey { reason, message };
+    }
+
+    OpenrouterError::NotOk {
+      status,
+      body,
+      retry_after,
+    }
+  }
+}
+
+impl Retryable for OpenrouterError {
+  fn classify(&self) -> RetryClass {
+    match self {
+      OpenrouterError::Request(err) => retry::classify_reqwest(err),
+      OpenrouterError::NotOk {
+        status, retry_after, ..
+      } => match (retry::classify_status(*status, None), retry_after) {
+        (RetryClass::Transient, Some(delay)) => RetryClass::RetryAfter(*delay),
+        (class, _) => class,
+      },
+      OpenrouterError::BuildRequest(_) | OpenrouterError::Parse(_) | OpenrouterError::InvalidKey { .. } => {
+        RetryClass::Fatal
+      }
+    }
+  }
 }
 
 impl OpenrouterClient {
@@ -43,29 +177,51 @@ impl OpenrouterClient {
       &self.client
     };
 
-    let builder = client.request(method, self.base_url.join(path)?);
+    let builder = client
+      .request(method, self.base_url.join(path)?)
+      .headers(telemetry::trace_headers());
 
     Ok(builder)
   }
 
   fn model_request_builder(&self, method: Method, path: &str) -> anyhow::Result<RequestBuilder> {
-    Ok(self.client.request(method, self.model_base_url.join(path)?))
+    Ok(
+      self
+        .client
+        .request(method, self.model_base_url.join(path)?)
+        .headers(telemetry::trace_headers()),
+    )
   }
 
-  pub async fn get_models(&self) -> Result<ListModelsResponse, OpenrouterError> {
+  /// request for the model list, also used to check that openrouter is reachable
+  pub fn models_request(&self) -> RequestBuilder {
     const PATH: &str = "models";
     // path is static so just unwrap
-    let builder = self.model_request_builder(Method::GET, PATH).unwrap();
+    self.model_request_builder(Method::GET, PATH).unwrap()
+  }
+
+  #[tracing::instrument("openrouter models", skip_all, fields(otel.kind = "client"))]
+  pub async fn get_models(&self) -> Result<ListModelsResponse, OpenrouterError> {
+    let builder = self.models_request();
 
-    let response = builder.send().await?;
+    retry::retry(&RetryPolicy::default(), "list models", || async {
+      // GET requests have no body so they can always be cloned
+      let started = Instant::now();
+      let response = builder.try_clone().unwrap().send().await;
+      metrics::observe_openrouter(
+        "models",
+        response.as_ref().is_ok_and(|r| r.status().is_success()),
+        started.elapsed(),
+      );
+      let response = response?;
 
-    if !response.status().is_success() {
-      let status = response.status();
-      let text = response.text().await.ok();
-      return Err(OpenrouterError::NotOk(status, text));
-    }
+      if !response.status().is_success() {
+        return Err(OpenrouterError::from_response(response).await);
+      }
 
-    Ok(response.json().await?)
+      Ok(response.json().await?)
+    })
+    .await
   }
 }
 
@@ -86,9 +242,76 @@ pub fn create_openrouter_client(config: &OpenrouterConfig) -> anyhow::Result<Ope
 
   let client = create_http_client(api_key).context("failed to create HTTP client for Openrouter")?;
 
+  let provider = config.data_collection.map(|data_collection| ProviderPreferences {
+    data_collection: Some(data_collection),
+    ..Default::default()
+  });
+
   Ok(OpenrouterClient {
     base_url: config.api_url.clone(),
     model_base_url: config.model_api_url.clone(),
     client,
+    routing: ModelRouting {
+      fallback_models: config.fallback_models.clone(),
+      provider,
+    },
   })
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn server_routing() -> ModelRouting {
+    ModelRouting {
+      fallback_models: vec!["openai/gpt-4o-mini".into(), "anthropic/claude-3-haiku".into()],
+      provider: Some(ProviderPreferences {
+        data_collection: Some(DataCollection::Deny),
+        ..Default::default()
+      }),
+    }
+  }
+
+  #[test]
+  fn test_resolve_uses_server_defaults() {
+    let (models, provider) = server_routing().resolve("openai/gpt-4o-mini", ModelRouting::default());
+
+    // the requested model is not repeated as its own fallback
+    assert_eq!(models, vec!["anthropic/claude-3-haiku".to_string()]);
+    assert_eq!(provider.unwrap().data_collection, Some(DataCollection::Deny));
+  }
+
+  #[test]
+  fn test_resolve_request_cannot_allow_data_collection() {
+    let request = ModelRouting {
+      fallback_models: vec!["google/gemini-2.5-flash".into()],
+      provider: Some(ProviderPreferences {
+        order: vec!["openai".into()],
+        data_collection: Some(DataCollection::Allow),
+        ..Default::default()
+      }),
+    };
+
+    let (models, provider) = server_routing().resolve("openai/gpt-4o", request);
+    let provider = provider.unwrap();
+
+    assert_eq!(models, vec!["google/gemini-2.5-flash".to_string()]);
+    assert_eq!(provider.order, vec!["openai".to_string()]);
+    assert_eq!(provider.data_collection, Some(DataCollection::Deny));
+  }
+
+  #[tokio::test]
+  async fn test_invalid_key_from_response() {
+    let response = axum::http::Response::builder()
+      .status(StatusCode::UNAUTHORIZED)
+      .body(r#"{"error":{"code":401,"message":"User not found."}}"#)
+      .unwrap();
+
+    let OpenrouterError::InvalidKey { reason, message } = OpenrouterError::from_response(response.into()).await else {
+      panic!("expected an invalid key error");
+    };
+
+    assert_eq!(reason, InvalidKeyReason::Unauthorized);
+    assert_eq!(message, "User not found.");
+  }
+}
@@ -0,0 +1,74 @@
+use axum::http::header::CONTENT_TYPE;
+use axum::routing::post;
+use futures::Stream;
+use stream_cancel::Trigger;
+use tokio::net::TcpListener;
+
+use crate::openrouter::completions::{OpenrouterEvent, stream_openrouter_chat};
+use crate::prelude::*;
+
+/// serves a recorded openrouter `text/event-stream` body on a local port and streams it through
+/// [`stream_openrouter_chat`], so parsing can be checked against real responses without calling openrouter
+pub async fn replay_fixture(fixture: String) -> anyhow::Result<(Trigger, impl Stream<Item = OpenrouterEvent>)> {
+  let listener = TcpListener::bind("127.0.0.1:0")
+    .await
+    .context("failed to bind replay server")?;
+  let address = listener.local_addr()?;
+
+  let app = axum::Router::new().route(
+    "/chat/completions",
+    post(move || async move { ([(CONTENT_TYPE, "text/event-stream")], fixture) }),
+  );
+
+  tokio::spawn(async move {
+    if let Err(err) = axum::serve(listener, app).await {
+      error!("Replay server failed: {err:?}");
+    }
+  });
+
+  let request = reqwest::Client::new()
+    .post(format!("http://{address}/chat/completions"))
+    .body("{}");
+
+  stream_openrouter_chat(request, false).await
+}
+
+#[cfg(test)]
+mod tests {
+  use futures::StreamExt;
+
+  use super::*;
+  use crate::openrouter::types::{ChatDelta, FinishReason};
+
+  #[tokio::test]
+  async fn test_replay_chat_stream() {
+    let fixture = include_str!("../../test_data/chat-stream.sse");
+
+    let (_trigger, stream) = replay_fixture(fixture.to_string()).await.unwrap();
+    let events = stream.collect::<Vec<_>>().await;
+
+    let mut text = String::new();
+    let mut usage = None;
+    let mut finish_reason = None;
+
+    for event in events {
+      let OpenrouterEvent::Completion(completion) = event else {
+        panic!("unexpected event: {event:?}");
+      };
+
+      for choice in completion.choices {
+        finish_reason = choice.finish_reason.or(finish_reason);
+
+        if let ChatDelta::Text { content, .. } = choice.delta {
+          text.push_str(&content);
+        }
+      }
+
+      usage = completion.usage.or(usage);
+    }
+
+    assert_eq!(text, "Hello! How can I help you today?");
+    assert_eq!(usage.unwrap().completion_tokens, 9);
+    assert_eq!(finish_reason, Some(FinishReason::Stop));
+  }
+}
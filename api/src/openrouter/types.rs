@@ -54,6 +54,11 @@ pub enum Modality {
   Image,
   #[serde(rename = "text")]
   Text,
+  #[serde(rename = "audio")]
+  Audio,
+  /// modalities added after this list was written
+  #[serde(other)]
+  Other,
 }
 
 #[derive(Deserialize, PartialEq, Eq)]
@@ -157,6 +162,53 @@ pub struct TopProvider {
 
 // endregion
 
+// region: keys
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct KeyInfoResponse {
+  pub data: KeyInfo,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct KeyInfo {
+  pub label: String,
+  /// credit limit of the key, unlimited when not set
+  pub limit: Option<f64>,
+  /// credits used by the key
+  pub usage: f64,
+  pub limit_remaining: Option<f64>,
+  #[serde(default)]
+  pub is_free_tier: bool,
+  pub rate_limit: Option<RateLimit>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct RateLimit {
+  pub requests: u32,
+  /// e.g. `10s`
+  pub interval: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CreditsResponse {
+  pub data: Credits,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Credits {
+  /// credits bought on the account
+  pub total_credits: f64,
+  /// credits used by every key of the account
+  pub total_usage: f64,
+}
+
+// endregion
+
 // region: completions
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -174,13 +226,13 @@ pub enum Role {
   Tool,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub struct ImageUrl {
   pub url: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub struct File {
   pub filename: String,
@@ -188,7 +240,16 @@ pub struct File {
   pub file_data: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct InputAudio {
+  /// base64 encoded audio without a data url prefix
+  pub data: String,
+  /// `wav` or `mp3`
+  pub format: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "snake_case")]
 #[serde(tag = "type")]
 pub enum ContentPart {
@@ -198,13 +259,32 @@ pub enum ContentPart {
   Image { image_url: ImageUrl },
   #[serde(rename = "file")]
   File { file: File },
+  #[serde(rename = "input_audio")]
+  InputAudio { input_audio: InputAudio },
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub struct MessageRequest {
   pub role: Role,
   pub content: Vec<ContentPart>,
+  /// reasoning of an earlier assistant turn, sent back to models that continue from it
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub reasoning: Option<String>,
+  /// provider reasoning blocks (signatures, encrypted reasoning) of an earlier assistant turn, passed back verbatim
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub reasoning_details: Option<Vec<ReasoningDetail>>,
+}
+
+impl MessageRequest {
+  pub fn new(role: Role, content: Vec<ContentPart>) -> Self {
+    Self {
+      role,
+      content,
+      reasoning: None,
+      reasoning_details: None,
+    }
+  }
 }
 
 #[derive(Debug, Deserialize)]
@@ -214,7 +294,7 @@ pub struct MessageResponse {
   pub content: String,
 }
 
-#[derive(Serialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, clap::ValueEnum)]
 #[serde(rename_all = "snake_case")]
 pub enum ReasoningEffort {
   #[serde(rename = "low")]
@@ -225,10 +305,62 @@ pub enum ReasoningEffort {
   High,
 }
 
+/// either `effort` or `max_tokens` is used, `max_tokens` wins when both are set
 #[derive(Serialize)]
 #[serde(rename_all = "snake_case")]
 pub struct ReasoningRequest {
-  pub effort: ReasoningEffort,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub effort: Option<ReasoningEffort>,
+  /// reasoning token budget
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub max_tokens: Option<u32>,
+  /// the model still reasons but the reasoning is not returned
+  #[serde(skip_serializing_if = "std::ops::Not::not")]
+  pub exclude: bool,
+}
+
+/// provider specific reasoning block, e.g. `reasoning.text` with a signature or `reasoning.encrypted`
+///
+/// kept as json because it has to be sent back exactly as it was received
+pub type ReasoningDetail = serde_json::Value;
+
+/// merges streamed reasoning detail deltas, text of a block arrives in pieces that share its `index`
+pub fn merge_reasoning_details(details: &mut Vec<ReasoningDetail>, deltas: Vec<ReasoningDetail>) {
+  const APPENDED: [&str; 3] = ["text", "summary", "data"];
+
+  for delta in deltas {
+    let serde_json::Value::Object(delta) = delta else {
+      continue;
+    };
+
+    let existing = details.iter_mut().find_map(|detail| match detail {
+      serde_json::Value::Object(detail)
+        if detail.get("index") == delta.get("index") && detail.get("type") == delta.get("type") =>
+      {
+        Some(detail)
+      }
+      _ => None,
+    });
+
+    let Some(existing) = existing else {
+      details.push(serde_json::Value::Object(delta));
+      continue;
+    };
+
+    for (key, value) in delta {
+      match (existing.get_mut(&key), value) {
+        (Some(serde_json::Value::String(current)), serde_json::Value::String(piece))
+          if APPENDED.contains(&key.as_str()) =>
+        {
+          current.push_str(&piece);
+        }
+        (_, serde_json::Value::Null) => {}
+        (_, value) => {
+          existing.insert(key, value);
+        }
+      }
+    }
+  }
 }
 
 #[derive(Serialize)]
@@ -251,12 +383,44 @@ pub struct PluginRequest {
   pub pdf: PdfOptions,
 }
 
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DataCollection {
+  /// providers may store and train on prompts
+  Allow,
+  /// only use providers that do not retain prompts
+  Deny,
+}
+
+/// provider routing, see https://openrouter.ai/docs/features/provider-routing
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ProviderPreferences {
+  /// provider slugs to try in order
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub order: Vec<String>,
+  /// whether other providers may be used when the ones in `order` are unavailable
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub allow_fallbacks: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub data_collection: Option<DataCollection>,
+  /// e.g. `fp8`, `bf16`
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub quantizations: Vec<String>,
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "snake_case")]
 pub struct CompletionRequest {
   pub model: String,
+  /// tried in order when `model` is unavailable
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub models: Vec<String>,
   pub messages: Vec<MessageRequest>,
 
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub provider: Option<ProviderPreferences>,
+
   #[serde(skip_serializing_if = "Option::is_none")]
   pub reasoning: Option<ReasoningRequest>,
 
@@ -276,6 +440,7 @@ pub struct CompletionRequest {
 #[serde(rename_all = "snake_case")]
 pub struct CompletionChoice {
   pub message: MessageResponse,
+  pub finish_reason: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -283,6 +448,10 @@ pub struct CompletionChoice {
 pub struct CompletionResponse {
   pub id: String,
   pub choices: Vec<CompletionChoice>,
+  pub usage: Option<ChatUsage>,
+  /// model that served the request, differs from the requested one after a fallback
+  pub model: Option<String>,
+  pub provider: Option<String>,
 }
 
 // endregion
@@ -294,12 +463,17 @@ pub struct CompletionResponse {
 pub struct ChatCompletion {
   pub choices: Vec<ChatChoice>,
   pub usage: Option<ChatUsage>,
+  /// model that served the request, differs from the requested one after a fallback
+  pub model: Option<String>,
+  pub provider: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct ChatChoice {
   pub delta: ChatDelta,
+  /// set on the last chunk of the choice
+  pub finish_reason: Option<FinishReason>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -309,31 +483,71 @@ pub enum ChatDelta {
   Text {
     content: String,
     reasoning: Option<String>,
+    reasoning_details: Option<Vec<ReasoningDetail>>,
     annotations: Option<Vec<Annotation>>,
   },
   Finished {
-    finish_reason: String,
+    finish_reason: FinishReason,
   },
   Refusal {
     refusal: String,
   },
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum FinishReason {
   Stop,
+  /// the answer hit the token limit and was cut off
   Length,
   ContentFilter,
+  ToolCalls,
   ToolUse,
   FunctionCall,
+  Error,
+  /// anything a provider sends that openrouter does not normalize
+  #[serde(other)]
+  Unknown,
+}
+
+impl FinishReason {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      FinishReason::Stop => "stop",
+      FinishReason::Length => "length",
+      FinishReason::ContentFilter => "content_filter",
+      FinishReason::ToolCalls => "tool_calls",
+      FinishReason::ToolUse => "tool_use",
+      FinishReason::FunctionCall => "function_call",
+      FinishReason::Error => "error",
+      FinishReason::Unknown => "unknown",
+    }
+  }
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct ChatUsage {
   pub prompt_tokens: u32,
+  /// includes the reasoning tokens
   pub completion_tokens: u32,
+  pub completion_tokens_details: Option<CompletionTokensDetails>,
+}
+
+impl ChatUsage {
+  pub fn reasoning_tokens(&self) -> u32 {
+    self
+      .completion_tokens_details
+      .as_ref()
+      .and_then(|details| details.reasoning_tokens)
+      .unwrap_or_default()
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CompletionTokensDetails {
+  pub reasoning_tokens: Option<u32>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -382,5 +596,50 @@ mod tests {
     let parsed = serde_json::from_str::<ChatCompletion>(json).unwrap();
 
     assert_eq!(parsed.choices.len(), 1);
+    assert_eq!(
+      parsed.model.as_deref(),
+      Some("google/gemini-2.5-flash-lite-preview-06-17")
+    );
+    assert_eq!(parsed.provider.as_deref(), Some("Google AI Studio"));
+  }
+
+  #[test]
+  fn test_parse_finish_reason() {
+    let parse = |json| serde_json::from_str::<ChatChoice>(json).unwrap().finish_reason;
+
+    assert_eq!(
+      parse(r#"{"delta":{"content":""},"finish_reason":"length"}"#),
+      Some(FinishReason::Length)
+    );
+    assert_eq!(
+      parse(r#"{"delta":{"content":""},"finish_reason":"end_turn"}"#),
+      Some(FinishReason::Unknown)
+    );
+    assert_eq!(parse(r#"{"delta":{"content":"Hi"},"finish_reason":null}"#), None);
+  }
+
+  #[test]
+  fn test_merge_reasoning_details() {
+    let json = include_str!("../../test_data/reasoning-details.json");
+    let chunks = serde_json::from_str::<Vec<Vec<ReasoningDetail>>>(json).unwrap();
+
+    let mut details = vec![];
+    for chunk in chunks {
+      merge_reasoning_details(&mut details, chunk);
+    }
+
+    assert_eq!(
+      details,
+      vec![
+        serde_json::json!({
+          "type": "reasoning.text",
+          "index": 0,
+          "format": "anthropic-claude-v1",
+          "text": "The user greets me. I should greet back.",
+          "signature": "EqoBCkgIBxABGAIiQ"
+        }),
+        serde_json::json!({ "type": "reasoning.encrypted", "index": 1, "data": "gAAAAABo" }),
+      ]
+    );
   }
 }
@@ -0,0 +1,78 @@
+use axum::response::Response;
+use serde_json::json;
+
+use crate::convex::ConvexError;
+use crate::openrouter::{InvalidKeyReason, OpenrouterError};
+use crate::prelude::*;
+
+pub mod tokens;
+pub mod types;
+
+/// errors of the openai compatible api, returned in the body shape openai clients parse
+#[derive(Debug, thiserror::Error)]
+pub enum ProxyError {
+  #[error("missing bearer token")]
+  MissingToken,
+  #[error("invalid api token")]
+  InvalidToken,
+  #[error("api token has been revoked")]
+  RevokedToken,
+  #[error("no model specified")]
+  NoModelSpecified,
+  #[error("model not allowed: {0}")]
+  ModelNotAllowed(String),
+  #[error("openrouter error: {0}")]
+  Openrouter(#[from] OpenrouterError),
+  #[error("convex error: {0}")]
+  Convex(#[from] ConvexError),
+  #[error("unexpected error: {0}")]
+  Unexpected(#[from] anyhow::Error),
+}
+
+impl ProxyError {
+  fn status(&self) -> StatusCode {
+    match self {
+      ProxyError::MissingToken | ProxyError::InvalidToken | ProxyError::RevokedToken => StatusCode::UNAUTHORIZED,
+      ProxyError::NoModelSpecified => StatusCode::BAD_REQUEST,
+      ProxyError::ModelNotAllowed(_) => StatusCode::FORBIDDEN,
+      // pass through rate limits and validation errors so clients can react to them, auth errors are about the
+      // server key and not the caller's token
+      ProxyError::Openrouter(OpenrouterError::NotOk { status, .. })
+        if status.is_client_error() && *status != StatusCode::UNAUTHORIZED && *status != StatusCode::FORBIDDEN =>
+      {
+        *status
+      }
+      ProxyError::Openrouter(OpenrouterError::InvalidKey {
+        reason: InvalidKeyReason::InsufficientCredits,
+        ..
+      }) => StatusCode::PAYMENT_REQUIRED,
+      ProxyError::Openrouter(_) => StatusCode::BAD_GATEWAY,
+      ProxyError::Convex(_) | ProxyError::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+  }
+}
+
+/// `{ "error": { "message", "type" } }` like the openai api
+pub fn error_body(status: StatusCode, message: String) -> serde_json::Value {
+  let kind = match status {
+    StatusCode::UNAUTHORIZED => "authentication_error",
+    StatusCode::FORBIDDEN => "permission_error",
+    StatusCode::TOO_MANY_REQUESTS => "rate_limit_error",
+    status if status.is_client_error() => "invalid_request_error",
+    _ => "api_error",
+  };
+
+  json!({ "error": { "message": message, "type": kind } })
+}
+
+impl IntoResponse for ProxyError {
+  fn into_response(self) -> Response {
+    let status = self.status();
+
+    if status.is_server_error() {
+      error!("Proxy request failed: {self:?}");
+    }
+
+    (status, Json(error_body(status, self.to_string()))).into_response()
+  }
+}
@@ -0,0 +1,78 @@
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use sha2::{Digest, Sha256};
+
+use crate::auth::bearer_token;
+use crate::convex::Doc;
+use crate::convex::api_tokens::{self, ApiToken};
+use crate::prelude::*;
+use crate::proxy::ProxyError;
+
+/// makes leaked tokens easy to recognise in logs and secret scanners
+pub const TOKEN_PREFIX: &str = "awa_";
+
+fn hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// new random token, only its hash is stored
+pub fn generate_token() -> anyhow::Result<String> {
+  let mut bytes = [0u8; 32];
+  getrandom::fill(&mut bytes).map_err(|err| anyhow!("failed to generate token: {err}"))?;
+
+  Ok(format!("{TOKEN_PREFIX}{}", hex(&bytes)))
+}
+
+pub fn hash_token(token: &str) -> String {
+  hex(&Sha256::digest(token.as_bytes()))
+}
+
+/// the api token from the `Authorization: Bearer` header, rejects missing, unknown and revoked tokens
+pub struct ApiTokenAuth(pub Doc<ApiToken>);
+
+impl FromRequestParts<AppState> for ApiTokenAuth {
+  type Rejection = ProxyError;
+
+  async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+    let token = bearer_token(parts).ok_or(ProxyError::MissingToken)?;
+
+    let mut convex = state.convex.clone();
+
+    let Some(token) = api_tokens::get_by_hash(&mut convex, hash_token(token)).await? else {
+      return Err(ProxyError::InvalidToken);
+    };
+
+    if token.revoked_at.is_some() {
+      return Err(ProxyError::RevokedToken);
+    }
+
+    // last used is informational, don't hold up the request for it
+    let id = token.id.clone();
+    tokio::spawn(async move {
+      if let Err(err) = api_tokens::touch(&mut convex, id).await {
+        warn!("Failed to update api token last use: {err}");
+      }
+    });
+
+    Ok(Self(token))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_token_hash() {
+    let token = generate_token().unwrap();
+
+    assert!(token.starts_with(TOKEN_PREFIX));
+    assert_eq!(token.len(), TOKEN_PREFIX.len() + 64);
+    assert_ne!(token, generate_token().unwrap());
+
+    assert_eq!(
+      hash_token("awa_test"),
+      "304df0996f1e43541c66c7cd135f93226a871c7ca4e93927f2b1ca7bdd51bbaa"
+    );
+  }
+}
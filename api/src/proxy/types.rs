@@ -0,0 +1,232 @@
+//! request and response bodies of the openai chat completions api, only the fields we forward
+
+use crate::openrouter::types::{ContentPart, File, ImageUrl, MessageRequest, ReasoningEffort, Role};
+use crate::prelude::*;
+
+#[derive(Debug, Deserialize)]
+pub struct ChatCompletionRequest {
+  pub model: String,
+  pub messages: Vec<ChatMessage>,
+  #[serde(default)]
+  pub stream: bool,
+  pub stream_options: Option<StreamOptions>,
+  /// deprecated by openai but still sent by most clients
+  pub max_tokens: Option<u32>,
+  pub max_completion_tokens: Option<u32>,
+  pub reasoning_effort: Option<ReasoningEffort>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StreamOptions {
+  #[serde(default)]
+  pub include_usage: bool,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatRole {
+  System,
+  /// newer name for system used by reasoning models
+  Developer,
+  User,
+  Assistant,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatMessage {
+  pub role: ChatRole,
+  pub content: MessageContent,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum MessageContent {
+  Text(String),
+  Parts(Vec<ChatContentPart>),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatImageUrl {
+  pub url: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatFile {
+  pub filename: String,
+  pub file_data: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatContentPart {
+  Text { text: String },
+  ImageUrl { image_url: ChatImageUrl },
+  File { file: ChatFile },
+}
+
+impl From<ChatContentPart> for ContentPart {
+  fn from(part: ChatContentPart) -> Self {
+    match part {
+      ChatContentPart::Text { text } => ContentPart::Text { text },
+      ChatContentPart::ImageUrl { image_url } => ContentPart::Image {
+        image_url: ImageUrl { url: image_url.url },
+      },
+      ChatContentPart::File { file } => ContentPart::File {
+        file: File {
+          filename: file.filename,
+          file_data: file.file_data,
+        },
+      },
+    }
+  }
+}
+
+impl From<ChatMessage> for MessageRequest {
+  fn from(message: ChatMessage) -> Self {
+    let role = match message.role {
+      ChatRole::System | ChatRole::Developer => Role::System,
+      ChatRole::User => Role::User,
+      ChatRole::Assistant => Role::Assistant,
+    };
+
+    let content = match message.content {
+      MessageContent::Text(text) => vec![ContentPart::Text { text }],
+      MessageContent::Parts(parts) => parts.into_iter().map(Into::into).collect(),
+    };
+
+    MessageRequest::new(role, content)
+  }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Usage {
+  pub prompt_tokens: u32,
+  pub completion_tokens: u32,
+  pub total_tokens: u32,
+}
+
+impl Usage {
+  pub fn new(prompt_tokens: u32, completion_tokens: u32) -> Self {
+    Self {
+      prompt_tokens,
+      completion_tokens,
+      total_tokens: prompt_tokens + completion_tokens,
+    }
+  }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResponseMessage {
+  pub role: &'static str,
+  pub content: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResponseChoice {
+  pub index: u32,
+  pub message: ResponseMessage,
+  pub finish_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatCompletionResponse {
+  pub id: String,
+  pub object: &'static str,
+  pub created: i64,
+  pub model: String,
+  pub choices: Vec<ResponseChoice>,
+  pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ChunkDelta {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub role: Option<&'static str>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub content: Option<String>,
+  /// not part of the openai api but understood by most clients that talk to openrouter
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub reasoning: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub refusal: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChunkChoice {
+  pub index: u32,
+  pub delta: ChunkDelta,
+  pub finish_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatCompletionChunk {
+  pub id: String,
+  pub object: &'static str,
+  pub created: i64,
+  pub model: String,
+  pub choices: Vec<ChunkChoice>,
+  /// only set on the last chunk when the client asked for `stream_options.include_usage`
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModelObject {
+  pub id: String,
+  pub object: &'static str,
+  pub created: i64,
+  pub owned_by: String,
+}
+
+impl ModelObject {
+  pub fn new(id: String, created: i64) -> Self {
+    // openrouter ids are `provider/model`
+    let owned_by = id
+      .split_once('/')
+      .map(|(owner, _)| owner)
+      .unwrap_or("openrouter")
+      .to_string();
+
+    Self {
+      id,
+      object: "model",
+      created,
+      owned_by,
+    }
+  }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModelList {
+  pub object: &'static str,
+  pub data: Vec<ModelObject>,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_chat_completion_request() {
+    let json = include_str!("../../test_data/openai-chat-request.json");
+
+    let request = serde_json::from_str::<ChatCompletionRequest>(json).unwrap();
+
+    assert!(request.stream);
+    assert!(request.stream_options.unwrap().include_usage);
+
+    let messages = request
+      .messages
+      .into_iter()
+      .map(MessageRequest::from)
+      .collect::<Vec<_>>();
+
+    assert_eq!(messages.len(), 3);
+    assert_eq!(messages[0].role, Role::System);
+    assert!(matches!(&messages[1].content[..], [ContentPart::Text { text }] if text == "What is in this image?"));
+    assert!(matches!(
+      &messages[2].content[..],
+      [ContentPart::Text { .. }, ContentPart::Image { .. }]
+    ));
+  }
+}
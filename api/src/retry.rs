@@ -0,0 +1,262 @@
+use std::error::Error as StdError;
+use std::io;
+use std::time::Duration;
+
+use axum::http::HeaderMap;
+use axum::http::header::RETRY_AFTER;
+
+use crate::prelude::*;
+
+/// exponential backoff with jitter, shared by convex and openrouter calls
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+  /// total attempts including the first one
+  pub max_attempts: u32,
+  pub base_delay: Duration,
+  pub max_delay: Duration,
+  /// give up instead of waiting when the server asks for a longer pause than this
+  pub max_retry_after: Duration,
+}
+
+impl Default for RetryPolicy {
+  fn default() -> Self {
+    Self {
+      max_attempts: 3,
+      base_delay: Duration::from_millis(250),
+      max_delay: Duration::from_secs(5),
+      max_retry_after: Duration::from_secs(30),
+    }
+  }
+}
+
+impl RetryPolicy {
+  /// delay before the given retry (starting at 0), between half and the full exponential delay
+  pub fn backoff(&self, retry: u32) -> Duration {
+    let delay = self
+      .base_delay
+      .saturating_mul(2u32.saturating_pow(retry))
+      .min(self.max_delay);
+    delay.mul_f64(0.5 + fastrand::f64() / 2.0)
+  }
+
+  /// how long to wait before retrying after `error`, `None` if it should not be retried
+  pub fn delay<E: Retryable>(&self, retry: u32, error: &E) -> Option<Duration> {
+    if retry + 1 >= self.max_attempts {
+      return None;
+    }
+
+    match error.classify() {
+      RetryClass::Fatal => None,
+      RetryClass::Transient => Some(self.backoff(retry)),
+      RetryClass::RetryAfter(delay) if delay <= self.max_retry_after => Some(delay),
+      RetryClass::RetryAfter(_) => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryClass {
+  /// validation errors and anything else that would fail the same way again
+  Fatal,
+  /// timeouts, connection resets and overloaded servers
+  Transient,
+  /// the server told us when to come back
+  RetryAfter(Duration),
+}
+
+pub trait Retryable {
+  fn classify(&self) -> RetryClass;
+}
+
+/// runs `operation` until it succeeds, fails with a non retryable error or runs out of attempts
+pub async fn retry<T, E, F, Fut>(policy: &RetryPolicy, name: &str, mut operation: F) -> Result<T, E>
+where
+  E: Retryable + std::fmt::Display,
+  F: FnMut() -> Fut,
+  Fut: Future<Output = Result<T, E>>,
+{
+  let mut retry = 0;
+
+  loop {
+    match operation().await {
+      Ok(value) => return Ok(value),
+      Err(err) => {
+        let Some(delay) = policy.delay(retry, &err) else {
+          return Err(err);
+        };
+
+        warn!("{name} failed (attempt {}), retrying in {delay:?}: {err}", retry + 1);
+
+        tokio::time::sleep(delay).await;
+        retry += 1;
+      }
+    }
+  }
+}
+
+/// 408, 429 and 5xx are worth another try
+pub fn classify_status(status: StatusCode, headers: Option<&HeaderMap>) -> RetryClass {
+  let retryable =
+    status == StatusCode::REQUEST_TIMEOUT || status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+
+  if !retryable {
+    return RetryClass::Fatal;
+  }
+
+  match headers.and_then(retry_after) {
+    Some(delay) => RetryClass::RetryAfter(delay),
+    None => RetryClass::Transient,
+  }
+}
+
+pub fn classify_reqwest(err: &reqwest::Error) -> RetryClass {
+  if let Some(status) = err.status() {
+    return classify_status(status, None);
+  }
+
+  if err.is_timeout() || err.is_connect() || is_connection_reset(err) {
+    RetryClass::Transient
+  } else {
+    RetryClass::Fatal
+  }
+}
+
+fn is_connection_reset(err: &(dyn StdError + 'static)) -> bool {
+  let mut current = Some(err);
+
+  while let Some(err) = current {
+    if let Some(io_err) = err.downcast_ref::<io::Error>() {
+      return matches!(
+        io_err.kind(),
+        io::ErrorKind::ConnectionReset
+          | io::ErrorKind::ConnectionAborted
+          | io::ErrorKind::BrokenPipe
+          | io::ErrorKind::UnexpectedEof
+      );
+    }
+
+    current = err.source();
+  }
+
+  false
+}
+
+/// `Retry-After` is either a number of seconds or an http date
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+  let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+  if let Ok(seconds) = value.parse::<u64>() {
+    return Some(Duration::from_secs(seconds));
+  }
+
+  let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+  let delay = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+
+  // a date in the past means retry right away
+  Some(delay.to_std().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::http::HeaderValue;
+
+  use super::*;
+
+  struct TestError(RetryClass);
+
+  impl Retryable for TestError {
+    fn classify(&self) -> RetryClass {
+      self.0
+    }
+  }
+
+  impl std::fmt::Display for TestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+      write!(f, "{:?}", self.0)
+    }
+  }
+
+  #[test]
+  fn test_classify_status() {
+    assert_eq!(classify_status(StatusCode::BAD_REQUEST, None), RetryClass::Fatal);
+    assert_eq!(classify_status(StatusCode::UNAUTHORIZED, None), RetryClass::Fatal);
+    assert_eq!(
+      classify_status(StatusCode::REQUEST_TIMEOUT, None),
+      RetryClass::Transient
+    );
+    assert_eq!(classify_status(StatusCode::BAD_GATEWAY, None), RetryClass::Transient);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(RETRY_AFTER, HeaderValue::from_static("7"));
+
+    assert_eq!(
+      classify_status(StatusCode::TOO_MANY_REQUESTS, Some(&headers)),
+      RetryClass::RetryAfter(Duration::from_secs(7))
+    );
+    // retry-after on a validation error does not make it retryable
+    assert_eq!(
+      classify_status(StatusCode::BAD_REQUEST, Some(&headers)),
+      RetryClass::Fatal
+    );
+  }
+
+  #[test]
+  fn test_retry_after_http_date() {
+    let mut headers = HeaderMap::new();
+    headers.insert(RETRY_AFTER, HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"));
+
+    assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+  }
+
+  #[test]
+  fn test_backoff_is_bounded() {
+    let policy = RetryPolicy::default();
+
+    for retry in 0..10 {
+      let delay = policy.backoff(retry);
+      assert!(delay <= policy.max_delay);
+      assert!(delay >= policy.base_delay / 2);
+    }
+  }
+
+  #[test]
+  fn test_delay_respects_attempts_and_retry_after() {
+    let policy = RetryPolicy::default();
+
+    assert!(policy.delay(0, &TestError(RetryClass::Transient)).is_some());
+    assert!(policy.delay(2, &TestError(RetryClass::Transient)).is_none());
+    assert!(policy.delay(0, &TestError(RetryClass::Fatal)).is_none());
+    assert_eq!(
+      policy.delay(0, &TestError(RetryClass::RetryAfter(Duration::from_secs(2)))),
+      Some(Duration::from_secs(2))
+    );
+    assert!(
+      policy
+        .delay(0, &TestError(RetryClass::RetryAfter(Duration::from_secs(120))))
+        .is_none()
+    );
+  }
+
+  #[tokio::test]
+  async fn test_retry_stops_on_fatal_error() {
+    let policy = RetryPolicy {
+      base_delay: Duration::from_millis(1),
+      ..Default::default()
+    };
+
+    let mut calls = 0;
+    let result: Result<(), _> = retry(&policy, "test", || {
+      calls += 1;
+      let class = if calls == 1 {
+        RetryClass::Transient
+      } else {
+        RetryClass::Fatal
+      };
+      async move { Err(TestError(class)) }
+    })
+    .await;
+
+    assert!(result.is_err());
+    assert_eq!(calls, 2);
+  }
+}
@@ -0,0 +1,31 @@
+use crate::health::{self, BUILD_INFO, BuildInfo};
+use crate::prelude::*;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Liveness {
+  pub ok: bool,
+  pub build: BuildInfo,
+}
+
+/// the process is up and serving requests, dependencies are not checked
+pub async fn healthz() -> impl IntoResponse {
+  Json(Liveness {
+    ok: true,
+    build: BUILD_INFO,
+  })
+}
+
+/// 503 while convex or openrouter are unreachable so load balancers stop routing to us
+#[tracing::instrument(name = "readiness check", skip(state))]
+pub async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+  let readiness = health::readiness(&state).await;
+
+  let status = if readiness.ready {
+    StatusCode::OK
+  } else {
+    StatusCode::SERVICE_UNAVAILABLE
+  };
+
+  (status, Json(readiness))
+}
@@ -0,0 +1,8 @@
+use crate::prelude::*;
+use crate::routes::Router;
+
+pub mod get;
+
+pub fn router() -> Router<AppState> {
+  Router::new().get("/healthz", get::healthz).get("/readyz", get::readyz)
+}
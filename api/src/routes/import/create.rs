@@ -0,0 +1,144 @@
+use std::io::{self, Read};
+
+use axum::body::{Body, Bytes};
+use futures::StreamExt;
+use tokio::sync::mpsc;
+
+use crate::auth::AuthUser;
+use crate::import::{self, Conversation, ImportError, ImportFormat, ImportOptions, ImportResult};
+use crate::prelude::*;
+
+/// exports with years of history are large, but they are parsed while they arrive and never held in memory whole
+const IMPORT_BODY_LIMIT: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportQuery {
+  /// detected from the export when not set
+  pub format: Option<ImportFormat>,
+  #[serde(default)]
+  pub dry_run: bool,
+  #[serde(default)]
+  pub allow_duplicates: bool,
+}
+
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportResponse {
+  pub format: ImportFormat,
+  pub dry_run: bool,
+  pub results: Vec<ImportResult>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImportConversationsError {
+  #[error("export is larger than {IMPORT_BODY_LIMIT} bytes")]
+  TooLarge,
+  #[error("import failed: {0}")]
+  Import(#[from] ImportError),
+  #[error("unexpected error: {0}")]
+  Unexpected(#[from] anyhow::Error),
+}
+
+into_response!(
+  ImportConversationsError {
+    TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+    Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    Import(ImportError::UnknownFormat) => StatusCode::BAD_REQUEST,
+    Import(ImportError::Parse(_)) => StatusCode::BAD_REQUEST,
+    Import(ImportError::Convex(_)) => StatusCode::INTERNAL_SERVER_ERROR,
+  }
+);
+
+/// hands the request body to the blocking json parser chunk by chunk
+struct BodyReader {
+  chunks: mpsc::Receiver<io::Result<Bytes>>,
+  current: Bytes,
+}
+
+impl Read for BodyReader {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    while self.current.is_empty() {
+      match self.chunks.blocking_recv() {
+        Some(chunk) => self.current = chunk?,
+        None => return Ok(0),
+      }
+    }
+
+    let len = buf.len().min(self.current.len());
+    buf[..len].copy_from_slice(&self.current.split_to(len));
+
+    Ok(len)
+  }
+}
+
+/// parses the export while it is uploaded, rejects it once it is larger than [`IMPORT_BODY_LIMIT`]
+async fn parse_body(
+  format: Option<ImportFormat>,
+  body: Body,
+) -> Result<(ImportFormat, Vec<Conversation>), ImportConversationsError> {
+  let (tx, rx) = mpsc::channel(16);
+
+  let parser = tokio::task::spawn_blocking(move || {
+    import::parse(
+      format,
+      BodyReader {
+        chunks: rx,
+        current: Bytes::new(),
+      },
+    )
+  });
+
+  let mut stream = body.into_data_stream();
+  let mut size = 0;
+
+  while let Some(chunk) = stream.next().await {
+    let chunk = chunk.map_err(io::Error::other);
+
+    if let Ok(chunk) = &chunk {
+      size += chunk.len();
+
+      // dropping the sender ends the body early, the parser fails on the truncated json
+      if size > IMPORT_BODY_LIMIT {
+        return Err(ImportConversationsError::TooLarge);
+      }
+    }
+
+    // the parser already stopped on invalid json
+    if tx.send(chunk).await.is_err() {
+      break;
+    }
+  }
+
+  drop(tx);
+
+  Ok(parser.await.context("import parser panicked")??)
+}
+
+#[tracing::instrument("import conversations", skip(state, body), err)]
+#[axum::debug_handler]
+pub async fn import_conversations(
+  State(state): State<AppState>,
+  user: AuthUser,
+  Query(query): Query<ImportQuery>,
+  body: Body,
+) -> Result<Json<ImportResponse>, ImportConversationsError> {
+  let (format, conversations) = parse_body(query.format, body).await?;
+
+  info!("Importing {} {} conversations", conversations.len(), format.as_str());
+
+  let options = ImportOptions {
+    user_id: user.user_id,
+    dry_run: query.dry_run,
+    allow_duplicates: query.allow_duplicates,
+  };
+
+  let mut convex = state.convex.clone();
+  let results = import::import_conversations(&mut convex, format, conversations, &options).await?;
+
+  Ok(Json(ImportResponse {
+    format,
+    dry_run: query.dry_run,
+    results,
+  }))
+}
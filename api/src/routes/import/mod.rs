@@ -0,0 +1,8 @@
+use crate::prelude::*;
+use crate::routes::Router;
+
+pub mod create;
+
+pub fn router() -> Router<AppState> {
+  Router::new().post("/", create::import_conversations)
+}
@@ -0,0 +1,29 @@
+use crate::auth::AuthUser;
+use crate::keys::KeyError;
+use crate::prelude::*;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RemoveKeyError {
+  #[error("no key stored for this user")]
+  NotFound,
+  #[error("failed to remove key: {0}")]
+  Key(#[from] KeyError),
+}
+
+into_response!(
+  RemoveKeyError {
+    NotFound => StatusCode::NOT_FOUND,
+    Key(_) => StatusCode::INTERNAL_SERVER_ERROR,
+  }
+);
+
+#[tracing::instrument(name = "remove key", skip(state), err)]
+pub async fn remove_key(State(state): State<AppState>, user: AuthUser) -> Result<StatusCode, RemoveKeyError> {
+  let mut convex = state.convex.clone();
+
+  if !state.keys.remove(&mut convex, &user.user_id).await? {
+    return Err(RemoveKeyError::NotFound);
+  }
+
+  Ok(StatusCode::NO_CONTENT)
+}
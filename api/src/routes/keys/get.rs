@@ -0,0 +1,37 @@
+use crate::auth::AuthUser;
+use crate::keys::{KeyError, KeyPolicy};
+use crate::prelude::*;
+
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyStatusResponse {
+  pub policy: KeyPolicy,
+  /// whether the server can store keys at all
+  pub storage_enabled: bool,
+  pub has_key: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum GetKeyStatusError {
+  #[error("failed to get key status: {0}")]
+  Key(#[from] KeyError),
+}
+
+into_response!(GetKeyStatusError {
+  Key(_) => StatusCode::INTERNAL_SERVER_ERROR,
+});
+
+/// never returns the key itself
+#[tracing::instrument(name = "get key status", skip(state), err)]
+pub async fn get_key_status(
+  State(state): State<AppState>,
+  user: AuthUser,
+) -> Result<Json<KeyStatusResponse>, GetKeyStatusError> {
+  let mut convex = state.convex.clone();
+
+  Ok(Json(KeyStatusResponse {
+    policy: state.keys.policy(),
+    storage_enabled: state.keys.storage_enabled(),
+    has_key: state.keys.has_key(&mut convex, &user.user_id).await?,
+  }))
+}
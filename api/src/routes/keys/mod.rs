@@ -0,0 +1,16 @@
+use crate::prelude::*;
+use crate::routes::Router;
+
+pub mod delete;
+pub mod get;
+pub mod put;
+pub mod validate;
+
+/// every route acts on the key of the signed in user
+pub fn router() -> Router<AppState> {
+  Router::new()
+    .get("/", get::get_key_status)
+    .put("/", put::store_key)
+    .delete("/", delete::remove_key)
+    .post("/validate", validate::validate_key)
+}
@@ -0,0 +1,50 @@
+use crate::auth::AuthUser;
+use crate::keys::{KeyError, KeyPolicy};
+use crate::prelude::*;
+
+#[derive(Debug, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreKeyRequest {
+  pub key: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum StoreKeyError {
+  #[error("no key specified")]
+  NoKeySpecified,
+  #[error("user keys are not used with the server key policy")]
+  ServerPolicy,
+  #[error("failed to store key: {0}")]
+  Key(#[from] KeyError),
+}
+
+into_response!(
+  StoreKeyError {
+    NoKeySpecified => StatusCode::BAD_REQUEST,
+    ServerPolicy => StatusCode::CONFLICT,
+    Key(KeyError::StorageDisabled) => StatusCode::SERVICE_UNAVAILABLE,
+    Key(_) => StatusCode::INTERNAL_SERVER_ERROR,
+  }
+);
+
+/// encrypts and stores the user's openrouter key, replacing any previous one
+#[tracing::instrument(name = "store key", skip(state, payload), err)]
+pub async fn store_key(
+  State(state): State<AppState>,
+  user: AuthUser,
+  Json(payload): Json<StoreKeyRequest>,
+) -> Result<StatusCode, StoreKeyError> {
+  let key = payload.key.trim();
+  if key.is_empty() {
+    return Err(StoreKeyError::NoKeySpecified);
+  }
+
+  if state.keys.policy() == KeyPolicy::Server {
+    return Err(StoreKeyError::ServerPolicy);
+  }
+
+  let mut convex = state.convex.clone();
+  state.keys.store(&mut convex, &user.user_id, key).await?;
+
+  Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,59 @@
+use crate::auth::AuthUser;
+use crate::keys::KeyError;
+use crate::openrouter::OpenrouterError;
+use crate::openrouter::key_check::KeyStatus;
+use crate::prelude::*;
+
+#[derive(Debug, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidateKeyRequest {
+  /// key to check before storing it, the stored key of the signed in user is checked when not set
+  pub key: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ValidateKeyError {
+  #[error("no key specified")]
+  NoKeySpecified,
+  #[error("no key stored for this user")]
+  NoStoredKey,
+  #[error("failed to read stored key: {0}")]
+  Key(#[from] KeyError),
+  #[error("failed to reach openrouter: {0}")]
+  Openrouter(#[from] OpenrouterError),
+}
+
+into_response!(
+  ValidateKeyError {
+    NoKeySpecified => StatusCode::BAD_REQUEST,
+    NoStoredKey => StatusCode::NOT_FOUND,
+    Key(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    Openrouter(_) => StatusCode::BAD_GATEWAY,
+  }
+);
+
+/// checks a key against openrouter and returns its remaining limit, usage and rate limit
+#[tracing::instrument(name = "validate key", skip_all, err)]
+pub async fn validate_key(
+  State(state): State<AppState>,
+  user: Option<AuthUser>,
+  Json(payload): Json<ValidateKeyRequest>,
+) -> Result<Json<KeyStatus>, ValidateKeyError> {
+  let key = match (payload.key, user) {
+    (Some(key), _) => key.trim().to_string(),
+    (None, Some(user)) => {
+      let mut convex = state.convex.clone();
+
+      state
+        .keys
+        .stored_key(&mut convex, &user.user_id)
+        .await?
+        .ok_or(ValidateKeyError::NoStoredKey)?
+    }
+    (None, None) => return Err(ValidateKeyError::NoKeySpecified),
+  };
+
+  let status = state.key_checks.check(state.openrouter.clone(), &key).await?;
+
+  Ok(Json(status))
+}
@@ -1,13 +1,16 @@
 use axum::Json;
 use axum::extract::State;
 
+use crate::convex::Id;
+use crate::convex::threads::Thread;
 use crate::convex_serde;
 use crate::prelude::*;
 
 #[derive(Debug, Deserialize, Type)]
 #[serde(rename_all = "camelCase")]
 pub struct CancelMessageRequest {
-  pub thread_id: String,
+  #[specta(type = String)]
+  pub thread_id: Id<Thread>,
 }
 
 #[derive(Debug, Serialize, Type)]
@@ -1,34 +1,85 @@
-use std::sync::Arc;
 use std::sync::atomic::{AtomicU32, Ordering};
-use std::time::Instant;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
 use axum::http::HeaderMap;
 use axum::response::Sse;
 use axum::response::sse::Event;
-use base64::Engine;
-use futures::{Stream, StreamExt, pin_mut};
+use futures::{Stream, StreamExt};
 use tokio::sync::{Mutex, mpsc};
+use tracing::Instrument;
 
+use crate::auth::AuthUser;
+use crate::convex::attachments::Attachment;
 use crate::convex::messages::{
-  Annotation as ConvexAnnotation, AnnotationArgs, CompleteMessageArgs, Message as ConvexMessage, MessagePart,
+  Annotation as ConvexAnnotation, ClaimResult, CompleteMessageArgs, Message as ConvexMessage, MessagePart,
   MessageStatus, ModelParams, ReasoningEffort as ConvexReasoningEffort, Role as ConvexRole,
 };
 use crate::convex::threads::Thread;
-use crate::convex::{ConvexClient, ConvexError, attachments, messages, threads};
+use crate::convex::usage::RecordUsageArgs;
+use crate::convex::writer::{FlushPolicy, MessageWriter, WriterError};
+use crate::convex::{ConvexClient, ConvexError, Doc, Id, attachments, messages, threads, usage};
 use crate::convex_serde;
-use crate::openrouter::completions::{OpenrouterEvent, stream_completions, stream_openrouter_chat};
+use crate::keys::KeyError;
+use crate::metrics::METRICS;
+use crate::openrouter::attachments::{AttachmentError, AttachmentFetcher, ExtractOptions, Extractor, skipped_notice};
+use crate::openrouter::completions::{CompletionOptions, OpenrouterEvent, stream_completions, stream_openrouter_chat};
 use crate::openrouter::title::generate_title_from_content;
 use crate::openrouter::types::{
-  Annotation, ChatDelta, ContentPart, File, FileCitationContent, ImageUrl, MessageRequest, ReasoningEffort, Role,
+  Annotation, ChatDelta, ContentPart, DataCollection, FileCitationContent, FinishReason, MessageRequest, Modality,
+  ProviderPreferences, ReasoningEffort, Role, merge_reasoning_details,
 };
-use crate::openrouter::{OpenrouterClient, OpenrouterError};
+use crate::openrouter::{ModelRouting, OpenrouterClient, OpenrouterError};
 use crate::prelude::*;
 
 #[derive(Debug, Deserialize, Type)]
 #[serde(rename_all = "camelCase")]
 pub struct ModelParamsRequest {
   pub reasoning_effort: Option<ReasoningEffortRequest>,
+  /// reasoning token budget for models that take one, replaces `reasoning_effort`
+  pub reasoning_max_tokens: Option<u32>,
+  /// let the model reason without returning or storing the reasoning
+  pub exclude_reasoning: Option<bool>,
   pub include_search: bool,
+  /// models to try in order when the selected one is unavailable, replaces the server default
+  pub fallback_models: Option<Vec<String>>,
+  pub provider: Option<ProviderPreferencesRequest>,
+}
+
+#[derive(Debug, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderPreferencesRequest {
+  pub order: Option<Vec<String>>,
+  pub allow_fallbacks: Option<bool>,
+  pub data_collection: Option<DataCollectionRequest>,
+  pub quantizations: Option<Vec<String>>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum DataCollectionRequest {
+  Allow,
+  Deny,
+}
+
+impl From<DataCollectionRequest> for DataCollection {
+  fn from(val: DataCollectionRequest) -> Self {
+    match val {
+      DataCollectionRequest::Allow => DataCollection::Allow,
+      DataCollectionRequest::Deny => DataCollection::Deny,
+    }
+  }
+}
+
+impl From<ProviderPreferencesRequest> for ProviderPreferences {
+  fn from(val: ProviderPreferencesRequest) -> Self {
+    ProviderPreferences {
+      order: val.order.unwrap_or_default(),
+      allow_fallbacks: val.allow_fallbacks,
+      data_collection: val.data_collection.map(|data_collection| data_collection.into()),
+      quantizations: val.quantizations.unwrap_or_default(),
+    }
+  }
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, Type)]
@@ -59,11 +110,31 @@ impl From<ReasoningEffortRequest> for ConvexReasoningEffort {
   }
 }
 
+/// the params stored on a pending message, it has no fallback models or provider preferences
+impl From<ModelParams> for ModelParamsRequest {
+  fn from(val: ModelParams) -> Self {
+    ModelParamsRequest {
+      reasoning_effort: val.reasoning_effort.map(|effort| match effort {
+        ConvexReasoningEffort::Low => ReasoningEffortRequest::Low,
+        ConvexReasoningEffort::Medium => ReasoningEffortRequest::Medium,
+        ConvexReasoningEffort::High => ReasoningEffortRequest::High,
+      }),
+      reasoning_max_tokens: val.reasoning_max_tokens.map(|tokens| tokens as u32),
+      exclude_reasoning: val.exclude_reasoning,
+      include_search: val.include_search,
+      fallback_models: None,
+      provider: None,
+    }
+  }
+}
+
 #[derive(Debug, Deserialize, Type)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateMessageRequest {
-  pub thread_id: String,
-  pub response_message_id: String,
+  #[specta(type = String)]
+  pub thread_id: Id<Thread>,
+  #[specta(type = String)]
+  pub response_message_id: Id<ConvexMessage>,
   pub model: String,
   pub model_params: Option<ModelParamsRequest>,
 }
@@ -76,6 +147,10 @@ pub enum CreateMessageError {
   ThreadNotFound,
   #[error("response message not pending")]
   ResponseMessageNotPending,
+  #[error("response message is already being generated")]
+  AlreadyGenerating,
+  #[error("response message failed too many times")]
+  AttemptsExhausted,
   #[error("unexpected error: {0}")]
   Unexpected(#[from] anyhow::Error),
   #[error("serialization error: {0}")]
@@ -84,9 +159,12 @@ pub enum CreateMessageError {
   Convex(#[from] ConvexError),
   #[error("no model specified")]
   NoModelSpecified,
-
-  #[error("OpenRouter key not found in headers")]
-  OpenrouterKeyNotFound,
+  #[error("model not allowed: {0}")]
+  ModelNotAllowed(String),
+  #[error("too many generations in progress")]
+  TooManyGenerations,
+  #[error("openrouter key unavailable: {0}")]
+  Key(#[from] KeyError),
 }
 
 into_response!(
@@ -94,149 +172,358 @@ into_response!(
     MessageNotFound => StatusCode::NOT_FOUND,
     ThreadNotFound => StatusCode::NOT_FOUND,
     ResponseMessageNotPending => StatusCode::BAD_REQUEST,
+    AlreadyGenerating => StatusCode::CONFLICT,
+    AttemptsExhausted => StatusCode::CONFLICT,
     NoModelSpecified => StatusCode::BAD_REQUEST,
+    ModelNotAllowed(_) => StatusCode::FORBIDDEN,
+    TooManyGenerations => StatusCode::SERVICE_UNAVAILABLE,
     Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
     Serialization(_) => StatusCode::INTERNAL_SERVER_ERROR,
     Convex(_) => StatusCode::INTERNAL_SERVER_ERROR,
-
-    OpenrouterKeyNotFound => StatusCode::UNAUTHORIZED,
+    Key(KeyError::NoKey) => StatusCode::UNAUTHORIZED,
+    Key(KeyError::QuotaExceeded) => StatusCode::TOO_MANY_REQUESTS,
+    Key(_) => StatusCode::INTERNAL_SERVER_ERROR,
   }
 );
 
 struct ChatContext {
   model: String,
-  message: ConvexMessage,
-  thread: Thread,
+  /// `model` as a bounded metrics label
+  model_label: String,
+  message: Doc<ConvexMessage>,
+  thread: Doc<Thread>,
   complete_args: CompleteMessageArgs,
   custom_key: Option<String>,
   set_title: bool,
+  title_model: String,
   messages: Vec<MessageRequest>,
   reasoning_effort: Option<ReasoningEffort>,
+  reasoning_max_tokens: Option<u32>,
+  exclude_reasoning: bool,
   enable_pdf: bool,
+  routing: ModelRouting,
+  max_continuations: u32,
+  attachments: AttachmentFetcher,
 }
 
-fn encode_base64(mime_type: String, bytes: &[u8]) -> String {
-  let base64 = base64::engine::general_purpose::STANDARD.encode(bytes);
+/// attachment the model did not get, reported to the client
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SkippedAttachment {
+  name: String,
+  /// see [`AttachmentError::code`], `too_large` when a size limit was hit
+  code: &'static str,
+  reason: String,
+}
 
-  format!("data:{mime_type};base64,{base64}")
+/// whether the model takes audio, looked up the first time a message contains audio
+enum AudioInput<'a> {
+  Unknown {
+    openrouter: &'a Arc<Mutex<OpenrouterClient>>,
+    model: &'a str,
+  },
+  Known(bool),
 }
 
-async fn convex_to_messages(client: &mut ConvexClient, message: ConvexMessage) -> anyhow::Result<MessageRequest> {
-  let role = match message.role {
-    ConvexRole::User => Role::User,
-    ConvexRole::Assistant => Role::Assistant,
-    ConvexRole::System => Role::System,
-  };
+impl AudioInput<'_> {
+  async fn accepted(&mut self) -> bool {
+    let AudioInput::Unknown { openrouter, model } = self else {
+      return matches!(self, AudioInput::Known(true));
+    };
 
-  let mut request = MessageRequest { role, content: vec![] };
+    let accepted = match openrouter.lock().await.get_models().await {
+      Ok(models) => models
+        .data
+        .iter()
+        .any(|m| m.id == *model && m.architecture.input_modalities.contains(&Modality::Audio)),
+      Err(err) => {
+        warn!("Failed to look up audio support of {model}: {err}");
+        false
+      }
+    };
 
-  for part in message.parts {
-    let part = match part {
-      MessagePart::Text { text } => ContentPart::Text { text },
-      MessagePart::Attachment { id } => {
-        let attachment = attachments::get_by_id(client, id.clone()).await?;
-        let Some(attachment) = attachment else {
-          warn!("Attachment with ID {id} not found");
-          continue;
-        };
+    *self = AudioInput::Known(accepted);
+    accepted
+  }
+}
 
-        // reject unsupported before downloading
-        match attachment.mime_type.as_ref() {
-          "application/json" | "image/png" | "image/jpeg" | "image/webp" | "application/pdf" => {}
-          _ if attachment.mime_type.starts_with("text/") => {}
-          _ => {
-            warn!("Unsupported attachment type: {}", attachment.mime_type);
-            continue;
-          }
-        }
+/// downloads and converts attachments for one model
+struct AttachmentLoader<'a> {
+  fetcher: &'a AttachmentFetcher,
+  options: ExtractOptions,
+  audio_input: AudioInput<'a>,
+}
 
-        // download data from attachment url
-        let response = reqwest::get(&attachment.url).await?;
-        if !response.status().is_success() {
-          warn!("Failed to download attachment with ID {id}: {}", response.status());
-          continue;
-        }
+impl<'a> AttachmentLoader<'a> {
+  fn new(fetcher: &'a AttachmentFetcher, model: &str, audio_input: AudioInput<'a>) -> Self {
+    Self {
+      fetcher,
+      options: ExtractOptions {
+        max_image_dimension: fetcher.config.image_dimension_for(model),
+      },
+      audio_input,
+    }
+  }
 
-        let bytes = response.bytes().await?;
+  /// looks up what the downloads depend on once, so they can run concurrently afterwards
+  async fn prepare<'b>(&mut self, attachments: impl IntoIterator<Item = &'b Attachment>) {
+    let has_audio = attachments
+      .into_iter()
+      .any(|attachment| Extractor::for_mime_type(&attachment.mime_type) == Some(Extractor::Audio));
 
-        match attachment.mime_type.as_ref() {
-          "image/png" | "image/jpeg" | "image/webp" => {
-            let base64_data = encode_base64(attachment.mime_type, &bytes);
-            ContentPart::Image {
-              image_url: ImageUrl { url: base64_data },
-            }
-          }
-          "application/json" => ContentPart::Text {
-            text: String::from_utf8_lossy(&bytes).to_string(),
-          },
-          _ if attachment.mime_type.starts_with("text/") => ContentPart::Text {
-            text: String::from_utf8_lossy(&bytes).to_string(),
-          },
-          "application/pdf" => {
-            let base64_data = encode_base64(attachment.mime_type, &bytes);
-            ContentPart::File {
-              file: File {
-                filename: attachment.name.clone(),
-                file_data: base64_data,
-              },
-            }
-          }
-          _ => unreachable!(),
+    if has_audio {
+      self.audio_input.accepted().await;
+    }
+  }
+
+  /// everything that can be rejected without the file is checked before downloading.
+  /// audio is only accepted after [`Self::prepare`] found that the model takes it
+  async fn load(&self, attachment: &Attachment) -> Result<ContentPart, AttachmentError> {
+    let extractor = match Extractor::for_mime_type(&attachment.mime_type) {
+      Some(Extractor::Audio) if !matches!(self.audio_input, AudioInput::Known(true)) => {
+        return Err(AttachmentError::AudioNotAccepted);
+      }
+      Some(extractor) => extractor,
+      None => return Err(AttachmentError::Unsupported(attachment.mime_type.clone())),
+    };
+
+    let limit = self.fetcher.config.limit_for(&attachment.mime_type);
+    if attachment.size > limit as f64 {
+      return Err(AttachmentError::TooLarge { limit });
+    }
+
+    let bytes = self.fetcher.download(&attachment.url, limit).await?;
+
+    extractor.extract(attachment.name.clone(), &attachment.mime_type, &bytes, &self.options)
+  }
+}
+
+/// a converted message and the attachments it could not include
+struct PromptMessage {
+  request: MessageRequest,
+  skipped: Vec<SkippedAttachment>,
+}
+
+/// `keep_reasoning` decides per message whether its stored reasoning is sent back, only useful when the same model
+/// continues the conversation. attachment metadata and files are fetched concurrently, at most
+/// `max_concurrent_downloads` at a time, and put back in their original place. attachments that can't be sent are
+/// replaced by a notice and reported in `skipped`
+async fn convex_to_messages(
+  client: &ConvexClient,
+  messages: Vec<Doc<ConvexMessage>>,
+  keep_reasoning: impl Fn(&ConvexMessage) -> bool,
+  loader: &mut AttachmentLoader<'_>,
+) -> Vec<PromptMessage> {
+  let concurrency = loader.fetcher.config.max_concurrent_downloads;
+
+  let ids = messages
+    .iter()
+    .flat_map(|message| &message.parts)
+    .filter_map(|part| match part {
+      MessagePart::Attachment { id } => Some(id.clone()),
+      MessagePart::Text { .. } => None,
+    })
+    .collect::<Vec<_>>();
+
+  let found = futures::stream::iter(ids)
+    .map(|id| {
+      let mut client = client.clone();
+
+      async move {
+        let result = attachments::get_by_id(&mut client, id.clone()).await;
+        (id, result)
+      }
+    })
+    .buffered(concurrency)
+    .collect::<Vec<_>>()
+    .await;
+
+  loader
+    .prepare(found.iter().filter_map(|(_, result)| result.as_ref().ok()?.as_deref()))
+    .await;
+
+  let loader = &*loader;
+
+  // `buffered` keeps the order, so results line up with the attachment parts again
+  let mut loaded = futures::stream::iter(found)
+    .map(|(id, result)| async move {
+      // a missing attachment is reported like any other that can't be sent, instead of vanishing from the prompt
+      let attachment = match result {
+        Ok(Some(attachment)) => attachment,
+        Ok(None) => {
+          warn!("Attachment with ID {id} not found");
+          return (id.into_string(), Err(AttachmentError::Lookup("not found".into())));
+        }
+        Err(err) => {
+          warn!("Failed to look up attachment with ID {id}: {err}");
+          return (id.into_string(), Err(AttachmentError::Lookup(err.to_string())));
         }
+      };
+
+      let part = loader.load(&attachment).await;
+      if let Err(err) = &part {
+        warn!("Skipping attachment with ID {id}: {err}");
       }
+
+      (attachment.fields.name, part)
+    })
+    .buffered(concurrency)
+    .collect::<Vec<_>>()
+    .await
+    .into_iter();
+
+  let mut prompt = Vec::with_capacity(messages.len());
+
+  for message in messages {
+    let (id, message) = (message.id, message.fields);
+    let role = match message.role {
+      ConvexRole::User => Role::User,
+      ConvexRole::Assistant => Role::Assistant,
+      ConvexRole::System => Role::System,
     };
 
-    request.content.push(part);
+    let mut request = MessageRequest::new(role, vec![]);
+    let mut skipped = vec![];
+
+    if role == Role::Assistant && keep_reasoning(&message) {
+      request.reasoning = message.reasoning;
+      request.reasoning_details = match message.reasoning_details.as_deref().map(serde_json::from_str) {
+        Some(Ok(details)) => Some(details),
+        Some(Err(err)) => {
+          warn!("Dropping invalid reasoning details of message ID {id}: {err}");
+          None
+        }
+        None => None,
+      };
+    }
+
+    for part in message.parts {
+      let part = match part {
+        MessagePart::Text { text } => ContentPart::Text { text },
+        MessagePart::Attachment { .. } => match loaded.next() {
+          Some((_, Ok(part))) => part,
+          Some((name, Err(err))) => {
+            let notice = skipped_notice(&name, &err);
+            skipped.push(SkippedAttachment {
+              name,
+              code: err.code(),
+              reason: err.to_string(),
+            });
+
+            notice
+          }
+          None => continue,
+        },
+      };
+
+      request.content.push(part);
+    }
+
+    prompt.push(PromptMessage { request, skipped });
   }
 
-  Ok(request)
+  prompt
 }
 
 #[tracing::instrument("create message", skip(state), err)]
 #[axum::debug_handler]
 pub async fn create_message(
   State(state): State<AppState>,
+  user: Option<AuthUser>,
   headers: HeaderMap,
   Json(payload): Json<CreateMessageRequest>,
 ) -> Result<Sse<impl Stream<Item = Result<Event, CreateMessageError>>>, CreateMessageError> {
   debug!("creating chat with payload: {:?}", payload);
 
-  let message_id = payload.response_message_id.trim();
-  if message_id.is_empty() {
+  // still accepted so clients that keep the key in the browser keep working
+  let supplied_key = headers
+    .get("X-OpenRouter-Key")
+    .and_then(|value| value.to_str().ok())
+    .map(|s| s.to_string());
+
+  let mut chat_rx = start_generation(&state, payload, Requester::Http { user, supplied_key }).await?;
+
+  let stream = async_stream::stream! {
+    while let Some(event) = chat_rx.recv().await {
+      yield Ok(Event::default().data(event.into_string()?).event("message"))
+    }
+
+    yield Ok(Event::default().event("end"));
+  };
+
+  Ok(Sse::new(stream))
+}
+
+/// starts the generation for a pending message without anyone listening to its events, used by the worker
+pub(crate) async fn generate(state: &AppState, payload: CreateMessageRequest) -> Result<(), CreateMessageError> {
+  start_generation(state, payload, Requester::Worker).await?;
+
+  Ok(())
+}
+
+/// who started a generation, the stored key and the server key are only used on behalf of the thread owner
+enum Requester {
+  /// picks up messages the owner created through convex
+  Worker,
+  /// anonymous when `user` is not set
+  Http {
+    user: Option<AuthUser>,
+    supplied_key: Option<String>,
+  },
+}
+
+/// checks the request, claims the response message and spawns the generation. the generation keeps running when
+/// the returned receiver is dropped
+async fn start_generation(
+  state: &AppState,
+  payload: CreateMessageRequest,
+  requester: Requester,
+) -> Result<mpsc::Receiver<ChatEvent>, CreateMessageError> {
+  let response_message_id = payload.response_message_id.as_str().trim();
+  if response_message_id.is_empty() {
     return Err(CreateMessageError::MessageNotFound);
   }
 
-  let thread_id = payload.thread_id.trim();
+  let thread_id = payload.thread_id.as_str().trim();
   if thread_id.is_empty() {
     return Err(CreateMessageError::ThreadNotFound);
   }
 
-  let response_message_id = payload.response_message_id.trim();
-  if response_message_id.is_empty() {
-    return Err(CreateMessageError::MessageNotFound);
-  }
-
   let model = payload.model.trim();
   if model.is_empty() {
     return Err(CreateMessageError::NoModelSpecified);
   }
 
-  let custom_key = headers
-    .get("X-OpenRouter-Key")
-    .and_then(|value| value.to_str().ok())
-    .map(|s| s.to_string());
-
-  let Some(custom_key) = custom_key else {
-    return Err(CreateMessageError::OpenrouterKeyNotFound);
-  };
+  let fallback_models = payload
+    .model_params
+    .as_ref()
+    .and_then(|params| params.fallback_models.as_deref())
+    .unwrap_or_default();
+
+  if let Some(model) = std::iter::once(model)
+    .chain(fallback_models.iter().map(String::as_str))
+    .find(|model| !state.models.is_allowed(model))
+  {
+    return Err(CreateMessageError::ModelNotAllowed(model.to_string()));
+  }
 
   let mut convex = state.convex.clone();
 
-  let Some(thread) = threads::get_by_id(&mut convex, thread_id.to_string()).await? else {
+  let Some(thread) = threads::get_by_id(&mut convex, Id::new(thread_id)).await? else {
     return Err(CreateMessageError::ThreadNotFound);
   };
 
-  let Some(message) = messages::get_by_id(&mut convex, response_message_id.to_string()).await? else {
+  let (supplied_key, is_owner) = match requester {
+    Requester::Worker => (None, true),
+    Requester::Http { user, supplied_key } => (supplied_key, user.is_some_and(|user| user.user_id == thread.user_id)),
+  };
+
+  let custom_key = state
+    .keys
+    .resolve(&mut convex, &thread.user_id, supplied_key, is_owner)
+    .await?;
+
+  let Some(message) = messages::get_by_id(&mut convex, Id::new(response_message_id)).await? else {
     return Err(CreateMessageError::MessageNotFound);
   };
 
@@ -244,16 +531,84 @@ pub async fn create_message(
     return Err(CreateMessageError::ResponseMessageNotPending);
   }
 
-  let Some(convex_messages) = messages::get_until(&mut convex, thread.id.clone(), message.id.clone()).await? else {
-    return Err(CreateMessageError::MessageNotFound);
+  let (text_tx, chat_rx) = mpsc::channel(128);
+  let (kill_tx, kill_rx) = mpsc::channel(1);
+
+  {
+    let mut active_threads = state.active_threads.lock().await;
+
+    // replacing the entry would drop the kill sender of the running generation
+    if active_threads.contains_key(&thread.id) {
+      return Err(CreateMessageError::AlreadyGenerating);
+    }
+
+    if active_threads.len() >= state.limits.max_concurrent_generations {
+      return Err(CreateMessageError::TooManyGenerations);
+    }
+
+    active_threads.insert(thread.id.clone(), kill_tx);
+  }
+
+  // claimed before the prompt is assembled, so attachments aren't downloaded for a message someone else generates
+  let lease = state.limits.generation_lease;
+  let claimed = messages::claim(
+    &mut convex,
+    message.id.clone(),
+    state.instance_id.clone(),
+    lease.as_millis() as f64,
+    state.limits.max_generation_attempts as f64,
+  )
+  .await;
+
+  if !matches!(claimed, Ok(ClaimResult::Claimed)) {
+    state.active_threads.lock().await.remove(&thread.id);
+
+    return Err(match claimed? {
+      ClaimResult::Failed => CreateMessageError::AttemptsExhausted,
+      _ => CreateMessageError::AlreadyGenerating,
+    });
+  }
+
+  let convex_messages = match messages::get_until(&mut convex, thread.id.clone(), message.id.clone()).await {
+    Ok(Some(convex_messages)) => convex_messages,
+    result => {
+      release_claim(state, &thread.id, &message.id).await;
+
+      return Err(match result {
+        Err(err) => err.into(),
+        Ok(_) => CreateMessageError::MessageNotFound,
+      });
+    }
   };
 
   let mut messages = vec![];
+  // only attachments of the latest user message are reported, older ones were reported on their own turn
+  let mut skipped_attachments = vec![];
 
   let mut enable_pdf = false;
 
-  for message in convex_messages {
-    let message = convex_to_messages(&mut convex, message).await?;
+  let passes_reasoning = state.models.passes_reasoning(model);
+  let mut loader = AttachmentLoader::new(
+    &state.attachments,
+    model,
+    AudioInput::Unknown {
+      openrouter: &state.openrouter,
+      model,
+    },
+  );
+
+  // reasoning blocks are only accepted by the model that produced them, compared by the requested slug because the
+  // served model is reported under openrouter's own id
+  let keep_reasoning = |message: &ConvexMessage| passes_reasoning && message.model.as_deref() == Some(model);
+
+  let prompt = convex_to_messages(&convex, convex_messages, keep_reasoning, &mut loader).await;
+
+  for prompt_message in prompt {
+    let message = prompt_message.request;
+
+    if message.role == Role::User {
+      skipped_attachments = prompt_message.skipped;
+    }
 
     if message
       .content
@@ -268,22 +623,30 @@ pub async fn create_message(
 
   let complete_args = CompleteMessageArgs {
     message_id: message.id.clone(),
+    claimed_by: state.instance_id.clone(),
     model: payload.model.clone(),
     model_params: payload.model_params.as_ref().map(|params| ModelParams {
       reasoning_effort: params.reasoning_effort.map(|effort| effort.into()),
       include_search: params.include_search,
+      reasoning_max_tokens: params.reasoning_max_tokens.map(|tokens| tokens as f64),
+      exclude_reasoning: params.exclude_reasoning,
     }),
     prompt_token_count: 0.0,
     token_count: 0.0,
     duration_ms: 0.0,
     tokens_per_second: 0.0,
     time_to_first_token_ms: 0.0,
+    served_model: None,
+    provider: None,
+    reasoning_token_count: 0.0,
+    reasoning_details: None,
+    finish_reason: None,
+    continuation_count: 0.0,
   };
 
-  let (text_tx, mut chat_rx) = mpsc::channel(128);
-  let (kill_tx, kill_rx) = mpsc::channel(1);
-
-  state.active_threads.lock().await.insert(thread.id.clone(), kill_tx);
+  if !skipped_attachments.is_empty() {
+    let _ = text_tx.send(ChatEvent::SkippedAttachments(skipped_attachments)).await;
+  }
 
   let set_title = thread.title.is_none();
 
@@ -291,43 +654,122 @@ pub async fn create_message(
   let convex_client = state.convex.clone();
   let active_threads = state.active_threads.clone();
   let thread_id = thread.id.clone();
+  let message_id = message.id.clone();
+  let instance_id = state.instance_id.clone();
+  let worker_enabled = state.worker_enabled;
 
   let reasoning_effort = payload
     .model_params
+    .as_ref()
     .and_then(|p| p.reasoning_effort.map(|effort| effort.into()));
+  let reasoning_max_tokens = payload.model_params.as_ref().and_then(|p| p.reasoning_max_tokens);
+  let exclude_reasoning = payload
+    .model_params
+    .as_ref()
+    .and_then(|p| p.exclude_reasoning)
+    .unwrap_or_default();
+
+  let routing = payload
+    .model_params
+    .map(|params| ModelRouting {
+      fallback_models: params.fallback_models.unwrap_or_default(),
+      provider: params.provider.map(|provider| provider.into()),
+    })
+    .unwrap_or_default();
 
   let context = ChatContext {
     model: model.to_string(),
+    model_label: state.models.metrics_label(model),
     message,
     thread,
     complete_args,
-    // force user supplied key for now
-    custom_key: Some(custom_key),
+    custom_key,
     set_title,
+    title_model: state.models.title.clone(),
     messages,
     reasoning_effort,
+    reasoning_max_tokens,
+    exclude_reasoning,
     enable_pdf,
+    routing,
+    max_continuations: state.models.max_continuations,
+    attachments: state.attachments.clone(),
   };
 
-  tokio::spawn(async move {
-    let result = stream_chat(openrouter, convex_client, text_tx, kill_rx, context).await;
+  // keep the generation in the trace of the request that started it
+  tokio::spawn(
+    async move {
+      let mut convex_client = convex_client;
+      let heartbeat = hold_lease(convex_client.clone(), message_id.clone(), instance_id.clone(), lease);
 
-    active_threads.lock().await.remove(&thread_id);
+      METRICS.active_generations.inc();
+      let result = tokio::select! {
+        result = stream_chat(openrouter, convex_client.clone(), text_tx, kill_rx, context) => Some(result),
+        () = heartbeat => None,
+      };
+      METRICS.active_generations.dec();
 
-    if let Err(err) = result {
-      error!("Failed to stream chat: {:?}", err);
-    }
-  });
+      active_threads.lock().await.remove(&thread_id);
 
-  let stream = async_stream::stream! {
-    while let Some(event) = chat_rx.recv().await {
-      yield Ok(Event::default().data(event.into_string()?).event("message"))
+      match result {
+        Some(Ok(())) => {}
+        Some(Err(err)) => {
+          METRICS.generation_errors.with_label_values(&[err.kind()]).inc();
+          error!("Failed to stream chat: {:?}", err);
+
+          give_up(&mut convex_client, message_id, instance_id, worker_enabled).await;
+        }
+        None => warn!("Lost the lease on message {message_id}, stopped generating it"),
+      }
     }
+    .in_current_span(),
+  );
 
-    yield Ok(Event::default().event("end"));
+  Ok(chat_rx)
+}
+
+/// undoes the claim when the generation can't be started after all
+async fn release_claim(state: &AppState, thread_id: &Id<Thread>, message_id: &Id<ConvexMessage>) {
+  state.active_threads.lock().await.remove(thread_id);
+
+  let mut convex = state.convex.clone();
+  let instance_id = state.instance_id.clone();
+  give_up(&mut convex, message_id.clone(), instance_id, state.worker_enabled).await;
+}
+
+/// leaves a failed message to the worker instead of waiting for the lease to expire. without a worker nothing would
+/// pick it up again, so it is marked as failed instead
+async fn give_up(convex: &mut ConvexClient, message_id: Id<ConvexMessage>, instance_id: String, worker_enabled: bool) {
+  let result = if worker_enabled {
+    messages::release(convex, message_id.clone(), instance_id).await
+  } else {
+    messages::fail(convex, message_id.clone(), instance_id).await
   };
 
-  Ok(Sse::new(stream))
+  if let Err(err) = result {
+    warn!("Failed to give up message {message_id}: {:?}", err);
+  }
+}
+
+/// renews the claim on the message a few times per lease, returns once another instance took it over
+async fn hold_lease(mut convex: ConvexClient, message_id: Id<ConvexMessage>, instance_id: String, lease: Duration) {
+  loop {
+    tokio::time::sleep(lease / 3).await;
+
+    // a failed heartbeat is retried on the next one, the lease outlives two of them
+    match messages::renew_lease(
+      &mut convex,
+      message_id.clone(),
+      instance_id.clone(),
+      lease.as_millis() as f64,
+    )
+    .await
+    {
+      Ok(true) => {}
+      Ok(false) => return,
+      Err(err) => warn!("Failed to renew the lease on message {message_id}: {:?}", err),
+    }
+  }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -340,14 +782,27 @@ pub enum StreamChatError {
   ConvexError(#[from] ConvexError),
   #[error("failed to create OpenRouter stream: {0}")]
   OpenRouter(anyhow::Error),
-  #[error("failed to append text to message")]
-  AppendText,
+  #[error("failed to persist streamed output: {0}")]
+  Writer(#[from] WriterError),
   #[error("failed to complete message")]
   CompleteMessage,
   #[error("failed to set thread title")]
   SetThreadTitle,
-  #[error("failed to append annotations to message")]
-  AppendAnnotations,
+}
+
+impl StreamChatError {
+  /// stable label for metrics
+  fn kind(&self) -> &'static str {
+    match self {
+      StreamChatError::Unexpected(_) => "unexpected",
+      StreamChatError::OpenrouterError(_) => "openrouter",
+      StreamChatError::ConvexError(_) => "convex",
+      StreamChatError::OpenRouter(_) => "openrouter_stream",
+      StreamChatError::Writer(_) => "writer",
+      StreamChatError::CompleteMessage => "complete_message",
+      StreamChatError::SetThreadTitle => "set_thread_title",
+    }
+  }
 }
 
 enum ChatEvent {
@@ -359,6 +814,8 @@ enum ChatEvent {
   End,
   Unauthorized,
   Annotations(Vec<ConvexAnnotation>),
+  Finished(FinishReason),
+  SkippedAttachments(Vec<SkippedAttachment>),
 }
 
 impl ChatEvent {
@@ -375,12 +832,21 @@ impl ChatEvent {
       ChatEvent::Refusal(refusal) => format!("5:{refusal}"),
       ChatEvent::End => "6:".into(),
       ChatEvent::Unauthorized => "7:".into(),
+      ChatEvent::Finished(reason) => format!("8:{}", reason.as_str()),
+      ChatEvent::SkippedAttachments(skipped) => {
+        let serialized = serde_json::to_string(&skipped).context("failed to serialize skipped attachments")?;
+        format!("9:{serialized}")
+      }
     };
 
     Ok(string)
   }
 }
 
+/// sent after an answer was cut off by the token limit, the truncated answer is part of the prompt
+const CONTINUE_PROMPT: &str =
+  "Your previous answer was cut off. Continue exactly where it stopped, without repeating anything.";
+
 fn sanitize_text(text: &str) -> String {
   text.replace('\r', "").replace('\n', "\\n")
 }
@@ -412,7 +878,7 @@ fn openrouter_annotation_to_convex(annotation: Annotation) -> ConvexAnnotation {
   }
 }
 
-#[tracing::instrument("stream_chat", skip_all, fields(model = context.model, message_id = context.message.id, thread_id = context.thread.id), err)]
+#[tracing::instrument("stream_chat", skip_all, fields(model = context.model, message_id = %context.message.id, thread_id = %context.thread.id), err)]
 async fn stream_chat(
   openrouter: Arc<Mutex<OpenrouterClient>>,
   mut convex_client: ConvexClient,
@@ -422,26 +888,39 @@ async fn stream_chat(
 ) -> Result<(), StreamChatError> {
   let using_custom_key = context.custom_key.is_some();
 
-  let source = stream_completions(
+  let options = CompletionOptions {
+    reasoning: context.reasoning_effort,
+    reasoning_max_tokens: context.reasoning_max_tokens,
+    exclude_reasoning: context.exclude_reasoning,
+    enable_pdf: context.enable_pdf,
+    routing: context.routing,
+    ..Default::default()
+  };
+
+  // the prompt is sent again to continue an answer that was cut off
+  let prompt = (context.max_continuations > 0).then(|| context.messages.clone());
+
+  let request = stream_completions(
     openrouter.clone(),
     &context.model,
     context.messages,
     context.custom_key.clone(),
-    None,
-    context.reasoning_effort,
-    context.enable_pdf,
+    options.clone(),
   )
   .await?;
 
-  let (stream_trigger, stream) = stream_openrouter_chat(source, using_custom_key)
+  let (stream_trigger, stream) = stream_openrouter_chat(request, using_custom_key)
     .await
     .map_err(StreamChatError::OpenRouter)?;
 
-  pin_mut!(stream);
+  let mut stream = Box::pin(stream);
+  // replaced by every continuation, dropping a trigger cancels its stream
+  let stream_trigger = Arc::new(std::sync::Mutex::new(Some(stream_trigger)));
 
   // region: kill listener
 
   let kl_message_id = context.message.id.clone();
+  let kl_stream_trigger = stream_trigger.clone();
   let mut kl_convex_client = convex_client.clone();
 
   let kill_listener = async move {
@@ -452,7 +931,9 @@ async fn stream_chat(
     let _ = kill_rx.recv().await;
 
     info!("streaming killed, cleaning up");
-    stream_trigger.cancel();
+    if let Some(trigger) = kl_stream_trigger.lock().unwrap().take() {
+      trigger.cancel();
+    }
 
     if !messages::cancel(&mut kl_convex_client, kl_message_id).await? {
       error!("failed to cancel message in Convex");
@@ -466,29 +947,114 @@ async fn stream_chat(
   let start = Instant::now();
   let prompt_token_count = Arc::new(AtomicU32::new(0));
   let completion_token_count = Arc::new(AtomicU32::new(0));
+  let reasoning_token_count = Arc::new(AtomicU32::new(0));
+  let reasoning_details = Arc::new(std::sync::Mutex::new(Vec::new()));
   let time_to_first_token_ms = Arc::new(AtomicU32::new(0));
+  let finish_reason = Arc::new(std::sync::Mutex::new(None));
+  let continuation_count = Arc::new(AtomicU32::new(0));
+  // model and provider reported by openrouter, set from the first chunk
+  let served_by = Arc::new(OnceLock::<(String, Option<String>)>::new());
 
   // region: event listener
 
-  let mut el_convex_client = convex_client.clone();
+  let (writer, writer_handle) = MessageWriter::spawn(
+    convex_client.clone(),
+    context.message.id.clone(),
+    context.complete_args.claimed_by.clone(),
+    FlushPolicy::default(),
+  );
+
+  let el_writer = writer.clone();
   let el_chat_tx = chat_tx.clone();
 
   let el_message_id = context.message.id.clone();
 
   let el_prompt_token_count = prompt_token_count.clone();
   let el_completion_token_count = completion_token_count.clone();
+  let el_reasoning_token_count = reasoning_token_count.clone();
+  let el_reasoning_details = reasoning_details.clone();
   let el_time_to_first_token_ms = time_to_first_token_ms.clone();
+  let el_served_by = served_by.clone();
+  let el_finish_reason = finish_reason.clone();
+  let el_continuation_count = continuation_count.clone();
+  let el_stream_trigger = stream_trigger.clone();
+  let el_openrouter = openrouter.clone();
+  let el_model = context.model.clone();
+  let el_custom_key = context.custom_key.clone();
 
   let event_listener = async move {
-    let mut text_accumulator = String::new();
-    let mut reasoning_accumulator = String::new();
-
     info!("Starting OpenRouter chat stream for message ID: {}", context.message.id);
 
+    // answer text of all requests, the prompt of a continuation
+    let mut answer = String::new();
+
     let final_event = loop {
       let Some(event) = stream.next().await else {
         info!("OpenRouter stream ended for message ID: {}", context.message.id);
-        break ChatEvent::End;
+
+        let continuations = el_continuation_count.load(Ordering::Relaxed);
+        let truncated = *el_finish_reason.lock().unwrap() == Some(FinishReason::Length);
+
+        let Some(prompt) = prompt
+          .as_ref()
+          .filter(|_| truncated && continuations < context.max_continuations)
+        else {
+          break ChatEvent::End;
+        };
+
+        info!(
+          "Answer for message ID {} was cut off, continuing ({}/{})",
+          context.message.id,
+          continuations + 1,
+          context.max_continuations
+        );
+
+        let mut messages = prompt.clone();
+        messages.push(MessageRequest::new(
+          Role::Assistant,
+          vec![ContentPart::Text { text: answer.clone() }],
+        ));
+        messages.push(MessageRequest::new(
+          Role::User,
+          vec![ContentPart::Text {
+            text: CONTINUE_PROMPT.into(),
+          }],
+        ));
+
+        // keep the truncated answer when a continuation can't be started
+        let request = match stream_completions(
+          el_openrouter.clone(),
+          &el_model,
+          messages,
+          el_custom_key.clone(),
+          options.clone(),
+        )
+        .await
+        {
+          Ok(request) => request,
+          Err(err) => {
+            error!("Failed to continue message ID {}: {err:?}", context.message.id);
+            break ChatEvent::End;
+          }
+        };
+
+        match stream_openrouter_chat(request, using_custom_key).await {
+          Ok((trigger, next)) => {
+            *el_stream_trigger.lock().unwrap() = Some(trigger);
+            stream = Box::pin(next);
+          }
+          Err(err) => {
+            error!("Failed to continue message ID {}: {err:?}", context.message.id);
+            break ChatEvent::End;
+          }
+        }
+
+        el_continuation_count.fetch_add(1, Ordering::Relaxed);
+        *el_finish_reason.lock().unwrap() = None;
+        // reasoning blocks of different requests can't be merged, only the latest is kept
+        el_reasoning_details.lock().unwrap().clear();
+
+        continue;
       };
 
       let mut completion = match event {
@@ -497,9 +1063,15 @@ async fn stream_chat(
         OpenrouterEvent::Error => break ChatEvent::Error,
       };
 
+      if let Some(model) = completion.model.take() {
+        let _ = el_served_by.set((model, completion.provider.take()));
+      }
+
       if let Some(usage) = completion.usage {
-        el_prompt_token_count.store(usage.prompt_tokens, Ordering::Relaxed);
-        el_completion_token_count.store(usage.completion_tokens, Ordering::Relaxed);
+        // usage is reported once per request, continuations add to it
+        el_prompt_token_count.fetch_add(usage.prompt_tokens, Ordering::Relaxed);
+        el_completion_token_count.fetch_add(usage.completion_tokens, Ordering::Relaxed);
+        el_reasoning_token_count.fetch_add(usage.reasoning_tokens(), Ordering::Relaxed);
       }
 
       if completion.choices.is_empty() {
@@ -509,20 +1081,31 @@ async fn stream_chat(
 
       let choice = completion.choices.swap_remove(0);
 
+      if let Some(reason) = choice.finish_reason {
+        *el_finish_reason.lock().unwrap() = Some(reason);
+      }
+
       let (text, annotations) = match choice.delta {
         ChatDelta::Text {
           content,
           reasoning,
+          reasoning_details,
           annotations,
         } => {
+          if let Some(details) = reasoning_details {
+            merge_reasoning_details(&mut el_reasoning_details.lock().unwrap(), details);
+          }
+
           if let Some(reasoning) = reasoning {
-            info!("Received reasoning: {}", reasoning);
             (ReasoningOrText::Reasoning(reasoning), annotations)
           } else {
             (ReasoningOrText::Text(content), annotations)
           }
         }
-        ChatDelta::Finished { .. } => break ChatEvent::End,
+        ChatDelta::Finished { finish_reason } => {
+          *el_finish_reason.lock().unwrap() = Some(finish_reason);
+          continue;
+        }
         ChatDelta::Refusal { refusal } => break ChatEvent::Refusal(refusal),
       };
 
@@ -533,11 +1116,12 @@ async fn stream_chat(
 
       let event = match text {
         ReasoningOrText::Reasoning(text) => {
-          reasoning_accumulator.push_str(&text);
+          el_writer.reasoning(&text).await?;
           ChatEvent::Reasoning(sanitize_text(&text))
         }
         ReasoningOrText::Text(text) => {
-          text_accumulator.push_str(&text);
+          el_writer.text(&text).await?;
+          answer.push_str(&text);
           ChatEvent::Text(sanitize_text(&text))
         }
       };
@@ -550,71 +1134,14 @@ async fn stream_chat(
 
         if !annotations.is_empty() {
           let _ = el_chat_tx.send(ChatEvent::Annotations(annotations.clone())).await;
-
-          let args = AnnotationArgs {
-            message_id: context.message.id.clone(),
-            annotations,
-          };
-
-          let success = messages::append_annotations(&mut el_convex_client, args).await?;
-
-          if !success {
-            return Err(StreamChatError::AppendAnnotations);
-          }
+          el_writer.annotations(annotations).await?;
         }
       }
 
       let _ = el_chat_tx.send(event).await;
-
-      if text_accumulator.len() >= 100 {
-        let success = messages::append_text(
-          &mut el_convex_client,
-          context.message.id.clone(),
-          text_accumulator.clone(),
-        )
-        .await?;
-
-        if !success {
-          return Err(StreamChatError::AppendText);
-        }
-
-        text_accumulator.clear();
-      }
-
-      if reasoning_accumulator.len() >= 100 {
-        let success = messages::append_reasoning(
-          &mut el_convex_client,
-          context.message.id.clone(),
-          reasoning_accumulator.clone(),
-        )
-        .await?;
-
-        if !success {
-          return Err(StreamChatError::AppendText);
-        }
-
-        reasoning_accumulator.clear();
-      }
     };
 
-    if !text_accumulator.is_empty() {
-      let success = messages::append_text(&mut el_convex_client, context.message.id.clone(), text_accumulator).await?;
-
-      if !success {
-        return Err(StreamChatError::AppendText);
-      }
-    }
-
-    if !reasoning_accumulator.is_empty() {
-      let success =
-        messages::append_reasoning(&mut el_convex_client, context.message.id, reasoning_accumulator).await?;
-
-      if !success {
-        return Err(StreamChatError::AppendText);
-      }
-    }
-
-    Ok(final_event)
+    Ok::<_, StreamChatError>(final_event)
   };
 
   // endregion
@@ -639,13 +1166,88 @@ async fn stream_chat(
 
   let prompt_token_count = prompt_token_count.load(Ordering::Relaxed);
 
+  let model = [context.model_label.as_str()];
+
+  if time_to_first_token_ms > 0 {
+    METRICS
+      .time_to_first_token
+      .with_label_values(&model)
+      .observe(time_to_first_token_ms as f64 / 1000.0);
+  }
+
+  match &final_event {
+    ChatEvent::End => {
+      METRICS.completions.with_label_values(&model).inc();
+      METRICS
+        .generation_duration
+        .with_label_values(&model)
+        .observe(duration.as_secs_f64());
+      if token_count > 0 {
+        METRICS
+          .tokens_per_second
+          .with_label_values(&model)
+          .observe(tokens_per_second);
+      }
+    }
+    ChatEvent::Cancelled => METRICS.cancellations.with_label_values(&model).inc(),
+    ChatEvent::Refusal(_) => METRICS.refusals.with_label_values(&model).inc(),
+    // the stream ended with an error event rather than a StreamChatError
+    ChatEvent::Error => METRICS.generation_errors.with_label_values(&["stream"]).inc(),
+    ChatEvent::Unauthorized => METRICS.generation_errors.with_label_values(&["unauthorized"]).inc(),
+    ChatEvent::Text(_)
+    | ChatEvent::Reasoning(_)
+    | ChatEvent::Annotations(_)
+    | ChatEvent::Finished(_)
+    | ChatEvent::SkippedAttachments(_) => {}
+  }
+
+  let finish_reason = *finish_reason.lock().unwrap();
+  let continuation_count = continuation_count.load(Ordering::Relaxed);
+
+  if let Some(reason) = finish_reason
+    && matches!(final_event, ChatEvent::End)
+  {
+    if reason == FinishReason::Length {
+      warn!("Answer for message ID {el_message_id} was cut off after {continuation_count} continuations");
+    }
+
+    let _ = chat_tx.send(ChatEvent::Finished(reason)).await;
+  }
+
   let _ = chat_tx.send(final_event).await;
 
+  // flush whatever is still buffered, this also runs when the stream was cancelled
+  writer.close();
+  let writer_stats = writer_handle.await.context("message writer panicked")??;
+
+  debug!(
+    "Persisted {} bytes in {} writes for message ID: {}",
+    writer_stats.bytes, writer_stats.writes, el_message_id
+  );
+
   context.complete_args.prompt_token_count = prompt_token_count as f64;
   context.complete_args.token_count = token_count as f64;
   context.complete_args.duration_ms = duration_ms as f64;
   context.complete_args.tokens_per_second = tokens_per_second;
   context.complete_args.time_to_first_token_ms = time_to_first_token_ms as f64;
+  context.complete_args.reasoning_token_count = reasoning_token_count.load(Ordering::Relaxed) as f64;
+  context.complete_args.finish_reason = finish_reason.map(|reason| reason.as_str().to_string());
+  context.complete_args.continuation_count = continuation_count as f64;
+
+  let reasoning_details = std::mem::take(&mut *reasoning_details.lock().unwrap());
+  if !reasoning_details.is_empty() {
+    context.complete_args.reasoning_details =
+      Some(serde_json::to_string(&reasoning_details).context("failed to serialize reasoning details")?);
+  }
+
+  if let Some((served_model, provider)) = served_by.get() {
+    if *served_model != context.model {
+      info!("Message ID {el_message_id} was served by fallback model {served_model}");
+    }
+
+    context.complete_args.served_model = Some(served_model.clone());
+    context.complete_args.provider = provider.clone();
+  }
 
   let success = messages::complete(&mut convex_client, &context.complete_args).await?;
 
@@ -653,16 +1255,42 @@ async fn stream_chat(
     return Err(StreamChatError::CompleteMessage);
   }
 
-  if context.set_title {
-    let convex_messages = messages::get_by_thread_id(&mut convex_client, context.thread.id.clone()).await?;
-
-    let mut messages = vec![];
+  // generations paid by the server key count towards the user's daily quota
+  if !using_custom_key && (prompt_token_count > 0 || token_count > 0) {
+    let args = RecordUsageArgs {
+      user_id: context.thread.user_id.clone(),
+      token_id: None,
+      model: context.model.clone(),
+      served_model: context.complete_args.served_model.clone(),
+      prompt_tokens: prompt_token_count as f64,
+      completion_tokens: token_count as f64,
+    };
 
-    for message in convex_messages {
-      messages.push(convex_to_messages(&mut convex_client, message).await?);
+    if let Err(err) = usage::record(&mut convex_client, &args).await {
+      error!("Failed to record usage for message ID {el_message_id}: {err:?}");
     }
+  }
+
+  if context.set_title {
+    let convex_messages = messages::get_by_thread_id(&mut convex_client, context.thread.id.clone()).await?;
 
-    match generate_title_from_content(openrouter, context.thread.id.clone(), messages, context.custom_key).await {
+    let mut loader = AttachmentLoader::new(&context.attachments, &context.title_model, AudioInput::Known(false));
+
+    let messages = convex_to_messages(&convex_client, convex_messages, |_| false, &mut loader)
+      .await
+      .into_iter()
+      .map(|message| message.request)
+      .collect();
+
+    match generate_title_from_content(
+      openrouter,
+      &context.title_model,
+      context.thread.id.to_string(),
+      messages,
+      context.custom_key,
+    )
+    .await
+    {
       Ok(title) => {
         info!("Setting thread title to: {}", title);
         let success = threads::set_title(&mut convex_client, context.thread.id, title).await?;
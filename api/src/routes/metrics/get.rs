@@ -0,0 +1,21 @@
+use axum::http::header::CONTENT_TYPE;
+
+use crate::metrics::METRICS;
+use crate::prelude::*;
+
+#[derive(Debug, thiserror::Error)]
+pub enum GetMetricsError {
+  #[error("failed to encode metrics: {0}")]
+  Encode(#[from] prometheus::Error),
+}
+
+into_response!(GetMetricsError {
+  Encode(_) => StatusCode::INTERNAL_SERVER_ERROR,
+});
+
+#[tracing::instrument(name = "get metrics", err)]
+pub async fn get_metrics() -> Result<impl IntoResponse, GetMetricsError> {
+  let body = METRICS.encode()?;
+
+  Ok(([(CONTENT_TYPE, "text/plain; version=0.0.4")], body))
+}
@@ -3,12 +3,24 @@ pub use router::{RouteInfo, Router, print_routes};
 
 use crate::prelude::*;
 
-mod message;
+mod health;
+mod import;
+mod keys;
+pub(crate) mod message;
+mod metrics;
 mod models;
+mod threads;
+mod v1;
 
-#[tracing::instrument(name = "creating main router", skip(_state))]
-pub fn router(_state: AppState) -> Router<AppState> {
+#[tracing::instrument(name = "creating main router")]
+pub fn router() -> Router<AppState> {
   Router::new()
+    .merge(health::router())
+    .nest("/import", import::router())
+    .nest("/keys", keys::router())
     .nest("/message", message::router())
+    .nest("/metrics", metrics::router())
     .nest("/models", models::router())
+    .nest("/threads", threads::router())
+    .nest("/v1", v1::router())
 }
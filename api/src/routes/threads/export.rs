@@ -0,0 +1,114 @@
+use axum::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+
+use crate::auth::AuthUser;
+use crate::convex::{ConvexError, Id, threads};
+use crate::prelude::*;
+use crate::transcript::{ExportOptions, build_transcript, render_html, render_markdown};
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+  #[default]
+  #[serde(rename = "md")]
+  Markdown,
+  #[serde(rename = "json")]
+  Json,
+  #[serde(rename = "html")]
+  Html,
+}
+
+impl ExportFormat {
+  fn content_type(&self) -> &'static str {
+    match self {
+      ExportFormat::Markdown => "text/markdown; charset=utf-8",
+      ExportFormat::Json => "application/json",
+      ExportFormat::Html => "text/html; charset=utf-8",
+    }
+  }
+
+  fn extension(&self) -> &'static str {
+    match self {
+      ExportFormat::Markdown => "md",
+      ExportFormat::Json => "json",
+      ExportFormat::Html => "html",
+    }
+  }
+}
+
+#[derive(Debug, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportThreadQuery {
+  #[serde(default)]
+  pub format: ExportFormat,
+  /// include model reasoning in the export
+  #[serde(default)]
+  pub reasoning: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExportThreadError {
+  #[error("thread not found")]
+  ThreadNotFound,
+  #[error("failed to read thread: {0}")]
+  Convex(#[from] ConvexError),
+  #[error("failed to serialize transcript: {0}")]
+  Serialization(#[from] serde_json::Error),
+}
+
+into_response!(
+  ExportThreadError {
+    ThreadNotFound => StatusCode::NOT_FOUND,
+    Convex(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    Serialization(_) => StatusCode::INTERNAL_SERVER_ERROR,
+  }
+);
+
+#[tracing::instrument("export thread", skip(state), err)]
+#[axum::debug_handler]
+pub async fn export_thread(
+  State(state): State<AppState>,
+  user: AuthUser,
+  Path(id): Path<String>,
+  Query(query): Query<ExportThreadQuery>,
+) -> Result<impl IntoResponse, ExportThreadError> {
+  let thread_id = id.trim();
+  if thread_id.is_empty() {
+    return Err(ExportThreadError::ThreadNotFound);
+  }
+
+  let mut convex = state.convex.clone();
+
+  // threads of other users look the same as missing ones
+  let Some(thread) = threads::get_by_id(&mut convex, Id::new(thread_id))
+    .await?
+    .filter(|thread| thread.user_id == user.user_id)
+  else {
+    return Err(ExportThreadError::ThreadNotFound);
+  };
+
+  let options = ExportOptions {
+    include_reasoning: query.reasoning,
+  };
+
+  let transcript = build_transcript(&mut convex, thread, &options).await?;
+
+  let body = match query.format {
+    ExportFormat::Markdown => render_markdown(&transcript),
+    ExportFormat::Json => serde_json::to_string_pretty(&transcript)?,
+    ExportFormat::Html => render_html(&transcript),
+  };
+
+  let disposition = format!(
+    "attachment; filename=\"{}.{}\"",
+    transcript.file_stem(),
+    query.format.extension()
+  );
+
+  Ok((
+    [
+      (CONTENT_TYPE, query.format.content_type().to_string()),
+      (CONTENT_DISPOSITION, disposition),
+    ],
+    body,
+  ))
+}
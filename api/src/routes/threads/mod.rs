@@ -0,0 +1,8 @@
+use crate::prelude::*;
+use crate::routes::Router;
+
+pub mod export;
+
+pub fn router() -> Router<AppState> {
+  Router::new().get("/{id}/export", export::export_thread)
+}
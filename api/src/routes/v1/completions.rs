@@ -0,0 +1,216 @@
+use axum::response::sse::Event;
+use axum::response::{Response, Sse};
+use futures::StreamExt;
+
+use crate::convex::Doc;
+use crate::convex::api_tokens::ApiToken;
+use crate::convex::usage::{self, RecordUsageArgs};
+use crate::openrouter::completions::{
+  CompletionOptions, OpenrouterEvent, get_completions, stream_completions, stream_openrouter_chat,
+};
+use crate::openrouter::types::{ChatDelta, ChatUsage, ContentPart, MessageRequest};
+use crate::prelude::*;
+use crate::proxy::tokens::ApiTokenAuth;
+use crate::proxy::types::{
+  ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse, ChunkChoice, ChunkDelta, ResponseChoice,
+  ResponseMessage, Usage,
+};
+use crate::proxy::{ProxyError, error_body};
+
+fn completion_id() -> String {
+  format!("chatcmpl-{:016x}", fastrand::u64(..))
+}
+
+/// logs the usage of every request and stores it when the token belongs to a user
+fn record_usage(state: &AppState, token: &Doc<ApiToken>, model: &str, served_model: Option<String>, usage: &ChatUsage) {
+  info!(
+    token = token.name,
+    model,
+    served_model,
+    prompt_tokens = usage.prompt_tokens,
+    completion_tokens = usage.completion_tokens,
+    "Proxy completion finished"
+  );
+
+  let Some(user_id) = token.user_id.clone() else {
+    return;
+  };
+
+  let args = RecordUsageArgs {
+    user_id,
+    token_id: Some(token.id.clone()),
+    model: model.to_string(),
+    served_model,
+    prompt_tokens: usage.prompt_tokens as f64,
+    completion_tokens: usage.completion_tokens as f64,
+  };
+
+  let mut convex = state.convex.clone();
+  tokio::spawn(async move {
+    if let Err(err) = usage::record(&mut convex, &args).await {
+      error!("Failed to record usage: {err:?}");
+    }
+  });
+}
+
+#[tracing::instrument(name = "proxy completion", skip_all, fields(model = request.model, stream = request.stream), err)]
+pub async fn create_completion(
+  ApiTokenAuth(token): ApiTokenAuth,
+  State(state): State<AppState>,
+  Json(request): Json<ChatCompletionRequest>,
+) -> Result<Response, ProxyError> {
+  if request.model.is_empty() {
+    return Err(ProxyError::NoModelSpecified);
+  }
+
+  if !state.models.is_allowed(&request.model) {
+    return Err(ProxyError::ModelNotAllowed(request.model));
+  }
+
+  let messages = request
+    .messages
+    .into_iter()
+    .map(MessageRequest::from)
+    .collect::<Vec<_>>();
+
+  let enable_pdf = messages
+    .iter()
+    .flat_map(|message| &message.content)
+    .any(|part| matches!(part, ContentPart::File { .. }));
+
+  let options = CompletionOptions {
+    max_tokens: request.max_completion_tokens.or(request.max_tokens),
+    reasoning: request.reasoning_effort,
+    enable_pdf,
+    ..Default::default()
+  };
+
+  let include_usage = request.stream_options.is_some_and(|options| options.include_usage);
+
+  if !request.stream {
+    let response = get_completions(state.openrouter.clone(), &request.model, messages, None, options).await?;
+
+    if let Some(usage) = &response.usage {
+      record_usage(&state, &token, &request.model, response.model.clone(), usage);
+    }
+
+    let choices = response
+      .choices
+      .into_iter()
+      .zip(0..)
+      .map(|(choice, index)| ResponseChoice {
+        index,
+        message: ResponseMessage {
+          role: "assistant",
+          content: choice.message.content,
+        },
+        finish_reason: choice.finish_reason,
+      })
+      .collect();
+
+    let response = ChatCompletionResponse {
+      id: completion_id(),
+      object: "chat.completion",
+      created: chrono::Utc::now().timestamp(),
+      model: response.model.unwrap_or(request.model),
+      choices,
+      usage: response
+        .usage
+        .map(|usage| Usage::new(usage.prompt_tokens, usage.completion_tokens)),
+    };
+
+    return Ok(Json(response).into_response());
+  }
+
+  let builder = stream_completions(state.openrouter.clone(), &request.model, messages, None, options).await?;
+  let (trigger, events) = stream_openrouter_chat(builder, false).await?;
+
+  let id = completion_id();
+  let created = chrono::Utc::now().timestamp();
+  let model = request.model;
+
+  let chunk = move |model: &str, delta: ChunkDelta, finish_reason: Option<String>| ChatCompletionChunk {
+    id: id.clone(),
+    object: "chat.completion.chunk",
+    created,
+    model: model.to_string(),
+    choices: vec![ChunkChoice {
+      index: 0,
+      delta,
+      finish_reason,
+    }],
+    usage: None,
+  };
+
+  let stream = async_stream::stream! {
+    // dropping the trigger cancels the upstream request, so it lives as long as the client is connected
+    let _trigger = trigger;
+    let mut events = std::pin::pin!(events);
+
+    let mut role = Some("assistant");
+    let mut served_model = None;
+    let mut finish_reason = None;
+    let mut last_usage = None;
+
+    while let Some(event) = events.next().await {
+      let completion = match event {
+        OpenrouterEvent::Completion(completion) => completion,
+        OpenrouterEvent::Error | OpenrouterEvent::Unauthorized => {
+          let body = error_body(StatusCode::BAD_GATEWAY, "upstream stream failed".into());
+          yield Event::default().json_data(body);
+          return;
+        }
+      };
+
+      served_model = completion.model.or(served_model);
+      last_usage = completion.usage.or(last_usage);
+
+      let model = served_model.as_deref().unwrap_or(&model);
+
+      for choice in completion.choices {
+        finish_reason = choice.finish_reason.or(finish_reason);
+
+        let delta = match choice.delta {
+          ChatDelta::Text { content, reasoning, .. } => ChunkDelta {
+            role: role.take(),
+            content: Some(content),
+            reasoning,
+            refusal: None,
+          },
+          ChatDelta::Refusal { refusal } => ChunkDelta {
+            role: role.take(),
+            refusal: Some(refusal),
+            ..Default::default()
+          },
+          ChatDelta::Finished { finish_reason: reason } => {
+            finish_reason = Some(reason);
+            continue;
+          }
+        };
+
+        yield Event::default().json_data(chunk(model, delta, None));
+      }
+    }
+
+    let model = served_model.as_deref().unwrap_or(&model);
+    let finish_reason = finish_reason.map_or("stop", |reason| reason.as_str()).to_string();
+
+    yield Event::default().json_data(chunk(model, ChunkDelta::default(), Some(finish_reason)));
+
+    if let Some(usage) = &last_usage {
+      record_usage(&state, &token, model, served_model.clone(), usage);
+
+      if include_usage {
+        let mut last = chunk(model, ChunkDelta::default(), None);
+        last.choices.clear();
+        last.usage = Some(Usage::new(usage.prompt_tokens, usage.completion_tokens));
+
+        yield Event::default().json_data(last);
+      }
+    }
+
+    yield Ok(Event::default().data("[DONE]"));
+  };
+
+  Ok(Sse::new(stream).into_response())
+}
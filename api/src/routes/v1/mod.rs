@@ -0,0 +1,12 @@
+use crate::prelude::*;
+use crate::routes::Router;
+
+pub mod completions;
+pub mod models;
+
+/// openai compatible api, authenticated with api tokens instead of user sessions
+pub fn router() -> Router<AppState> {
+  Router::new()
+    .post("/chat/completions", completions::create_completion)
+    .get("/models", models::list_models)
+}
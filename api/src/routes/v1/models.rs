@@ -0,0 +1,18 @@
+use crate::prelude::*;
+use crate::proxy::ProxyError;
+use crate::proxy::tokens::ApiTokenAuth;
+use crate::proxy::types::{ModelList, ModelObject};
+
+#[tracing::instrument(name = "list proxy models", skip_all, err)]
+pub async fn list_models(_auth: ApiTokenAuth, State(state): State<AppState>) -> Result<Json<ModelList>, ProxyError> {
+  let models = state.openrouter.lock().await.get_models().await?;
+
+  let data = models
+    .data
+    .into_iter()
+    .filter(|model| state.models.is_allowed(&model.id))
+    .map(|model| ModelObject::new(model.id, model.created))
+    .collect();
+
+  Ok(Json(ModelList { object: "list", data }))
+}
@@ -1,25 +1,32 @@
 use anyhow::Context;
+use axum::middleware;
 use snowflake::SnowflakeGenerator;
 use tokio::net::TcpListener;
 use tower_http::cors::CorsLayer;
 
-use crate::config::{Config, EPOCH_MS};
+use crate::config::{Config, EPOCH_MS, LimitsConfig, ModelsConfig, WorkerConfig};
 use crate::convex::{ConvexClient, create_convex_client};
+use crate::keys::KeyManager;
+use crate::openrouter::attachments::AttachmentFetcher;
 use crate::openrouter::{OpenrouterClient, create_openrouter_client};
 use crate::prelude::*;
 use crate::routes::{RouteInfo, Router, print_routes, router};
+use crate::{telemetry, worker};
 
 pub struct Application {
   port: u16,
   listener: TcpListener,
   state: AppState,
   router: Router<AppState>,
+  worker: WorkerConfig,
 }
 
 impl Application {
   pub async fn build(config: Config) -> anyhow::Result<Self> {
     let openrouter = create_openrouter_client(&config.openrouter)?;
+    let keys = KeyManager::new(&config.keys)?;
     let convex = create_convex_client(&config.convex).await?;
+    let attachments = AttachmentFetcher::new(config.attachments)?;
 
     let snowflakes = SnowflakeGenerator::new(config.snowflake.worker, config.snowflake.process, EPOCH_MS);
 
@@ -31,13 +38,22 @@ impl Application {
 
     let port = listener.local_addr()?.port();
 
-    let (state, router) = create_router(openrouter, convex, snowflakes);
+    let (state, router) = create_router(
+      openrouter,
+      convex,
+      snowflakes,
+      config.limits,
+      config.models,
+      keys,
+      attachments,
+    );
 
     Ok(Self {
       port,
       listener,
       state,
       router,
+      worker: config.worker,
     })
   }
 
@@ -52,13 +68,20 @@ impl Application {
   pub async fn run_until_stopped(self) -> std::io::Result<()> {
     let Self {
       listener,
-      state,
+      mut state,
       router,
+      worker,
       ..
     } = self;
 
+    state.worker_enabled = worker.enabled;
+    if worker.enabled {
+      tokio::spawn(worker::run(state.clone()));
+    }
+
     let app = axum::Router::new()
       .merge(router)
+      .layer(middleware::from_fn(telemetry::propagate_trace_context))
       .layer(CorsLayer::permissive())
       .with_state(state);
 
@@ -71,14 +94,23 @@ async fn root() -> &'static str {
   "hello awa"
 }
 
+/// every route served by the application, also used to export the route helpers without a running server
+pub fn app_router() -> Router<AppState> {
+  router().get("/", root)
+}
+
 fn create_router(
   openrouter: OpenrouterClient,
   convex: ConvexClient,
   snowflakes: SnowflakeGenerator,
+  limits: LimitsConfig,
+  models: ModelsConfig,
+  keys: KeyManager,
+  attachments: AttachmentFetcher,
 ) -> (AppState, Router<AppState>) {
-  let state = AppState::new(openrouter, convex, snowflakes);
+  let state = AppState::new(openrouter, convex, snowflakes, limits, models, keys, attachments);
 
-  let router = router(state.clone()).get("/", root);
+  let router = app_router();
 
   print_routes(&router.routes);
 
@@ -4,17 +4,35 @@ use std::sync::Arc;
 use snowflake::SnowflakeGenerator;
 use tokio::sync::{Mutex, mpsc};
 
-use crate::convex::ConvexClient;
+use crate::config::{LimitsConfig, ModelsConfig};
+use crate::convex::threads::Thread;
+use crate::convex::{ConvexClient, Id};
+use crate::health::ReadinessCache;
+use crate::keys::KeyManager;
 use crate::openrouter::OpenrouterClient;
+use crate::openrouter::attachments::AttachmentFetcher;
+use crate::openrouter::key_check::KeyCheckCache;
 
 #[derive(Clone)]
 pub struct AppState {
   pub openrouter: Arc<Mutex<OpenrouterClient>>,
   pub convex: ConvexClient,
   pub snowflakes: Arc<Mutex<SnowflakeGenerator>>,
+  pub limits: LimitsConfig,
+  pub models: Arc<ModelsConfig>,
+  pub keys: Arc<KeyManager>,
+  pub attachments: AttachmentFetcher,
 
+  /// random id of this process, recorded on the messages it claims
+  pub instance_id: String,
+  /// whether this process runs the worker, failed generations are only left pending for it when it does
+  pub worker_enabled: bool,
   /// map of thread ids to kill signal senders
-  pub active_threads: Arc<Mutex<HashMap<String, mpsc::Sender<()>>>>,
+  pub active_threads: Arc<Mutex<HashMap<Id<Thread>, mpsc::Sender<()>>>>,
+  /// last dependency check results served by `/readyz`
+  pub readiness: Arc<ReadinessCache>,
+  /// recent openrouter key checks served by `/keys/validate`
+  pub key_checks: Arc<KeyCheckCache>,
 }
 
 fn am<T>(value: T) -> Arc<Mutex<T>> {
@@ -22,13 +40,29 @@ fn am<T>(value: T) -> Arc<Mutex<T>> {
 }
 
 impl AppState {
-  pub fn new(openrouter: OpenrouterClient, convex: ConvexClient, snowflakes: SnowflakeGenerator) -> Self {
+  pub fn new(
+    openrouter: OpenrouterClient,
+    convex: ConvexClient,
+    snowflakes: SnowflakeGenerator,
+    limits: LimitsConfig,
+    models: ModelsConfig,
+    keys: KeyManager,
+    attachments: AttachmentFetcher,
+  ) -> Self {
     Self {
       openrouter: am(openrouter),
       convex,
       snowflakes: am(snowflakes),
+      limits,
+      models: Arc::new(models),
+      keys: Arc::new(keys),
+      attachments,
 
+      instance_id: format!("{:016x}", fastrand::u64(..)),
+      worker_enabled: false,
       active_threads: am(HashMap::new()),
+      readiness: Arc::default(),
+      key_checks: Arc::default(),
     }
   }
 }
@@ -0,0 +1,91 @@
+use axum::extract::Request;
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use opentelemetry::global;
+use opentelemetry::propagation::{Extractor, Injector};
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+  fn get(&self, key: &str) -> Option<&str> {
+    self.0.get(key).and_then(|value| value.to_str().ok())
+  }
+
+  fn keys(&self) -> Vec<&str> {
+    self.0.keys().map(|key| key.as_str()).collect()
+  }
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+  fn set(&mut self, key: &str, value: String) {
+    if value.is_empty() {
+      return;
+    }
+
+    if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(key.as_bytes()), HeaderValue::from_str(&value)) {
+      self.0.insert(name, value);
+    }
+  }
+}
+
+/// wraps every request in a span that continues the trace from an incoming `traceparent` header
+pub async fn propagate_trace_context(request: Request, next: Next) -> Response {
+  let parent = global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(request.headers())));
+
+  let span = tracing::info_span!(
+    "http request",
+    otel.kind = "server",
+    http.request.method = %request.method(),
+    url.path = %request.uri().path(),
+  );
+  let _ = span.set_parent(parent);
+
+  next.run(request).instrument(span).await
+}
+
+/// `traceparent` headers for an outgoing request made from the current span
+pub fn trace_headers() -> HeaderMap {
+  let mut headers = HeaderMap::new();
+  let context = tracing::Span::current().context();
+
+  global::get_text_map_propagator(|propagator| propagator.inject_context(&context, &mut HeaderInjector(&mut headers)));
+
+  headers
+}
+
+#[cfg(test)]
+mod tests {
+  use opentelemetry::propagation::TextMapPropagator;
+  use opentelemetry::trace::TraceContextExt;
+  use opentelemetry_sdk::propagation::TraceContextPropagator;
+
+  use super::*;
+
+  const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+  #[test]
+  fn test_traceparent_round_trip() {
+    let propagator = TraceContextPropagator::new();
+
+    let mut incoming = HeaderMap::new();
+    incoming.insert("traceparent", HeaderValue::from_static(TRACEPARENT));
+
+    let context = propagator.extract(&HeaderExtractor(&incoming));
+    assert_eq!(
+      context.span().span_context().trace_id().to_string(),
+      "4bf92f3577b34da6a3ce929d0e0e4736"
+    );
+
+    let mut outgoing = HeaderMap::new();
+    propagator.inject_context(&context, &mut HeaderInjector(&mut outgoing));
+
+    assert_eq!(outgoing.get("traceparent").unwrap(), TRACEPARENT);
+    // empty tracestate is not forwarded
+    assert!(outgoing.get("tracestate").is_none());
+  }
+}
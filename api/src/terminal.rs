@@ -0,0 +1,357 @@
+use std::io::Write as _;
+use std::path::Path;
+use std::sync::Arc;
+
+use futures::StreamExt;
+use owo_colors::OwoColorize;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::Mutex;
+
+use crate::cli::ChatArgs;
+use crate::config::Config;
+use crate::convex::imports::{self, ImportMessageArgs, ImportRole, ImportThreadArgs};
+use crate::convex::threads::Thread;
+use crate::convex::{Id, create_convex_client};
+use crate::openrouter::attachments::ExtractOptions;
+use crate::openrouter::completions::{CompletionOptions, OpenrouterEvent, stream_completions, stream_openrouter_chat};
+use crate::openrouter::title::generate_title_from_content;
+use crate::openrouter::types::{ChatDelta, ContentPart, MessageRequest, Role};
+use crate::openrouter::{self, OpenrouterClient, create_openrouter_client};
+use crate::prelude::*;
+
+const HELP: &str = "/attach <path> adds a file to the next message, /reset starts over, /exit quits";
+
+/// guesses the mime type from the extension, unknown files are sent as text when they are valid utf-8
+fn mime_type(path: &Path, bytes: &[u8]) -> Option<&'static str> {
+  let extension = path
+    .extension()
+    .and_then(|extension| extension.to_str())
+    .map(str::to_ascii_lowercase)
+    .unwrap_or_default();
+
+  let mime_type = match extension.as_str() {
+    "png" => "image/png",
+    "jpg" | "jpeg" => "image/jpeg",
+    "webp" => "image/webp",
+    "pdf" => "application/pdf",
+    "json" => "application/json",
+    "md" | "markdown" => "text/markdown",
+    "html" | "htm" => "text/html",
+    "csv" => "text/csv",
+    "gif" => "image/gif",
+    "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    "xls" => "application/vnd.ms-excel",
+    "mp3" => "audio/mpeg",
+    "wav" => "audio/wav",
+    _ if std::str::from_utf8(bytes).is_ok() => "text/plain",
+    _ => return None,
+  };
+
+  Some(mime_type)
+}
+
+/// reads a local file into the same content part an uploaded attachment would become
+fn read_attachment(path: &Path, options: &ExtractOptions) -> anyhow::Result<ContentPart> {
+  let bytes = std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+
+  let name = path
+    .file_name()
+    .map(|name| name.to_string_lossy().to_string())
+    .unwrap_or_default();
+
+  let mime_type = mime_type(path, &bytes).with_context(|| format!("unsupported attachment: {}", path.display()))?;
+
+  openrouter::attachments::attachment_part(name, mime_type, &bytes, options)
+    .with_context(|| format!("failed to attach {}", path.display()))
+}
+
+fn text_of(message: &MessageRequest) -> String {
+  message
+    .content
+    .iter()
+    .filter_map(|part| match part {
+      ContentPart::Text { text } => Some(text.as_str()),
+      _ => None,
+    })
+    .collect::<Vec<_>>()
+    .join("\n\n")
+}
+
+fn text_message(role: Role, text: String) -> MessageRequest {
+  MessageRequest::new(role, vec![ContentPart::Text { text }])
+}
+
+/// streams one reply to stdout, ctrl-c stops the generation and keeps what arrived so far
+async fn stream_reply(
+  openrouter: &Arc<Mutex<OpenrouterClient>>,
+  args: &ChatArgs,
+  messages: &[MessageRequest],
+) -> anyhow::Result<String> {
+  let enable_pdf = messages
+    .iter()
+    .flat_map(|message| &message.content)
+    .any(|part| matches!(part, ContentPart::File { .. }));
+
+  let options = CompletionOptions {
+    reasoning: args.reasoning,
+    enable_pdf,
+    ..Default::default()
+  };
+
+  let request = stream_completions(
+    openrouter.clone(),
+    &args.model,
+    messages.to_vec(),
+    args.key.clone(),
+    options,
+  )
+  .await?;
+
+  let (trigger, stream) = stream_openrouter_chat(request, args.key.is_some()).await?;
+  let mut stream = std::pin::pin!(stream);
+
+  let mut reply = String::new();
+  let mut reasoning = false;
+  let mut usage = None;
+
+  loop {
+    let event = tokio::select! {
+      event = stream.next() => event,
+      _ = tokio::signal::ctrl_c() => {
+        trigger.cancel();
+        print!("\n{}", "cancelled".dimmed());
+        break;
+      }
+    };
+
+    let Some(event) = event else {
+      break;
+    };
+
+    let completion = match event {
+      OpenrouterEvent::Completion(completion) => completion,
+      OpenrouterEvent::Error => bail!("openrouter stream failed"),
+      OpenrouterEvent::Unauthorized => bail!("openrouter rejected the key"),
+    };
+
+    usage = completion.usage.or(usage);
+
+    for choice in completion.choices {
+      match choice.delta {
+        ChatDelta::Text {
+          content,
+          reasoning: reasoning_delta,
+          ..
+        } => {
+          if let Some(reasoning_delta) = reasoning_delta {
+            reasoning = true;
+            print!("{}", reasoning_delta.dimmed());
+          }
+
+          if !content.is_empty() {
+            // separate the reasoning from the answer
+            if std::mem::take(&mut reasoning) {
+              print!("\n\n");
+            }
+
+            print!("{content}");
+            reply.push_str(&content);
+          }
+        }
+        ChatDelta::Refusal { refusal } => print!("{}", refusal.red()),
+        ChatDelta::Finished { .. } => {}
+      }
+    }
+
+    std::io::stdout().flush()?;
+  }
+
+  println!();
+
+  if let Some(usage) = usage {
+    let tokens = format!(
+      "{} prompt, {} completion tokens",
+      usage.prompt_tokens, usage.completion_tokens
+    );
+    println!("{}", tokens.dimmed());
+  }
+
+  Ok(reply)
+}
+
+/// stores the text of the session as a thread, attachments are not uploaded
+async fn save_session(
+  config: &Config,
+  openrouter: Arc<Mutex<OpenrouterClient>>,
+  args: &ChatArgs,
+  user_id: String,
+  messages: &[MessageRequest],
+  started_at: Timestamp,
+) -> anyhow::Result<Id<Thread>> {
+  let messages = messages
+    .iter()
+    .map(|message| text_message(message.role, text_of(message)))
+    .collect::<Vec<_>>();
+
+  let title = match generate_title_from_content(
+    openrouter,
+    &config.models.title,
+    "terminal".into(),
+    messages.clone(),
+    args.key.clone(),
+  )
+  .await
+  {
+    Ok(title) => Some(title),
+    Err(err) => {
+      warn!("Failed to generate title: {err:?}");
+      None
+    }
+  };
+
+  let messages = messages
+    .into_iter()
+    .filter_map(|message| {
+      let role = match message.role {
+        Role::User => ImportRole::User,
+        Role::Assistant => ImportRole::Assistant,
+        Role::System => ImportRole::System,
+        Role::Function | Role::Tool => return None,
+      };
+
+      Some(ImportMessageArgs {
+        role,
+        model: (role == ImportRole::Assistant).then(|| args.model.clone()),
+        text: text_of(&message),
+        created_at: None,
+      })
+    })
+    .collect();
+
+  let args = ImportThreadArgs {
+    user_id,
+    import_id: format!("terminal:{}", started_at.timestamp_millis()),
+    title,
+    model: args.model.clone(),
+    created_at: started_at.timestamp_millis() as f64,
+    updated_at: chrono::Utc::now().timestamp_millis() as f64,
+    messages,
+  };
+
+  let mut convex = create_convex_client(&config.convex).await?;
+
+  Ok(imports::import_thread(&mut convex, args).await?)
+}
+
+/// interactive chat using the same request building and stream parsing as the web app
+pub async fn run_chat(config: &Config, args: ChatArgs) -> anyhow::Result<()> {
+  let openrouter = Arc::new(Mutex::new(create_openrouter_client(&config.openrouter)?));
+  let started_at = chrono::Utc::now();
+
+  let options = ExtractOptions {
+    max_image_dimension: config.attachments.image_dimension_for(&args.model),
+  };
+
+  let system = args.system.clone().map(|system| text_message(Role::System, system));
+
+  let mut messages = system.iter().cloned().collect::<Vec<_>>();
+  let mut pending = args
+    .attachments
+    .iter()
+    .map(|path| read_attachment(path, &options))
+    .collect::<anyhow::Result<Vec<_>>>()?;
+
+  println!("{} {}", "Chatting with".dimmed(), args.model.bright_green());
+  println!("{}", HELP.dimmed());
+
+  let mut lines = BufReader::new(tokio::io::stdin()).lines();
+
+  loop {
+    print!("{} ", ">".bright_green());
+    std::io::stdout().flush()?;
+
+    let line = tokio::select! {
+      line = lines.next_line() => line?,
+      _ = tokio::signal::ctrl_c() => None,
+    };
+
+    let Some(line) = line else {
+      println!();
+      break;
+    };
+
+    let line = line.trim();
+
+    if line.is_empty() {
+      continue;
+    }
+
+    if line == "/exit" || line == "/quit" {
+      break;
+    }
+
+    if line == "/reset" {
+      messages = system.iter().cloned().collect();
+      pending.clear();
+      println!("{}", "Conversation cleared".dimmed());
+      continue;
+    }
+
+    if let Some(path) = line.strip_prefix("/attach ") {
+      match read_attachment(Path::new(path.trim()), &options) {
+        Ok(part) => {
+          pending.push(part);
+          println!("{}", format!("Attached {}", path.trim()).dimmed());
+        }
+        Err(err) => eprintln!("{}", format!("{err:#}").red()),
+      }
+      continue;
+    }
+
+    if line.starts_with('/') {
+      println!("{}", HELP.dimmed());
+      continue;
+    }
+
+    let mut content = std::mem::take(&mut pending);
+    content.push(ContentPart::Text { text: line.to_string() });
+    messages.push(MessageRequest::new(Role::User, content));
+
+    match stream_reply(&openrouter, &args, &messages).await {
+      Ok(reply) => messages.push(text_message(Role::Assistant, reply)),
+      Err(err) => {
+        eprintln!("{}", format!("{err:#}").red());
+        // drop the failed turn but keep its attachments for the next try
+        if let Some(mut message) = messages.pop() {
+          message.content.pop();
+          pending = message.content;
+        }
+      }
+    }
+  }
+
+  if let Some(user_id) = args.save_for.clone()
+    && messages.iter().any(|message| message.role == Role::Assistant)
+  {
+    let thread_id = save_session(config, openrouter, &args, user_id, &messages, started_at).await?;
+    println!("Saved session as thread {thread_id}");
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_mime_type() {
+    assert_eq!(mime_type(Path::new("photo.JPG"), &[0xff, 0xd8]), Some("image/jpeg"));
+    assert_eq!(mime_type(Path::new("paper.pdf"), b"%PDF"), Some("application/pdf"));
+    assert_eq!(mime_type(Path::new("main.rs"), b"fn main() {}"), Some("text/plain"));
+    assert_eq!(mime_type(Path::new("archive.zip"), &[0x50, 0x4b, 0x03, 0xff]), None);
+    assert_eq!(mime_type(Path::new("Makefile"), b"all: build"), Some("text/plain"));
+    assert_eq!(mime_type(Path::new("LICENSE"), &[0x00, 0xff]), None);
+  }
+}
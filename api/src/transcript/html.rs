@@ -0,0 +1,192 @@
+use std::fmt::Write;
+
+use reqwest::Url;
+
+use crate::transcript::{Transcript, TranscriptMessage, TranscriptPart, TranscriptRole};
+
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M UTC";
+
+const STYLE: &str = r#"
+body { font-family: system-ui, sans-serif; max-width: 48rem; margin: 2rem auto; padding: 0 1rem; line-height: 1.5; }
+header p, .meta { color: #666; font-size: 0.875rem; }
+article { border-top: 1px solid #ddd; padding: 1rem 0; }
+article.user h2 { color: #2563eb; }
+article.assistant h2 { color: #9333ea; }
+h2 { font-size: 1rem; margin: 0 0 0.25rem; }
+.text { white-space: pre-wrap; }
+details { background: #f5f5f5; border-radius: 0.25rem; padding: 0.5rem; margin: 0.5rem 0; }
+details .text { color: #555; }
+ol.sources { font-size: 0.875rem; }
+"#;
+
+/// renders a transcript as a standalone html document, message text is kept as preformatted text
+pub fn render_html(transcript: &Transcript) -> String {
+  let mut output = String::new();
+  let mut footnote = 0;
+
+  let title = escape(transcript.display_title());
+
+  writeln!(output, "<!DOCTYPE html>").unwrap();
+  writeln!(output, "<html lang=\"en\">").unwrap();
+  writeln!(output, "<head>").unwrap();
+  writeln!(output, "<meta charset=\"utf-8\">").unwrap();
+  writeln!(output, "<title>{title}</title>").unwrap();
+  writeln!(output, "<style>{STYLE}</style>").unwrap();
+  writeln!(output, "</head>").unwrap();
+  writeln!(output, "<body>").unwrap();
+
+  writeln!(output, "<header>").unwrap();
+  writeln!(output, "<h1>{title}</h1>").unwrap();
+  write!(
+    output,
+    "<p>Thread <code>{}</code>",
+    escape(transcript.thread_id.as_str())
+  )
+  .unwrap();
+  if let Some(created_at) = transcript.created_at {
+    write!(output, ", created {}", created_at.format(TIMESTAMP_FORMAT)).unwrap();
+  }
+  writeln!(
+    output,
+    ", exported {}</p>",
+    transcript.exported_at.format(TIMESTAMP_FORMAT)
+  )
+  .unwrap();
+  writeln!(output, "</header>").unwrap();
+
+  for message in &transcript.messages {
+    render_message(&mut output, message, &mut footnote);
+  }
+
+  writeln!(output, "</body>").unwrap();
+  writeln!(output, "</html>").unwrap();
+
+  output
+}
+
+fn render_message(output: &mut String, message: &TranscriptMessage, footnote: &mut usize) {
+  let class = match message.role {
+    TranscriptRole::User => "user",
+    TranscriptRole::Assistant => "assistant",
+    TranscriptRole::System => "system",
+  };
+
+  writeln!(
+    output,
+    "<article class=\"{class}\" id=\"{}\">",
+    escape(message.id.as_str())
+  )
+  .unwrap();
+  writeln!(output, "<h2>{}</h2>", message.role.label()).unwrap();
+
+  if let Some(metadata) = &message.metadata {
+    let summary = metadata.summary();
+    if !summary.is_empty() {
+      writeln!(output, "<p class=\"meta\">{}</p>", escape(&summary)).unwrap();
+    }
+  }
+
+  if let Some(reasoning) = &message.reasoning {
+    writeln!(output, "<details>").unwrap();
+    writeln!(output, "<summary>Reasoning</summary>").unwrap();
+    writeln!(output, "<div class=\"text\">{}</div>", escape(reasoning.trim())).unwrap();
+    writeln!(output, "</details>").unwrap();
+  }
+
+  for part in &message.parts {
+    match part {
+      TranscriptPart::Text { text } => {
+        writeln!(output, "<div class=\"text\">{}</div>", escape(text.trim_end())).unwrap();
+      }
+      TranscriptPart::Attachment {
+        name, url: Some(url), ..
+      } => {
+        writeln!(output, "<p>Attachment: {}</p>", link(url, name)).unwrap();
+      }
+      TranscriptPart::Attachment { name, url: None, .. } => {
+        writeln!(output, "<p>Attachment: {} (unavailable)</p>", escape(name)).unwrap();
+      }
+    }
+  }
+
+  if !message.annotations.is_empty() {
+    let start = *footnote + 1;
+    *footnote += message.annotations.len();
+
+    writeln!(output, "<ol class=\"sources\" start=\"{start}\">").unwrap();
+    for annotation in &message.annotations {
+      writeln!(output, "<li>{}</li>", link(&annotation.url, &annotation.title)).unwrap();
+    }
+    writeln!(output, "</ol>").unwrap();
+  }
+
+  writeln!(output, "</article>").unwrap();
+}
+
+/// only http(s) urls become links, anything else (`javascript:`, `data:`, ...) is shown as plain text
+fn link(url: &str, label: &str) -> String {
+  let linkable = Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"));
+
+  if linkable {
+    format!("<a href=\"{}\">{}</a>", escape(url), escape(label))
+  } else {
+    format!("{} ({})", escape(label), escape(url))
+  }
+}
+
+fn escape(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+
+  for c in text.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&#39;"),
+      c => escaped.push(c),
+    }
+  }
+
+  escaped
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::transcript::tests::sample_transcript;
+
+  #[test]
+  fn test_escape() {
+    assert_eq!(
+      escape("<a href=\"x\">&'</a>"),
+      "&lt;a href=&quot;x&quot;&gt;&amp;&#39;&lt;/a&gt;"
+    );
+  }
+
+  #[test]
+  fn test_link() {
+    assert_eq!(
+      link("https://example.com/?a=1&b=2", "docs"),
+      "<a href=\"https://example.com/?a=1&amp;b=2\">docs</a>"
+    );
+    assert_eq!(link("javascript:alert(1)", "click"), "click (javascript:alert(1))");
+    assert_eq!(link(" JavaScript:alert(1)", "click"), "click ( JavaScript:alert(1))");
+    assert_eq!(
+      link("data:text/html,<script>", "x"),
+      "x (data:text/html,&lt;script&gt;)"
+    );
+    assert_eq!(link("not a url", "x"), "x (not a url)");
+  }
+
+  #[test]
+  fn test_render_html() {
+    let output = render_html(&sample_transcript());
+
+    assert!(output.contains("<title>Rust &lt;lifetimes&gt; &amp; you</title>"));
+    assert!(output.contains("A lifetime is a &lt;region&gt; of code."));
+    assert!(output.contains("<a href=\"https://example.com/notes.txt\">notes.txt</a>"));
+    assert!(output.contains("<ol class=\"sources\" start=\"1\">"));
+    assert!(output.contains("<summary>Reasoning</summary>"));
+  }
+}
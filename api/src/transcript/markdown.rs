@@ -0,0 +1,128 @@
+use std::fmt::Write;
+
+use crate::transcript::{Transcript, TranscriptMessage, TranscriptPart};
+
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M UTC";
+
+/// renders a transcript as markdown, annotations become footnotes numbered across the whole document
+pub fn render_markdown(transcript: &Transcript) -> String {
+  let mut output = String::new();
+  let mut footnote = 0;
+
+  writeln!(output, "# {}", transcript.display_title()).unwrap();
+  writeln!(output).unwrap();
+
+  let mut header = format!("Thread `{}`", transcript.thread_id);
+  if let Some(created_at) = transcript.created_at {
+    write!(header, ", created {}", created_at.format(TIMESTAMP_FORMAT)).unwrap();
+  }
+  write!(header, ", exported {}", transcript.exported_at.format(TIMESTAMP_FORMAT)).unwrap();
+
+  writeln!(output, "_{header}_").unwrap();
+
+  for message in &transcript.messages {
+    writeln!(output).unwrap();
+    render_message(&mut output, message, &mut footnote);
+  }
+
+  output
+}
+
+fn render_message(output: &mut String, message: &TranscriptMessage, footnote: &mut usize) {
+  writeln!(output, "## {}", message.role.label()).unwrap();
+  writeln!(output).unwrap();
+
+  if let Some(metadata) = &message.metadata {
+    let summary = metadata.summary();
+    if !summary.is_empty() {
+      writeln!(output, "_{summary}_").unwrap();
+      writeln!(output).unwrap();
+    }
+  }
+
+  if let Some(reasoning) = &message.reasoning {
+    writeln!(output, "<details>").unwrap();
+    writeln!(output, "<summary>Reasoning</summary>").unwrap();
+    writeln!(output).unwrap();
+    for line in reasoning.trim().lines() {
+      writeln!(output, "> {line}").unwrap();
+    }
+    writeln!(output).unwrap();
+    writeln!(output, "</details>").unwrap();
+    writeln!(output).unwrap();
+  }
+
+  for part in &message.parts {
+    match part {
+      TranscriptPart::Text { text } => {
+        writeln!(output, "{}", text.trim_end()).unwrap();
+      }
+      TranscriptPart::Attachment {
+        name, url: Some(url), ..
+      } => {
+        writeln!(output, "Attachment: [{}](<{url}>)", escape_link_text(name)).unwrap();
+      }
+      TranscriptPart::Attachment { name, url: None, .. } => {
+        writeln!(output, "Attachment: {} (unavailable)", escape_link_text(name)).unwrap();
+      }
+    }
+    writeln!(output).unwrap();
+  }
+
+  if message.annotations.is_empty() {
+    return;
+  }
+
+  let first = *footnote + 1;
+  *footnote += message.annotations.len();
+
+  let references = (first..=*footnote).map(|n| format!("[^{n}]")).collect::<Vec<_>>();
+  writeln!(output, "Sources: {}", references.join(" ")).unwrap();
+  writeln!(output).unwrap();
+
+  for (n, annotation) in (first..).zip(&message.annotations) {
+    writeln!(
+      output,
+      "[^{n}]: [{}](<{}>)",
+      escape_link_text(&annotation.title),
+      annotation.url
+    )
+    .unwrap();
+  }
+}
+
+fn escape_link_text(text: &str) -> String {
+  text.replace('[', "\\[").replace(']', "\\]")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::transcript::tests::sample_transcript;
+
+  #[test]
+  fn test_render_markdown() {
+    let output = render_markdown(&sample_transcript());
+
+    assert!(output.starts_with("# Rust <lifetimes> & you\n"));
+    assert!(output.contains("## User\n\nWhat is a lifetime?\n"));
+    assert!(output.contains("Attachment: [notes.txt](<https://example.com/notes.txt>)"));
+    assert!(output.contains("_openai/gpt-4o · 12 tokens · 40.0 tok/s · 0.3s_"));
+    assert!(output.contains("> The user asks about lifetimes."));
+    assert!(output.contains("Sources: [^1]\n"));
+    assert!(output.contains("[^1]: [The Book](<https://doc.rust-lang.org/book/>)"));
+  }
+
+  #[test]
+  fn test_footnotes_numbered_across_messages() {
+    let mut transcript = sample_transcript();
+    let annotations = transcript.messages[1].annotations.clone();
+    transcript.messages[0].annotations = annotations;
+
+    let output = render_markdown(&transcript);
+
+    assert!(output.contains("[^1]: "));
+    assert!(output.contains("[^2]: "));
+    assert!(!output.contains("[^3]"));
+  }
+}
@@ -0,0 +1,351 @@
+use crate::convex::messages::{Annotation, Message, MessagePart, MessageStatus, Role};
+use crate::convex::threads::Thread;
+use crate::convex::{ConvexClient, ConvexError, Doc, Id, attachments, messages};
+use crate::prelude::*;
+
+mod html;
+mod markdown;
+
+pub use html::render_html;
+pub use markdown::render_markdown;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transcript {
+  pub thread_id: Id<Thread>,
+  pub title: Option<String>,
+  pub created_at: Option<Timestamp>,
+  pub exported_at: Timestamp,
+  pub messages: Vec<TranscriptMessage>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscriptMessage {
+  pub id: Id<Message>,
+  pub role: TranscriptRole,
+  pub created_at: Option<Timestamp>,
+  pub status: Option<TranscriptStatus>,
+  pub parts: Vec<TranscriptPart>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub reasoning: Option<String>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub annotations: Vec<Annotation>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub metadata: Option<MessageMetadata>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TranscriptRole {
+  User,
+  Assistant,
+  System,
+}
+
+impl TranscriptRole {
+  pub fn label(&self) -> &'static str {
+    match self {
+      TranscriptRole::User => "User",
+      TranscriptRole::Assistant => "Assistant",
+      TranscriptRole::System => "System",
+    }
+  }
+}
+
+impl From<Role> for TranscriptRole {
+  fn from(role: Role) -> Self {
+    match role {
+      Role::User => TranscriptRole::User,
+      Role::Assistant => TranscriptRole::Assistant,
+      Role::System => TranscriptRole::System,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TranscriptStatus {
+  Pending,
+  Complete,
+  Cancelled,
+  Error,
+}
+
+impl From<&MessageStatus> for TranscriptStatus {
+  fn from(status: &MessageStatus) -> Self {
+    match status {
+      MessageStatus::Pending => TranscriptStatus::Pending,
+      MessageStatus::Complete => TranscriptStatus::Complete,
+      MessageStatus::Cancelled => TranscriptStatus::Cancelled,
+      MessageStatus::Error => TranscriptStatus::Error,
+    }
+  }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(tag = "type")]
+pub enum TranscriptPart {
+  Text {
+    text: String,
+  },
+  Attachment {
+    name: String,
+    mime_type: String,
+    url: Option<String>,
+  },
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageMetadata {
+  pub model: Option<String>,
+  pub reasoning_effort: Option<String>,
+  pub include_search: Option<bool>,
+  pub prompt_token_count: Option<u64>,
+  pub token_count: Option<u64>,
+  pub duration_ms: Option<u64>,
+  pub tokens_per_second: Option<f64>,
+  pub time_to_first_token_ms: Option<u64>,
+}
+
+impl MessageMetadata {
+  /// short human readable summary, e.g. `openai/gpt-4o · 512 tokens · 42.1 tok/s · 3.2s`
+  pub fn summary(&self) -> String {
+    let mut items = vec![];
+
+    if let Some(model) = &self.model {
+      items.push(model.clone());
+    }
+
+    if let Some(effort) = &self.reasoning_effort {
+      items.push(format!("{effort} reasoning"));
+    }
+
+    if let Some(token_count) = self.token_count {
+      items.push(format!("{token_count} tokens"));
+    }
+
+    if let Some(tokens_per_second) = self.tokens_per_second {
+      items.push(format!("{tokens_per_second:.1} tok/s"));
+    }
+
+    if let Some(duration_ms) = self.duration_ms {
+      items.push(format!("{:.1}s", duration_ms as f64 / 1000.0));
+    }
+
+    items.join(" · ")
+  }
+}
+
+pub struct ExportOptions {
+  pub include_reasoning: bool,
+}
+
+fn timestamp_from_ms(ms: f64) -> Option<Timestamp> {
+  Timestamp::from_timestamp_millis(ms as i64)
+}
+
+/// convex stores counts as floats, zero means the value was never recorded
+fn positive_count(value: Option<f64>) -> Option<u64> {
+  value.filter(|v| *v > 0.0).map(|v| v.round() as u64)
+}
+
+fn message_metadata(message: &Message) -> Option<MessageMetadata> {
+  if message.role != Role::Assistant {
+    return None;
+  }
+
+  let model_params = message.model_params.as_ref();
+
+  Some(MessageMetadata {
+    model: message.model.clone(),
+    reasoning_effort: model_params
+      .and_then(|params| params.reasoning_effort)
+      .map(|effort| format!("{effort:?}").to_lowercase()),
+    include_search: model_params.map(|params| params.include_search),
+    prompt_token_count: positive_count(message.prompt_token_count),
+    token_count: positive_count(message.token_count),
+    duration_ms: positive_count(message.duration_ms),
+    tokens_per_second: message.tokens_per_second.filter(|v| *v > 0.0),
+    time_to_first_token_ms: positive_count(message.time_to_first_token_ms),
+  })
+}
+
+async fn message_to_transcript(
+  client: &mut ConvexClient,
+  Doc {
+    id,
+    creation_time,
+    fields: message,
+  }: Doc<Message>,
+  options: &ExportOptions,
+) -> Result<TranscriptMessage, ConvexError> {
+  let metadata = message_metadata(&message);
+
+  let mut parts = Vec::with_capacity(message.parts.len());
+
+  for part in message.parts {
+    let part = match part {
+      MessagePart::Text { text } => TranscriptPart::Text { text },
+      MessagePart::Attachment { id } => match attachments::get_by_id(client, id.clone()).await? {
+        Some(Doc { fields: attachment, .. }) => TranscriptPart::Attachment {
+          name: attachment.name,
+          mime_type: attachment.mime_type,
+          url: Some(attachment.url),
+        },
+        None => {
+          warn!("Attachment with ID {id} not found, exporting without link");
+          TranscriptPart::Attachment {
+            name: id.into_string(),
+            mime_type: "application/octet-stream".into(),
+            url: None,
+          }
+        }
+      },
+    };
+
+    parts.push(part);
+  }
+
+  let reasoning = if options.include_reasoning {
+    message.reasoning.filter(|reasoning| !reasoning.trim().is_empty())
+  } else {
+    None
+  };
+
+  Ok(TranscriptMessage {
+    id,
+    role: message.role.into(),
+    created_at: timestamp_from_ms(creation_time),
+    status: message.status.as_ref().map(TranscriptStatus::from),
+    parts,
+    reasoning,
+    annotations: message.annotations.unwrap_or_default(),
+    metadata,
+  })
+}
+
+pub async fn build_transcript(
+  client: &mut ConvexClient,
+  thread: Doc<Thread>,
+  options: &ExportOptions,
+) -> Result<Transcript, ConvexError> {
+  let convex_messages = messages::get_by_thread_id(client, thread.id.clone()).await?;
+
+  let mut messages = Vec::with_capacity(convex_messages.len());
+
+  for message in convex_messages {
+    messages.push(message_to_transcript(client, message, options).await?);
+  }
+
+  Ok(Transcript {
+    thread_id: thread.id,
+    title: thread.fields.title,
+    created_at: timestamp_from_ms(thread.creation_time),
+    exported_at: chrono::Utc::now(),
+    messages,
+  })
+}
+
+impl Transcript {
+  pub fn display_title(&self) -> &str {
+    self.title.as_deref().unwrap_or("Untitled thread")
+  }
+
+  /// file name safe version of the title, without extension
+  pub fn file_stem(&self) -> String {
+    let stem = self
+      .display_title()
+      .chars()
+      .map(|c| {
+        if c.is_ascii_alphanumeric() {
+          c.to_ascii_lowercase()
+        } else {
+          '-'
+        }
+      })
+      .collect::<String>();
+
+    let stem = stem.split('-').filter(|s| !s.is_empty()).collect::<Vec<_>>().join("-");
+
+    if stem.is_empty() {
+      self.thread_id.to_string()
+    } else {
+      stem
+    }
+  }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+  use super::*;
+
+  pub fn sample_transcript() -> Transcript {
+    let created_at = Timestamp::from_timestamp_millis(1_750_000_000_000);
+
+    Transcript {
+      thread_id: Id::new("thread1"),
+      title: Some("Rust <lifetimes> & you".into()),
+      created_at,
+      exported_at: created_at.unwrap(),
+      messages: vec![
+        TranscriptMessage {
+          id: Id::new("message1"),
+          role: TranscriptRole::User,
+          created_at,
+          status: None,
+          parts: vec![
+            TranscriptPart::Text {
+              text: "What is a lifetime?".into(),
+            },
+            TranscriptPart::Attachment {
+              name: "notes.txt".into(),
+              mime_type: "text/plain".into(),
+              url: Some("https://example.com/notes.txt".into()),
+            },
+          ],
+          reasoning: None,
+          annotations: vec![],
+          metadata: None,
+        },
+        TranscriptMessage {
+          id: Id::new("message2"),
+          role: TranscriptRole::Assistant,
+          created_at,
+          status: Some(TranscriptStatus::Complete),
+          parts: vec![TranscriptPart::Text {
+            text: "A lifetime is a <region> of code.".into(),
+          }],
+          reasoning: Some("The user asks about lifetimes.".into()),
+          annotations: vec![Annotation {
+            title: "The Book".into(),
+            url: "https://doc.rust-lang.org/book/".into(),
+            content: "Lifetimes chapter".into(),
+          }],
+          metadata: Some(MessageMetadata {
+            model: Some("openai/gpt-4o".into()),
+            token_count: Some(12),
+            tokens_per_second: Some(40.0),
+            duration_ms: Some(300),
+            ..Default::default()
+          }),
+        },
+      ],
+    }
+  }
+
+  #[test]
+  fn test_file_stem() {
+    let transcript = sample_transcript();
+    assert_eq!(transcript.file_stem(), "rust-lifetimes-you");
+  }
+
+  #[test]
+  fn test_metadata_summary() {
+    let transcript = sample_transcript();
+    let metadata = transcript.messages[1].metadata.as_ref().unwrap();
+    assert_eq!(metadata.summary(), "openai/gpt-4o · 12 tokens · 40.0 tok/s · 0.3s");
+  }
+}
@@ -1,5 +1,4 @@
 use std::collections::{HashMap, HashSet};
-use std::env;
 use std::fmt::Write as _;
 use std::fs::File;
 use std::io::{BufReader, BufWriter, Read, Write};
@@ -12,18 +11,16 @@ use specta_typescript::primitives::export;
 use specta_typescript::{BigIntExportBehavior, Typescript};
 
 use crate::routes::RouteInfo;
+use crate::types::{ExportOptions, TypesFormat};
 
-const TYPES_PATH_VAR: &str = "TYPES_PATH";
+pub fn export_types(options: &ExportOptions, routes: Vec<RouteInfo>) -> Result<()> {
+  let types_path = std::path::absolute(&options.path)?;
 
-pub fn export_types(debug_location: bool, routes: Vec<RouteInfo>) -> Result<()> {
-  let types_path = env::var(TYPES_PATH_VAR)?;
-
-  if types_path.is_empty() {
-    bail!("{} environment variable is not set", TYPES_PATH_VAR);
+  // ci builds export into a fresh directory
+  if let Some(parent) = types_path.parent() {
+    std::fs::create_dir_all(parent)?;
   }
 
-  let types_path = Path::new(&types_path).canonicalize()?;
-
   println!("Exporting types to: {}", display_path(&types_path));
 
   let types = specta::export();
@@ -81,7 +78,7 @@ pub fn export_types(debug_location: bool, routes: Vec<RouteInfo>) -> Result<()>
   for (type_output, location) in type_outputs {
     writeln!(output)?;
 
-    if debug_location {
+    if options.debug_location {
       writeln!(output, "/* {location} */")?;
     }
 
@@ -198,8 +195,13 @@ pub fn export_types(debug_location: bool, routes: Vec<RouteInfo>) -> Result<()>
 
   // attempt to format by piping output into prettier
 
-  // find yarn path
-  let output = if let Ok(yarn) = find_yarn_path() {
+  let yarn = match options.format {
+    TypesFormat::Auto => find_yarn_path().ok(),
+    TypesFormat::Prettier => Some(find_yarn_path().context("yarn is required for prettier formatting")?),
+    TypesFormat::Raw => None,
+  };
+
+  let output = if let Some(yarn) = yarn {
     // set cwd to the directory of the types_path
 
     let cwd = types_path
@@ -250,10 +252,12 @@ pub fn export_types(debug_location: bool, routes: Vec<RouteInfo>) -> Result<()>
 
     formatted_output
   } else {
-    eprintln!(
-      "Warning: {} not found, skipping formatting with prettier",
-      "yarn".bright_yellow()
-    );
+    if options.format == TypesFormat::Auto {
+      eprintln!(
+        "Warning: {} not found, skipping formatting with prettier",
+        "yarn".bright_yellow()
+      );
+    }
     output
   };
 
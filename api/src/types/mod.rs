@@ -1,3 +1,24 @@
+use std::path::PathBuf;
+
 mod export;
 
 pub use export::export_types;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum TypesFormat {
+  /// run prettier through yarn when it is installed
+  #[default]
+  Auto,
+  /// fail when yarn is not installed
+  Prettier,
+  /// write the output as generated
+  Raw,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+  pub path: PathBuf,
+  pub format: TypesFormat,
+  /// prefix every type with the source location it was defined at
+  pub debug_location: bool,
+}
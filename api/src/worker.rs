@@ -0,0 +1,159 @@
+use std::collections::HashSet;
+use std::pin::pin;
+use std::time::Duration;
+
+use futures::StreamExt;
+
+use crate::convex::messages::{self, Message};
+use crate::convex::{ConvexError, Doc, Id};
+use crate::keys::KeyError;
+use crate::prelude::*;
+use crate::routes::message::create::{self, CreateMessageError, CreateMessageRequest};
+
+/// wait before subscribing again after the subscription failed or closed
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(5);
+/// how often messages left waiting for a free generation slot are retried
+const RECHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// watches convex for pending assistant messages and generates them without an http request. messages whose lease
+/// expired are generated again, so generations interrupted by a crash are retried until they run out of attempts
+pub async fn run(state: AppState) {
+  info!("Worker {} watching for pending messages", state.instance_id);
+
+  loop {
+    if let Err(err) = watch(&state).await {
+      warn!("Pending message subscription failed: {:?}", err);
+    }
+
+    tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+  }
+}
+
+async fn watch(state: &AppState) -> Result<(), ConvexError> {
+  let mut convex = state.convex.clone();
+  let mut updates = pin!(messages::watch_pending(&mut convex).await?);
+
+  let mut pending = Vec::new();
+  // messages that can't be generated, skipped until they leave the pending list
+  let mut failed = HashSet::new();
+  let mut recheck = None;
+
+  loop {
+    tokio::select! {
+      update = updates.next() => match update {
+        Some(update) => {
+          pending = update?;
+          failed.retain(|id| pending.iter().any(|message: &Doc<Message>| &message.id == id));
+        }
+        None => return Ok(()),
+      },
+      _ = tokio::time::sleep(recheck.unwrap_or_default()), if recheck.is_some() => {}
+    }
+
+    recheck = start_pending(state, &pending, &mut failed).await;
+  }
+}
+
+/// starts as many of the claimable messages as there are free slots, returns when to look at the rest again
+async fn start_pending(
+  state: &AppState,
+  pending: &[Doc<Message>],
+  failed: &mut HashSet<Id<Message>>,
+) -> Option<Duration> {
+  let now = chrono::Utc::now().timestamp_millis() as f64;
+
+  // expired leases don't change the query result, so wake up when the next one runs out
+  let next_expiry = pending
+    .iter()
+    .filter(|message| !message.is_claimable(now))
+    .filter_map(|message| message.lease_expires_at)
+    .reduce(f64::min)
+    .map(|expiry| Duration::from_millis((expiry - now) as u64) + RECHECK_INTERVAL);
+  let mut recheck = next_expiry;
+
+  for message in pending {
+    if failed.contains(&message.id) || !message.is_claimable(now) {
+      continue;
+    }
+
+    let Some(model) = message.model.clone() else {
+      warn!("Pending message {} has no model", message.id);
+      mark_failed(state, message, failed).await;
+      continue;
+    };
+
+    let request = CreateMessageRequest {
+      thread_id: message.thread_id.clone(),
+      response_message_id: message.id.clone(),
+      model,
+      model_params: message.model_params.clone().map(Into::into),
+    };
+
+    match create::generate(state, request).await {
+      Ok(()) => debug!("Worker started generating message {}", message.id),
+      Err(CreateMessageError::TooManyGenerations) => return Some(RECHECK_INTERVAL),
+      // an http request or another instance got to it first, or it was finished in the meantime
+      Err(CreateMessageError::AlreadyGenerating | CreateMessageError::ResponseMessageNotPending) => {}
+      Err(CreateMessageError::AttemptsExhausted) => {
+        warn!("Message {} failed too many times, marked it as failed", message.id)
+      }
+      Err(err) if is_permanent(&err) => {
+        warn!("Marking message {} as failed: {:?}", message.id, err);
+        mark_failed(state, message, failed).await;
+      }
+      // convex hiccups, an exhausted quota and the like may pass, the message stays pending for the next look
+      Err(err) => {
+        warn!("Failed to start message {}, retrying later: {:?}", message.id, err);
+        recheck = Some(recheck.map_or(RECHECK_INTERVAL, |recheck| recheck.min(RECHECK_INTERVAL)));
+      }
+    }
+  }
+
+  recheck
+}
+
+/// errors that would come back on every attempt, the message is failed instead of staying pending
+fn is_permanent(err: &CreateMessageError) -> bool {
+  matches!(
+    err,
+    CreateMessageError::MessageNotFound
+      | CreateMessageError::ThreadNotFound
+      | CreateMessageError::NoModelSpecified
+      | CreateMessageError::ModelNotAllowed(_)
+      | CreateMessageError::Key(KeyError::NoKey)
+  )
+}
+
+/// marks the message as failed in convex, it is only skipped in memory when that doesn't work, since it would fail
+/// the same way on every look
+async fn mark_failed(state: &AppState, message: &Doc<Message>, failed: &mut HashSet<Id<Message>>) {
+  let mut convex = state.convex.clone();
+
+  if let Err(err) = messages::fail(&mut convex, message.id.clone(), state.instance_id.clone()).await {
+    warn!("Failed to mark message {} as failed: {:?}", message.id, err);
+    failed.insert(message.id.clone());
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_only_lasting_errors_fail_the_message() {
+    assert!(is_permanent(&CreateMessageError::Key(KeyError::NoKey)));
+    assert!(is_permanent(&CreateMessageError::ModelNotAllowed(
+      "openai/gpt-4o".into()
+    )));
+    assert!(is_permanent(&CreateMessageError::ThreadNotFound));
+
+    // these are retried after RECHECK_INTERVAL instead of being skipped until the worker resubscribes
+    assert!(!is_permanent(&CreateMessageError::Key(KeyError::QuotaExceeded)));
+    assert!(!is_permanent(&CreateMessageError::Convex(ConvexError::Message(
+      "timeout".into()
+    ))));
+    assert!(!is_permanent(&CreateMessageError::Key(KeyError::Cipher(
+      anyhow::anyhow!("bad key")
+    ))));
+  }
+}
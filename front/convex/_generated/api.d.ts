@@ -13,12 +13,18 @@ import type {
   FilterApi,
   FunctionReference,
 } from "convex/server";
+import type * as apiTokens from "../apiTokens.js";
 import type * as attachments from "../attachments.js";
 import type * as crons from "../crons.js";
+import type * as health from "../health.js";
+import type * as imports from "../imports.js";
 import type * as messages from "../messages.js";
 import type * as models from "../models.js";
+import type * as openrouterKeys from "../openrouterKeys.js";
 import type * as settings from "../settings.js";
 import type * as threads from "../threads.js";
+import type * as usage from "../usage.js";
+import type * as users from "../users.js";
 import type * as utils from "../utils.js";
 
 /**
@@ -30,12 +36,18 @@ import type * as utils from "../utils.js";
  * ```
  */
 declare const fullApi: ApiFromModules<{
+  apiTokens: typeof apiTokens;
   attachments: typeof attachments;
   crons: typeof crons;
+  health: typeof health;
+  imports: typeof imports;
   messages: typeof messages;
   models: typeof models;
+  openrouterKeys: typeof openrouterKeys;
   settings: typeof settings;
   threads: typeof threads;
+  usage: typeof usage;
+  users: typeof users;
   utils: typeof utils;
 }>;
 export declare const api: FilterApi<
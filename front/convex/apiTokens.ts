@@ -0,0 +1,84 @@
+import { v } from 'convex/values';
+import { mutation, query } from './_generated/server';
+import { validateKey } from './utils';
+
+// api only route
+export const apiCreate = mutation({
+  args: {
+    apiKey: v.string(),
+    name: v.string(),
+    tokenHash: v.string(),
+    userId: v.optional(v.string()),
+  },
+  handler: async (ctx, { apiKey, name, tokenHash, userId }) => {
+    validateKey(apiKey);
+
+    return await ctx.db.insert('apiTokens', {
+      name,
+      tokenHash,
+      userId,
+      createdAt: Date.now(),
+    });
+  },
+});
+
+// api only route
+export const apiGetByHash = query({
+  args: {
+    apiKey: v.string(),
+    tokenHash: v.string(),
+  },
+  handler: async (ctx, { apiKey, tokenHash }) => {
+    validateKey(apiKey);
+
+    const token = await ctx.db
+      .query('apiTokens')
+      .withIndex('by_hash', (q) => q.eq('tokenHash', tokenHash))
+      .first();
+
+    return token ?? null;
+  },
+});
+
+// api only route
+export const apiList = query({
+  args: {
+    apiKey: v.string(),
+  },
+  handler: async (ctx, { apiKey }) => {
+    validateKey(apiKey);
+
+    return await ctx.db.query('apiTokens').collect();
+  },
+});
+
+// api only route
+export const apiRevoke = mutation({
+  args: {
+    apiKey: v.string(),
+    id: v.id('apiTokens'),
+  },
+  handler: async (ctx, { apiKey, id }) => {
+    validateKey(apiKey);
+
+    const token = await ctx.db.get(id);
+    if (token == null) return false;
+
+    await ctx.db.patch(id, { revokedAt: Date.now() });
+
+    return true;
+  },
+});
+
+// api only route
+export const apiTouch = mutation({
+  args: {
+    apiKey: v.string(),
+    id: v.id('apiTokens'),
+  },
+  handler: async (ctx, { apiKey, id }) => {
+    validateKey(apiKey);
+
+    await ctx.db.patch(id, { lastUsedAt: Date.now() });
+  },
+});
@@ -0,0 +1,15 @@
+import { v } from 'convex/values';
+import { query } from './_generated/server';
+import { validateKey } from './utils';
+
+// api only route, cheap round trip used by the readiness probe
+export const apiPing = query({
+  args: {
+    apiKey: v.string(),
+  },
+  handler: async (_ctx, { apiKey }) => {
+    validateKey(apiKey);
+
+    return { ok: true };
+  },
+});
@@ -0,0 +1,138 @@
+import { ConvexError, v } from 'convex/values';
+import { mutation, query } from './_generated/server';
+import { validateKey } from './utils';
+
+// api only route
+export const apiGetByImportId = query({
+  args: {
+    apiKey: v.string(),
+    userId: v.string(),
+    importId: v.string(),
+  },
+  handler: async (ctx, { apiKey, userId, importId }) => {
+    validateKey(apiKey);
+
+    const thread = await ctx.db
+      .query('threads')
+      .withIndex('by_import', (q) => q.eq('userId', userId).eq('importId', importId))
+      .filter((q) => q.neq(q.field('visibility'), 'importing'))
+      .first();
+
+    return thread ?? null;
+  },
+});
+
+// api only route, creates the thread hidden until apiFinishImport. a thread left unfinished by an earlier import of
+// the same conversation is reused, the api continues after the messages it already has
+export const apiStartImport = mutation({
+  args: {
+    apiKey: v.string(),
+    userId: v.string(),
+    importId: v.string(),
+    title: v.optional(v.string()),
+    model: v.string(),
+    createdAt: v.number(),
+    updatedAt: v.number(),
+  },
+  handler: async (ctx, { apiKey, userId, importId, title, model, createdAt, updatedAt }) => {
+    validateKey(apiKey);
+
+    const unfinished = await ctx.db
+      .query('threads')
+      .withIndex('by_import', (q) => q.eq('userId', userId).eq('importId', importId))
+      .filter((q) => q.eq(q.field('visibility'), 'importing'))
+      .first();
+
+    if (unfinished != null) {
+      return { threadId: unfinished._id, importedCount: unfinished.importedCount ?? 0 };
+    }
+
+    const threadId = await ctx.db.insert('threads', {
+      generationStatus: 'complete',
+      createdAt,
+      updatedAt,
+      lastMessageAt: updatedAt,
+      userSetTitle: title != null,
+      title,
+      visibility: 'importing',
+      pinned: false,
+      model,
+      branchParent: undefined,
+      userId,
+      importId,
+      importedCount: 0,
+    });
+
+    return { threadId, importedCount: 0 };
+  },
+});
+
+// api only route, one batch of messages. `offset` is the number of messages stored before the batch, so a batch that
+// is sent again after a timeout is not stored twice
+export const apiImportMessages = mutation({
+  args: {
+    apiKey: v.string(),
+    threadId: v.id('threads'),
+    offset: v.number(),
+    messages: v.array(
+      v.object({
+        role: v.union(v.literal('user'), v.literal('assistant'), v.literal('system')),
+        text: v.string(),
+        createdAt: v.optional(v.number()),
+        model: v.optional(v.string()),
+      }),
+    ),
+  },
+  handler: async (ctx, { apiKey, threadId, offset, messages }) => {
+    validateKey(apiKey);
+
+    const thread = await ctx.db.get(threadId);
+    if (thread == null || thread.visibility !== 'importing') {
+      throw new ConvexError('thread is not being imported');
+    }
+
+    const importedCount = thread.importedCount ?? 0;
+    if (importedCount === offset + messages.length) {
+      return { threadId, importedCount };
+    }
+
+    if (importedCount !== offset) {
+      throw new ConvexError(`expected messages from ${importedCount}, got ${offset}`);
+    }
+
+    // messages are inserted in order so _creationTime keeps the original ordering
+    for (const message of messages) {
+      await ctx.db.insert('messages', {
+        parts: [{ type: 'text', text: message.text }],
+        role: message.role,
+        attachmentIds: [],
+        attachments: [],
+        threadId,
+        userId: thread.userId,
+        status: message.role === 'assistant' ? 'complete' : undefined,
+        model: message.model,
+        createdAt: message.createdAt,
+      });
+    }
+
+    await ctx.db.patch(threadId, { importedCount: offset + messages.length });
+
+    return { threadId, importedCount: offset + messages.length };
+  },
+});
+
+// api only route, shows the thread once all of its messages are stored
+export const apiFinishImport = mutation({
+  args: {
+    apiKey: v.string(),
+    threadId: v.id('threads'),
+  },
+  handler: async (ctx, { apiKey, threadId }) => {
+    validateKey(apiKey);
+
+    const thread = await ctx.db.get(threadId);
+    if (thread == null || thread.visibility !== 'importing') return;
+
+    await ctx.db.patch(threadId, { visibility: 'visible', importedCount: undefined });
+  },
+});
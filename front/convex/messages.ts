@@ -1,7 +1,7 @@
-import { ConvexError, v } from 'convex/values';
-import type { Id } from './_generated/dataModel';
+import { ConvexError, v, type Infer } from 'convex/values';
+import type { Doc, Id } from './_generated/dataModel';
 import { mutation, query } from './_generated/server';
-import { modelParamsValidator } from './schema';
+import { annotationValidator, modelParamsValidator } from './schema';
 import { getIdentity, validateKey } from './utils';
 
 export const getById = query({
@@ -81,104 +81,80 @@ function createTextPart(text: string) {
   return { type: 'text', text } as const;
 }
 
-// api only route
-export const apiAppendText = mutation({
-  args: { apiKey: v.string(), messageId: v.id('messages'), text: v.string() },
-  handler: async (ctx, args) => {
-    validateKey(args.apiKey);
-
-    const message = await ctx.db.get(args.messageId);
-    if (message == null) return null;
-
-    const parts = message.parts;
-
-    // if no parts, add a text part with args.text
-    // if last part is text, append args.text to it
-    // if last part is not text, add a new text part with args.text
-    const lastPart = parts.length === 0 ? null : parts[parts.length - 1];
+function appendTextToParts(parts: Doc<'messages'>['parts'], text: string) {
+  // if no parts, add a text part with text
+  // if last part is text, append text to it
+  // if last part is not text, add a new text part with text
+  const lastPart = parts.length === 0 ? null : parts[parts.length - 1];
+
+  if (lastPart == null) {
+    parts.push(createTextPart(text));
+  } else if (lastPart.type === 'text') {
+    lastPart.text += text;
+  } else {
+    parts.push(createTextPart(text));
+  }
+
+  return parts;
+}
 
-    if (lastPart == null) {
-      parts.push(createTextPart(args.text));
-    } else if (lastPart.type === 'text') {
-      lastPart.text += args.text;
-    } else {
-      parts.push(createTextPart(args.text));
-    }
+type Annotation = Infer<typeof annotationValidator>;
 
-    await ctx.db.patch(message._id, {
-      parts,
-    });
+function mergeAnnotations(existing: Annotation[], added: Annotation[]) {
+  const newAnnotations = added.filter(
+    (a) =>
+      !existing.some(
+        (existingAnnotation) =>
+          existingAnnotation.title === a.title &&
+          existingAnnotation.url === a.url &&
+          existingAnnotation.content === a.content,
+      ),
+  );
 
-    return { _id: message._id };
-  },
-});
+  return [...existing, ...newAnnotations];
+}
 
 // api only route
-export const apiAppendReasoning = mutation({
+// appends buffered text, reasoning and annotations in a single write
+export const apiAppend = mutation({
   args: {
     apiKey: v.string(),
     messageId: v.id('messages'),
-    reasoning: v.string(),
+    // instance generating the message, writes of an instance that lost its claim are rejected
+    claimedBy: v.string(),
+    text: v.optional(v.string()),
+    reasoning: v.optional(v.string()),
+    annotations: v.optional(v.array(annotationValidator)),
   },
-  handler: async (ctx, args) => {
-    validateKey(args.apiKey);
+  handler: async (ctx, { apiKey, messageId, claimedBy, text, reasoning, annotations }) => {
+    validateKey(apiKey);
 
-    const message = await ctx.db.get(args.messageId);
+    const message = await ctx.db.get(messageId);
     if (message == null) return null;
 
     if (message.role !== 'assistant') {
-      throw new ConvexError('Only assistant messages can have reasoning');
+      throw new ConvexError('Only assistant messages can be streamed into');
     }
 
-    const reasoning = (message.reasoning ?? '') + args.reasoning;
-
-    await ctx.db.patch(message._id, {
-      reasoning,
-    });
-
-    return { _id: message._id };
-  },
-});
-
-// api only route
-export const apiAppendAnnotations = mutation({
-  args: {
-    apiKey: v.string(),
-    messageId: v.id('messages'),
-    annotations: v.array(
-      v.object({
-        title: v.string(),
-        url: v.string(),
-        content: v.string(),
-      }),
-    ),
-  },
-  handler: async (ctx, args) => {
-    validateKey(args.apiKey);
+    if (message.claimedBy !== claimedBy) {
+      throw new ConvexError('Message is claimed by another instance');
+    }
 
-    const message = await ctx.db.get(args.messageId);
-    if (message == null) return null;
+    const patch: Partial<Doc<'messages'>> = {};
 
-    if (message.role !== 'assistant') {
-      throw new ConvexError('Only assistant messages can have annotations');
+    if (text != null && text !== '') {
+      patch.parts = appendTextToParts(message.parts, text);
     }
 
-    const existing = message.annotations ?? [];
-    const newAnnotations = args.annotations.filter(
-      (a) =>
-        !existing.some(
-          (existingAnnotation) =>
-            existingAnnotation.title === a.title &&
-            existingAnnotation.url === a.url &&
-            existingAnnotation.content === a.content,
-        ),
-    );
+    if (reasoning != null && reasoning !== '') {
+      patch.reasoning = (message.reasoning ?? '') + reasoning;
+    }
 
-    const annotations = [...existing, ...newAnnotations];
+    if (annotations != null && annotations.length > 0) {
+      patch.annotations = mergeAnnotations(message.annotations ?? [], annotations);
+    }
 
-    await ctx.db.patch(message._id, {
-      annotations,
-    });
+    await ctx.db.patch(message._id, patch);
 
     return { _id: message._id };
   },
@@ -189,6 +165,7 @@ export const apiComplete = mutation({
   args: {
     apiKey: v.string(),
     messageId: v.id('messages'),
+    claimedBy: v.string(),
     model: v.string(),
     modelParams: v.optional(modelParamsValidator),
     promptTokenCount: v.number(),
@@ -196,12 +173,19 @@ export const apiComplete = mutation({
     durationMs: v.number(),
     tokensPerSecond: v.number(),
     timeToFirstTokenMs: v.number(),
+    servedModel: v.optional(v.string()),
+    provider: v.optional(v.string()),
+    reasoningTokenCount: v.optional(v.number()),
+    reasoningDetails: v.optional(v.string()),
+    finishReason: v.optional(v.string()),
+    continuationCount: v.optional(v.number()),
   },
   handler: async (
     ctx,
     {
       apiKey,
       messageId,
+      claimedBy,
       model,
       modelParams,
       promptTokenCount,
@@ -209,6 +193,12 @@ export const apiComplete = mutation({
       durationMs,
       tokensPerSecond,
       timeToFirstTokenMs,
+      servedModel,
+      provider,
+      reasoningTokenCount,
+      reasoningDetails,
+      finishReason,
+      continuationCount,
     },
   ) => {
     validateKey(apiKey);
@@ -216,6 +206,10 @@ export const apiComplete = mutation({
     const message = await ctx.db.get(messageId);
     if (message == null) return null;
 
+    if (message.claimedBy !== claimedBy) {
+      throw new ConvexError('Message is claimed by another instance');
+    }
+
     if (message.role === 'assistant') {
       await ctx.db.patch(message._id, {
         status: 'complete',
@@ -226,6 +220,12 @@ export const apiComplete = mutation({
         durationMs,
         tokensPerSecond,
         timeToFirstTokenMs,
+        servedModel,
+        provider,
+        reasoningTokenCount,
+        reasoningDetails,
+        finishReason,
+        continuationCount,
       });
     } else {
       throw new ConvexError('Only assistant messages can be completed');
@@ -259,6 +259,116 @@ export const apiCancel = mutation({
   },
 });
 
+// api only route
+// pending assistant messages watched by the api worker: the oldest unclaimed ones, then the claimed ones whose lease
+// runs out first so expired leases can be picked up. each part is capped separately, so messages held by other
+// instances never hide new ones
+export const apiListPending = query({
+  args: { apiKey: v.string() },
+  handler: async (ctx, { apiKey }) => {
+    validateKey(apiKey);
+
+    const unclaimed = await ctx.db
+      .query('messages')
+      .withIndex('by_status_lease', (q) => q.eq('status', 'pending').eq('leaseExpiresAt', undefined))
+      .order('asc')
+      .take(256);
+
+    const claimed = await ctx.db
+      .query('messages')
+      .withIndex('by_status_lease', (q) => q.eq('status', 'pending').gt('leaseExpiresAt', 0))
+      .order('asc')
+      .take(64);
+
+    return [...unclaimed, ...claimed].filter((message) => message.role === 'assistant');
+  },
+});
+
+// api only route
+// claims a pending message for leaseMs, unless another instance holds a lease on it. a message that already used
+// maxAttempts generations is marked as failed instead
+export const apiClaim = mutation({
+  args: {
+    apiKey: v.string(),
+    messageId: v.id('messages'),
+    claimedBy: v.string(),
+    leaseMs: v.number(),
+    maxAttempts: v.number(),
+  },
+  handler: async (ctx, { apiKey, messageId, claimedBy, leaseMs, maxAttempts }) => {
+    validateKey(apiKey);
+
+    const message = await ctx.db.get(messageId);
+    if (message?.status !== 'pending') return 'taken';
+
+    const now = Date.now();
+    if (message.claimedBy != null && (message.leaseExpiresAt ?? 0) > now) return 'taken';
+
+    const attempts = message.attempts ?? 0;
+    if (attempts >= maxAttempts) {
+      await ctx.db.patch(message._id, { status: 'error', claimedBy: undefined, leaseExpiresAt: undefined });
+
+      return 'failed';
+    }
+
+    // a retry starts over instead of appending to what the interrupted generation wrote
+    const reset = attempts > 0 ? { parts: [], reasoning: undefined, annotations: undefined } : {};
+
+    await ctx.db.patch(message._id, {
+      ...reset,
+      claimedBy,
+      claimedAt: now,
+      leaseExpiresAt: now + leaseMs,
+      attempts: attempts + 1,
+    });
+
+    return 'claimed';
+  },
+});
+
+// api only route
+// extends the lease while the message is pending, returns false when another instance took it over
+export const apiRenewLease = mutation({
+  args: {
+    apiKey: v.string(),
+    messageId: v.id('messages'),
+    claimedBy: v.string(),
+    leaseMs: v.number(),
+  },
+  handler: async (ctx, { apiKey, messageId, claimedBy, leaseMs }) => {
+    validateKey(apiKey);
+
+    const message = await ctx.db.get(messageId);
+    if (message?.claimedBy !== claimedBy) return false;
+
+    if (message.status === 'pending') {
+      await ctx.db.patch(message._id, { leaseExpiresAt: Date.now() + leaseMs });
+    }
+
+    return true;
+  },
+});
+
+// api only route
+// gives up the claim after a failed generation so the message can be retried right away
+export const apiRelease = mutation({
+  args: {
+    apiKey: v.string(),
+    messageId: v.id('messages'),
+    claimedBy: v.string(),
+  },
+  handler: async (ctx, { apiKey, messageId, claimedBy }) => {
+    validateKey(apiKey);
+
+    const message = await ctx.db.get(messageId);
+    if (message?.status !== 'pending' || message.claimedBy !== claimedBy) return false;
+
+    await ctx.db.patch(message._id, { claimedBy: undefined, leaseExpiresAt: undefined });
+
+    return true;
+  },
+});
+
 export const retryMessage = mutation({
   args: { messageId: v.id('messages'), model: v.string(), modelParams: v.optional(modelParamsValidator) },
   handler: async (ctx, { messageId, model, modelParams }) => {
@@ -282,6 +392,10 @@ export const retryMessage = mutation({
         parts: [],
         reasoning: undefined,
         annotations: undefined,
+        claimedBy: undefined,
+        claimedAt: undefined,
+        leaseExpiresAt: undefined,
+        attempts: undefined,
       });
 
       assistantMessageId = message._id;
@@ -303,6 +417,10 @@ export const retryMessage = mutation({
           parts: [],
           reasoning: undefined,
           annotations: undefined,
+          claimedBy: undefined,
+          claimedAt: undefined,
+          leaseExpiresAt: undefined,
+          attempts: undefined,
         });
         assistantMessageId = assistantMessage._id;
       } else {
@@ -323,3 +441,27 @@ export const retryMessage = mutation({
     return { assistantMessageId };
   },
 });
+
+// api only route
+// marks a pending message as failed when it can't be generated at all, e.g. without a key or with a model that is
+// not allowed. only done by the instance holding the claim, or by any instance while nobody holds one
+export const apiFail = mutation({
+  args: {
+    apiKey: v.string(),
+    messageId: v.id('messages'),
+    claimedBy: v.string(),
+  },
+  handler: async (ctx, { apiKey, messageId, claimedBy }) => {
+    validateKey(apiKey);
+
+    const message = await ctx.db.get(messageId);
+    if (message?.status !== 'pending') return false;
+
+    const heldByOther = message.claimedBy != null && message.claimedBy !== claimedBy;
+    if (heldByOther && (message.leaseExpiresAt ?? 0) > Date.now()) return false;
+
+    await ctx.db.patch(message._id, { status: 'error', claimedBy: undefined, leaseExpiresAt: undefined });
+
+    return true;
+  },
+});
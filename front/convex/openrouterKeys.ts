@@ -0,0 +1,74 @@
+import { v } from 'convex/values';
+import { mutation, query } from './_generated/server';
+import { validateKey } from './utils';
+
+// api only route
+export const apiGet = query({
+  args: {
+    apiKey: v.string(),
+    userId: v.string(),
+  },
+  handler: async (ctx, { apiKey, userId }) => {
+    validateKey(apiKey);
+
+    const key = await ctx.db
+      .query('openrouterKeys')
+      .withIndex('by_user', (q) => q.eq('userId', userId))
+      .first();
+
+    return key ?? null;
+  },
+});
+
+// api only route
+export const apiSet = mutation({
+  args: {
+    apiKey: v.string(),
+    userId: v.string(),
+    encryptedKey: v.string(),
+  },
+  handler: async (ctx, { apiKey, userId, encryptedKey }) => {
+    validateKey(apiKey);
+
+    const existing = await ctx.db
+      .query('openrouterKeys')
+      .withIndex('by_user', (q) => q.eq('userId', userId))
+      .first();
+
+    const now = Date.now();
+
+    if (existing != null) {
+      await ctx.db.patch(existing._id, { encryptedKey, updatedAt: now });
+      return;
+    }
+
+    await ctx.db.insert('openrouterKeys', {
+      userId,
+      encryptedKey,
+      createdAt: now,
+      updatedAt: now,
+    });
+  },
+});
+
+// api only route
+export const apiRemove = mutation({
+  args: {
+    apiKey: v.string(),
+    userId: v.string(),
+  },
+  handler: async (ctx, { apiKey, userId }) => {
+    validateKey(apiKey);
+
+    const existing = await ctx.db
+      .query('openrouterKeys')
+      .withIndex('by_user', (q) => q.eq('userId', userId))
+      .first();
+
+    if (existing == null) return false;
+
+    await ctx.db.delete(existing._id);
+
+    return true;
+  },
+});
@@ -6,9 +6,18 @@ export const messagePartValidator = v.union(
   v.object({ type: v.literal('attachment'), id: v.id('attachments') }),
 );
 
+export const annotationValidator = v.object({
+  title: v.string(),
+  url: v.string(),
+  content: v.string(),
+});
+
 export const modelParamsValidator = v.object({
   includeSearch: v.boolean(),
   reasoningEffort: v.optional(v.string()),
+  // reasoning token budget, used instead of the effort when set
+  reasoningMaxTokens: v.optional(v.number()),
+  excludeReasoning: v.optional(v.boolean()),
 });
 
 export default defineSchema({
@@ -23,6 +32,9 @@ export default defineSchema({
 
     model: v.optional(v.string()),
     modelParams: v.optional(modelParamsValidator),
+    // model and provider that actually answered, differs from `model` after a fallback
+    servedModel: v.optional(v.string()),
+    provider: v.optional(v.string()),
     providerMetadata: v.optional(
       v.object({
         google: v.object({
@@ -32,29 +44,41 @@ export default defineSchema({
       }),
     ),
     resumableStreamId: v.optional(v.string()),
+    // api instance generating a pending message, so only one of them answers it
+    claimedBy: v.optional(v.string()),
+    claimedAt: v.optional(v.number()),
+    // the claim lapses when the instance stops renewing it, e.g. because it crashed
+    leaseExpiresAt: v.optional(v.number()),
+    // generations started for the message, it is marked as failed once they run out
+    attempts: v.optional(v.number()),
     status: v.optional(
       v.union(v.literal('pending'), v.literal('complete'), v.literal('cancelled'), v.literal('error')),
     ),
 
     reasoning: v.optional(v.string()),
-    annotations: v.optional(
-      v.array(
-        v.object({
-          title: v.string(),
-          url: v.string(),
-          content: v.string(),
-        }),
-      ),
-    ),
+    // provider reasoning blocks as json, sent back verbatim in later turns
+    reasoningDetails: v.optional(v.string()),
+    annotations: v.optional(v.array(annotationValidator)),
 
     promptTokenCount: v.optional(v.number()),
     tokenCount: v.optional(v.number()),
+    // part of tokenCount
+    reasoningTokenCount: v.optional(v.number()),
+    // why the generation stopped, `length` means the answer was cut off
+    finishReason: v.optional(v.string()),
+    // follow-up requests appended after the answer was cut off
+    continuationCount: v.optional(v.number()),
     durationMs: v.optional(v.number()),
     tokensPerSecond: v.optional(v.number()),
     timeToFirstTokenMs: v.optional(v.number()),
+
+    // original timestamp for messages imported from other chat tools
+    createdAt: v.optional(v.number()),
   })
     .index('by_thread', ['threadId'])
-    .index('by_user', ['userId']),
+    .index('by_user', ['userId'])
+    // pending messages without a lease come first, see apiListPending
+    .index('by_status_lease', ['status', 'leaseExpiresAt']),
 
   settings: defineTable({
     codeFont: v.string(),
@@ -86,12 +110,18 @@ export default defineSchema({
     userId: v.string(),
     userSetTitle: v.boolean(),
     visibility: v.string(),
+
+    // source conversation id for threads imported from other chat tools, e.g. `chatgpt:<id>`
+    importId: v.optional(v.string()),
+    // messages stored so far while the thread is still being imported, see imports.ts
+    importedCount: v.optional(v.number()),
   })
     .searchIndex('by_title', {
       searchField: 'title',
       filterFields: ['userId', 'visibility'],
     })
-    .index('by_user', ['userId', 'visibility']),
+    .index('by_user', ['userId', 'visibility'])
+    .index('by_import', ['userId', 'importId']),
 
   models: defineTable({
     id: v.string(),
@@ -117,4 +147,33 @@ export default defineSchema({
     size: v.number(),
     storageId: v.optional(v.id('_storage')),
   }),
+
+  // tokens for the openai compatible api, only the sha256 of the token is stored
+  apiTokens: defineTable({
+    name: v.string(),
+    tokenHash: v.string(),
+    // usage is recorded against this user when set
+    userId: v.optional(v.string()),
+    createdAt: v.number(),
+    lastUsedAt: v.optional(v.number()),
+    revokedAt: v.optional(v.number()),
+  }).index('by_hash', ['tokenHash']),
+
+  usage: defineTable({
+    userId: v.string(),
+    tokenId: v.optional(v.id('apiTokens')),
+    model: v.string(),
+    servedModel: v.optional(v.string()),
+    promptTokens: v.number(),
+    completionTokens: v.number(),
+    createdAt: v.number(),
+  }).index('by_user', ['userId', 'createdAt']),
+
+  // openrouter keys encrypted by the api server, the plaintext never reaches convex
+  openrouterKeys: defineTable({
+    userId: v.string(),
+    encryptedKey: v.string(),
+    createdAt: v.number(),
+    updatedAt: v.number(),
+  }).index('by_user', ['userId']),
 });
@@ -0,0 +1,40 @@
+import { v } from 'convex/values';
+import { mutation, query } from './_generated/server';
+import { validateKey } from './utils';
+
+// api only route
+export const apiRecord = mutation({
+  args: {
+    apiKey: v.string(),
+    userId: v.string(),
+    tokenId: v.optional(v.id('apiTokens')),
+    model: v.string(),
+    servedModel: v.optional(v.string()),
+    promptTokens: v.number(),
+    completionTokens: v.number(),
+  },
+  handler: async (ctx, { apiKey, ...usage }) => {
+    validateKey(apiKey);
+
+    await ctx.db.insert('usage', { ...usage, createdAt: Date.now() });
+  },
+});
+
+// api only route
+export const apiTotalSince = query({
+  args: {
+    apiKey: v.string(),
+    userId: v.string(),
+    since: v.number(),
+  },
+  handler: async (ctx, { apiKey, userId, since }) => {
+    validateKey(apiKey);
+
+    const usage = await ctx.db
+      .query('usage')
+      .withIndex('by_user', (q) => q.eq('userId', userId).gte('createdAt', since))
+      .collect();
+
+    return usage.reduce((total, row) => total + row.promptTokens + row.completionTokens, 0);
+  },
+});
@@ -0,0 +1,11 @@
+import { query } from './_generated/server';
+
+// called by the api with the session token of the user, resolves it to the id stored on their threads
+export const viewer = query({
+  args: {},
+  handler: async (ctx) => {
+    const identity = await ctx.auth.getUserIdentity();
+
+    return identity?.tokenIdentifier ?? null;
+  },
+});
@@ -12,12 +12,24 @@ export function getApiUrl(path: string): string {
   return `${baseUrl}/${path}`;
 }
 
+type ClerkWindow = {
+  Clerk?: { session?: { getToken(options: { template: string }): Promise<string | null> } | null };
+};
+
+// same token the convex client uses, the api resolves it to the signed in user through convex
+async function authHeaders(): Promise<Record<string, string>> {
+  const token = await (window as ClerkWindow).Clerk?.session?.getToken({ template: 'convex' });
+
+  return token ? { Authorization: `Bearer ${token}` } : {};
+}
+
 export async function apiPost<TRes, TReq>(path: string, body: TReq): Promise<TRes> {
   const url = getApiUrl(path);
   const res = await fetch(url, {
     method: 'POST',
     headers: {
       'Content-Type': 'application/json',
+      ...(await authHeaders()),
     },
     body: JSON.stringify(body),
   });
@@ -37,6 +49,7 @@ export async function apiGet<T>(path: string): Promise<T> {
     method: 'GET',
     headers: {
       'Content-Type': 'application/json',
+      ...(await authHeaders()),
     },
   });
 
@@ -49,10 +62,11 @@ export async function apiGet<T>(path: string): Promise<T> {
   return (await res.json()) as T;
 }
 
-export function apiPostSse<TReq>(path: string, body: TReq): SSE {
+export async function apiPostSse<TReq>(path: string, body: TReq): Promise<SSE> {
   const keys = useKeys();
   const headers: SSEHeaders = {
     'Content-Type': 'application/json',
+    ...(await authHeaders()),
   };
 
   let openrouter = keys.openrouter.trim();